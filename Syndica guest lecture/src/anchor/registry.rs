@@ -0,0 +1,232 @@
+//! Program-agnostic decoding of Anchor events.
+//!
+//! An Anchor event is the borsh-serialized event struct prefixed by its 8-byte discriminator. The
+//! discriminator alone does not say which program emitted the event, so an [`EventRegistry`] maps
+//! `(program id, discriminator)` pairs to decoders producing one output type `E`. That type is
+//! usually a per-program enum implementing [`ProgramEvents`], or an enum wrapping several of them.
+use std::{
+    collections::{HashMap, HashSet},
+    fmt, io,
+    sync::Arc,
+};
+
+use anchor_lang::{prelude::Pubkey, Event};
+
+pub type EventDiscriminator = [u8; 8];
+
+type Decoder<E> = Arc<dyn Fn(&[u8]) -> io::Result<E> + Send + Sync>;
+type Fallback<E> = Arc<dyn Fn(&[u8]) -> E + Send + Sync>;
+
+/// The events of one Anchor program, typically an enum with a variant per event.
+pub trait ProgramEvents: Sized + 'static {
+    const PROGRAM_ID: Pubkey;
+
+    /// A registry holding every event of the program.
+    fn registry() -> EventRegistry<Self>;
+}
+
+/// Maps `(program id, discriminator)` to event decoders, see the [module docs](self).
+pub struct EventRegistry<E> {
+    decoders: HashMap<(Pubkey, EventDiscriminator), Decoder<E>>,
+    /// Called for events of a registered program whose discriminator is not registered.
+    fallbacks: HashMap<Pubkey, Fallback<E>>,
+    programs: HashSet<Pubkey>,
+}
+
+impl<E> Default for EventRegistry<E> {
+    fn default() -> Self {
+        Self {
+            decoders: HashMap::new(),
+            fallbacks: HashMap::new(),
+            programs: HashSet::new(),
+        }
+    }
+}
+
+impl<E> fmt::Debug for EventRegistry<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventRegistry")
+            .field("programs", &self.programs)
+            .field("events", &self.decoders.len())
+            .finish()
+    }
+}
+
+impl<E: 'static> EventRegistry<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers event `T` of `program`, turned into `E` by `wrap`, e.g. an enum variant.
+    #[allow(unused)]
+    pub fn event<T: Event>(
+        mut self,
+        program: Pubkey,
+        wrap: impl Fn(T) -> E + Send + Sync + 'static,
+    ) -> Self {
+        let decoder: Decoder<E> =
+            Arc::new(move |mut data: &[u8]| T::deserialize(&mut data).map(&wrap));
+        self.decoders.insert((program, T::DISCRIMINATOR), decoder);
+        self.programs.insert(program);
+        self
+    }
+
+    /// Decodes events of `program` with an unregistered discriminator through `fallback`, which
+    /// receives the event data including the discriminator. Without a fallback they are skipped.
+    #[allow(unused)]
+    pub fn unknown(
+        mut self,
+        program: Pubkey,
+        fallback: impl Fn(&[u8]) -> E + Send + Sync + 'static,
+    ) -> Self {
+        self.fallbacks.insert(program, Arc::new(fallback));
+        self.programs.insert(program);
+        self
+    }
+
+    /// Registers every event of `P`, wrapped into `E`.
+    #[allow(unused)]
+    pub fn program<P: ProgramEvents>(self, wrap: impl Fn(P) -> E + Send + Sync + 'static) -> Self {
+        self.merge(P::registry().map(wrap))
+    }
+
+    /// Adds every event registered in `other`, replacing decoders registered in both.
+    #[allow(unused)]
+    pub fn merge(mut self, other: EventRegistry<E>) -> Self {
+        self.decoders.extend(other.decoders);
+        self.fallbacks.extend(other.fallbacks);
+        self.programs.extend(other.programs);
+        self
+    }
+
+    /// Turns every decoded event into an `F`.
+    pub fn map<F: 'static>(
+        self,
+        wrap: impl Fn(E) -> F + Send + Sync + 'static,
+    ) -> EventRegistry<F> {
+        let wrap = Arc::new(wrap);
+        let decoders = self
+            .decoders
+            .into_iter()
+            .map(|(key, decode)| {
+                let wrap = wrap.clone();
+                let decoder: Decoder<F> = Arc::new(move |data: &[u8]| decode(data).map(&*wrap));
+                (key, decoder)
+            })
+            .collect();
+        let fallbacks = self
+            .fallbacks
+            .into_iter()
+            .map(|(program, fallback)| {
+                let wrap = wrap.clone();
+                let fallback: Fallback<F> = Arc::new(move |data: &[u8]| wrap(fallback(data)));
+                (program, fallback)
+            })
+            .collect();
+        EventRegistry {
+            decoders,
+            fallbacks,
+            programs: self.programs,
+        }
+    }
+
+    pub fn contains_program(&self, program: &Pubkey) -> bool {
+        self.programs.contains(program)
+    }
+
+    /// Decodes event `data` emitted by `program`, discriminator included.
+    ///
+    /// Returns `None` if the data is too short to hold a discriminator, or if neither a decoder
+    /// nor a fallback is registered for it.
+    pub fn decode(&self, program: &Pubkey, data: &[u8]) -> Option<io::Result<E>> {
+        let discriminator: EventDiscriminator = data.get(..8)?.try_into().ok()?;
+        if let Some(decode) = self.decoders.get(&(*program, discriminator)) {
+            return Some(decode(&data[8..]));
+        }
+        let fallback = self.fallbacks.get(program)?;
+        Some(Ok(fallback(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::prelude::*;
+
+    use super::*;
+    use crate::raydium::anchor_events::{RaydiumCLMMEvent, SwapEvent};
+
+    #[event]
+    #[derive(Debug, PartialEq)]
+    pub struct CounterEvent {
+        pub value: u64,
+    }
+
+    #[derive(Debug)]
+    enum AnyEvent {
+        Raydium(RaydiumCLMMEvent),
+        Counter(CounterEvent),
+    }
+
+    fn swap() -> SwapEvent {
+        SwapEvent {
+            pool_state: Pubkey::new_unique(),
+            sender: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            amount_0: 1,
+            transfer_fee_0: 0,
+            amount_1: 2,
+            transfer_fee_1: 0,
+            zero_for_one: true,
+            sqrt_price_x64: 1 << 64,
+            liquidity: 10,
+            tick: 0,
+        }
+    }
+
+    #[test]
+    fn test_decodes_by_program_and_discriminator() {
+        let counter = Pubkey::new_unique();
+        let registry = EventRegistry::new()
+            .program(AnyEvent::Raydium)
+            .event(counter, AnyEvent::Counter);
+
+        let event = registry
+            .decode(&RaydiumCLMMEvent::PROGRAM_ID, &swap().data())
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            event,
+            AnyEvent::Raydium(RaydiumCLMMEvent::Swap(SwapEvent { amount_1: 2, .. }))
+        ));
+
+        let data = CounterEvent { value: 7 }.data();
+        let event = registry.decode(&counter, &data).unwrap().unwrap();
+        assert!(matches!(
+            event,
+            AnyEvent::Counter(CounterEvent { value: 7 })
+        ));
+
+        // The same event emitted by a program that is not registered is ignored.
+        assert!(registry.decode(&Pubkey::new_unique(), &data).is_none());
+        assert!(registry.decode(&counter, &swap().data()).is_none());
+        assert!(registry.decode(&counter, &data[..7]).is_none());
+        assert!(registry.decode(&counter, &data[..12]).unwrap().is_err());
+    }
+
+    #[test]
+    fn test_unknown_discriminator_fallback() {
+        assert_eq!(
+            RaydiumCLMMEvent::PROGRAM_ID.to_string(),
+            "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK"
+        );
+        let registry = RaydiumCLMMEvent::registry();
+        let data = CounterEvent { value: 7 }.data();
+
+        let event = registry
+            .decode(&RaydiumCLMMEvent::PROGRAM_ID, &data)
+            .unwrap()
+            .unwrap();
+        assert!(matches!(event, RaydiumCLMMEvent::Unknown(_)));
+    }
+}
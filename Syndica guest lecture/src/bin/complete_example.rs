@@ -2,13 +2,14 @@
 
 use chainstream_raydium_trade_pair::{
     chainstream::{
-        client::ChainStreamClient,
+        client::{ChainStreamClient, ClientBuilder},
         methods::{CommitmentLevel, Method},
+        reconnect::StreamEvent,
     },
     raydium::{anchor_events::RaydiumCLMMEvent, parse::parse_raydium_anchor_events},
 };
 
-const RAYDIUM_CLMM_PROGRAM: &'static str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+const RAYDIUM_CLMM_PROGRAM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -19,14 +20,31 @@ async fn main() -> anyhow::Result<()> {
         .one_of_account_keys(&[RAYDIUM_CLMM_PROGRAM])
         .commitment_level(CommitmentLevel::Confirmed);
 
-    let client = ChainStreamClient::new(&token).await?;
+    // Honors CHAINSTREAM_ENDPOINT / CHAINSTREAM_CONFIG, e.g. to point at a local proxy.
+    let client = ClientBuilder::from_env()?.token(&token).build().await?;
 
-    let mut subscription = client.subscribe(method).await?;
+    let mut subscription = client.subscribe_with_reconnect(method).await?;
 
-    while let Some(Ok(transaction)) = subscription.next().await {
-        let meta = transaction.meta();
-        if let Ok(anchor_events) = parse_raydium_anchor_events(meta) {
-            if let Some(RaydiumCLMMEvent::Swap(swap_event)) = anchor_events.get(0) {
+    while let Some(event) = subscription.next().await {
+        let transaction = match event? {
+            StreamEvent::Notification(transaction) => transaction,
+            StreamEvent::Reconnected { downtime, .. } => {
+                eprintln!("reconnected after {downtime:?}, swaps may have been missed");
+                continue;
+            }
+            StreamEvent::Lagged { missed: Some(missed) } => {
+                eprintln!("fell behind, {missed} transactions were dropped");
+                continue;
+            }
+            StreamEvent::Lagged { missed: None } => {
+                eprintln!("fell behind, transactions may have been dropped");
+                continue;
+            }
+        };
+        if let Ok(anchor_events) = parse_raydium_anchor_events(&transaction) {
+            if let Some(RaydiumCLMMEvent::Swap(swap_event)) =
+                anchor_events.first().map(|parsed| &parsed.event)
+            {
                 if swap_event.zero_for_one {
                     println!(
                         "{} --> {}",
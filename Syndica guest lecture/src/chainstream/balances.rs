@@ -0,0 +1,328 @@
+//! Balance changes of a transaction, computed from the pre and post balances in its meta.
+//!
+//! Token accounts that are created by the transaction only show up in `post_token_balances`, and
+//! accounts closed by it only in `pre_token_balances`; the missing side counts as zero.
+use std::collections::BTreeMap;
+
+use super::types::transaction::{Meta, TokenBalance};
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum BalanceError {
+    #[error("Token balance of account {0} has no amount")]
+    MissingAmount(u32),
+    #[error("Invalid token amount: {0}")]
+    InvalidAmount(String),
+    #[error("Account {account_index} changed mint from {pre} to {post}")]
+    MintMismatch {
+        account_index: u32,
+        pre: String,
+        post: String,
+    },
+    #[error("Account {account_index} changed decimals from {pre} to {post}")]
+    DecimalsMismatch {
+        account_index: u32,
+        pre: u32,
+        post: u32,
+    },
+    #[error("{pre} pre balances but {post} post balances")]
+    LengthMismatch { pre: usize, post: usize },
+}
+
+/// Change of the token balance of one account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBalanceDelta {
+    pub account_index: u32,
+    /// Owner before the transaction. Differs from `post_owner` if the transaction changed the
+    /// owner, e.g. through `SetAuthority`.
+    pub pre_owner: String,
+    pub post_owner: String,
+    pub mint: String,
+    pub decimals: u32,
+    /// Raw amounts, in the smallest unit of the mint.
+    pub pre: u64,
+    pub post: u64,
+}
+
+impl TokenBalanceDelta {
+    /// `post - pre`, in the smallest unit of the mint.
+    pub fn delta(&self) -> i128 {
+        self.post as i128 - self.pre as i128
+    }
+
+    pub fn ui_delta(&self) -> f64 {
+        ui_amount(self.delta(), self.decimals)
+    }
+}
+
+/// Net change of the balance an owner holds of a mint, summed over all their token accounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerBalanceDelta {
+    pub owner: String,
+    pub mint: String,
+    pub decimals: u32,
+    /// In the smallest unit of the mint.
+    pub delta: i128,
+}
+
+impl OwnerBalanceDelta {
+    pub fn ui_delta(&self) -> f64 {
+        ui_amount(self.delta, self.decimals)
+    }
+}
+
+fn ui_amount(amount: i128, decimals: u32) -> f64 {
+    amount as f64 / 10f64.powi(decimals as i32)
+}
+
+/// Raw amount and decimals of a token balance.
+fn amount(balance: &TokenBalance) -> Result<(u64, u32), BalanceError> {
+    let amount = balance
+        .ui_token_amount
+        .as_ref()
+        .ok_or(BalanceError::MissingAmount(balance.account_index))?;
+    let raw = amount
+        .amount
+        .parse()
+        .map_err(|_| BalanceError::InvalidAmount(amount.amount.clone()))?;
+    Ok((raw, amount.decimals))
+}
+
+impl Meta {
+    /// Token balance changes per account index, in account index order. Accounts whose balance
+    /// did not change are included with a zero delta.
+    pub fn token_balance_deltas(&self) -> Result<Vec<TokenBalanceDelta>, BalanceError> {
+        let mut deltas: BTreeMap<u32, TokenBalanceDelta> = BTreeMap::new();
+        for balance in &self.pre_token_balances {
+            let (pre, decimals) = amount(balance)?;
+            deltas.insert(
+                balance.account_index,
+                TokenBalanceDelta {
+                    account_index: balance.account_index,
+                    pre_owner: balance.owner.clone(),
+                    post_owner: balance.owner.clone(),
+                    mint: balance.mint.clone(),
+                    decimals,
+                    pre,
+                    post: 0,
+                },
+            );
+        }
+        for balance in &self.post_token_balances {
+            let (post, decimals) = amount(balance)?;
+            let delta = deltas
+                .entry(balance.account_index)
+                .or_insert_with(|| TokenBalanceDelta {
+                    account_index: balance.account_index,
+                    pre_owner: balance.owner.clone(),
+                    post_owner: balance.owner.clone(),
+                    mint: balance.mint.clone(),
+                    decimals,
+                    pre: 0,
+                    post: 0,
+                });
+            if delta.mint != balance.mint {
+                return Err(BalanceError::MintMismatch {
+                    account_index: balance.account_index,
+                    pre: delta.mint.clone(),
+                    post: balance.mint.clone(),
+                });
+            }
+            if delta.decimals != decimals {
+                return Err(BalanceError::DecimalsMismatch {
+                    account_index: balance.account_index,
+                    pre: delta.decimals,
+                    post: decimals,
+                });
+            }
+            delta.post_owner = balance.owner.clone();
+            delta.post = post;
+        }
+        Ok(deltas.into_values().collect())
+    }
+
+    /// Token balance changes per `(owner, mint)`, sorted by owner and mint. Owners whose net
+    /// balance of a mint did not change are left out. An account that changed owner counts as
+    /// its pre balance leaving the old owner and its post balance arriving at the new one.
+    pub fn owner_balance_deltas(&self) -> Result<Vec<OwnerBalanceDelta>, BalanceError> {
+        let mut deltas: BTreeMap<(String, String), OwnerBalanceDelta> = BTreeMap::new();
+        for account in self.token_balance_deltas()? {
+            let changes = [
+                (&account.pre_owner, -(account.pre as i128)),
+                (&account.post_owner, account.post as i128),
+            ];
+            for (owner, change) in changes {
+                deltas
+                    .entry((owner.clone(), account.mint.clone()))
+                    .or_insert_with(|| OwnerBalanceDelta {
+                        owner: owner.clone(),
+                        mint: account.mint.clone(),
+                        decimals: account.decimals,
+                        delta: 0,
+                    })
+                    .delta += change;
+            }
+        }
+        Ok(deltas.into_values().filter(|d| d.delta != 0).collect())
+    }
+
+    /// Lamport balance changes per account index, fee included.
+    pub fn lamport_deltas(&self) -> Result<Vec<i128>, BalanceError> {
+        if self.pre_balances.len() != self.post_balances.len() {
+            return Err(BalanceError::LengthMismatch {
+                pre: self.pre_balances.len(),
+                post: self.post_balances.len(),
+            });
+        }
+        Ok(self
+            .pre_balances
+            .iter()
+            .zip(&self.post_balances)
+            .map(|(pre, post)| *post as i128 - *pre as i128)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chainstream::{mock::fixtures, types::transaction::TokenAmount};
+
+    const USDC: &str = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v";
+    const SOL: &str = "So11111111111111111111111111111111111111112";
+
+    fn balance(account_index: u32, owner: &str, mint: &str, amount: u64) -> TokenBalance {
+        let decimals = if mint == USDC { 6 } else { 9 };
+        TokenBalance {
+            account_index,
+            mint: mint.to_string(),
+            owner: owner.to_string(),
+            program_id: "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA".to_string(),
+            ui_token_amount: Some(TokenAmount {
+                amount: amount.to_string(),
+                decimals,
+                ui_amount: Some(amount as f64 / 10f64.powi(decimals as i32)),
+                ui_amount_string: String::new(),
+            }),
+        }
+    }
+
+    /// A wallet swapping 2 SOL for 300 USDC against a pool, paying into a freshly created USDC
+    /// account.
+    fn swap_meta() -> Meta {
+        let mut meta = fixtures::transaction(1, fixtures::signature(1), &["A"], vec![])
+            .meta()
+            .unwrap();
+        meta.pre_token_balances = vec![
+            balance(1, "wallet", SOL, 5_000_000_000),
+            balance(3, "pool", SOL, 100_000_000_000),
+            balance(4, "pool", USDC, 50_000_000_000),
+        ];
+        meta.post_token_balances = vec![
+            balance(1, "wallet", SOL, 3_000_000_000),
+            balance(2, "wallet", USDC, 300_000_000),
+            balance(3, "pool", SOL, 102_000_000_000),
+            balance(4, "pool", USDC, 49_700_000_000),
+        ];
+        meta
+    }
+
+    #[test]
+    fn test_token_balance_deltas() {
+        let meta = swap_meta();
+
+        let per_account: Vec<_> = meta
+            .token_balance_deltas()
+            .unwrap()
+            .iter()
+            .map(|d| (d.account_index, d.delta()))
+            .collect();
+        assert_eq!(
+            per_account,
+            [
+                (1, -2_000_000_000),
+                (2, 300_000_000),
+                (3, 2_000_000_000),
+                (4, -300_000_000)
+            ]
+        );
+
+        let per_owner = meta.owner_balance_deltas().unwrap();
+        let wallet: Vec<_> = per_owner
+            .iter()
+            .filter(|d| d.owner == "wallet")
+            .map(|d| (d.mint.as_str(), d.ui_delta()))
+            .collect();
+        assert_eq!(wallet, [(USDC, 300.0), (SOL, -2.0)]);
+        assert_eq!(per_owner.len(), 4);
+    }
+
+    #[test]
+    fn test_owner_change() {
+        let mut meta = swap_meta();
+        // The wallet hands its SOL account, after the swap, over to a vault.
+        meta.post_token_balances[0] = balance(1, "vault", SOL, 3_000_000_000);
+
+        let account = &meta.token_balance_deltas().unwrap()[0];
+        assert_eq!(
+            (account.pre_owner.as_str(), account.post_owner.as_str()),
+            ("wallet", "vault")
+        );
+        assert_eq!(account.delta(), -2_000_000_000);
+
+        let per_owner: Vec<_> = meta
+            .owner_balance_deltas()
+            .unwrap()
+            .into_iter()
+            .filter(|d| d.mint == SOL)
+            .map(|d| (d.owner, d.delta))
+            .collect();
+        assert_eq!(
+            per_owner,
+            [
+                ("pool".to_string(), 2_000_000_000),
+                ("vault".to_string(), 3_000_000_000),
+                ("wallet".to_string(), -5_000_000_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_balance_errors() {
+        let mut meta = swap_meta();
+        meta.pre_balances = vec![10, 20];
+        meta.post_balances = vec![5, 25];
+        assert_eq!(meta.lamport_deltas().unwrap(), [-5, 5]);
+
+        meta.post_balances.pop();
+        assert_eq!(
+            meta.lamport_deltas(),
+            Err(BalanceError::LengthMismatch { pre: 2, post: 1 })
+        );
+
+        meta.post_token_balances[0].ui_token_amount = None;
+        assert_eq!(
+            meta.token_balance_deltas(),
+            Err(BalanceError::MissingAmount(1))
+        );
+
+        meta.post_token_balances[0] = balance(1, "wallet", USDC, 1);
+        assert_eq!(
+            meta.token_balance_deltas(),
+            Err(BalanceError::MintMismatch {
+                account_index: 1,
+                pre: SOL.to_string(),
+                post: USDC.to_string()
+            })
+        );
+
+        meta.post_token_balances[0].mint = SOL.to_string();
+        assert!(matches!(
+            meta.token_balance_deltas(),
+            Err(BalanceError::DecimalsMismatch {
+                account_index: 1,
+                pre: 9,
+                post: 6
+            })
+        ));
+    }
+}
@@ -9,26 +9,48 @@ use jsonrpsee::{
     ws_client::{PingConfig, WsClient, WsClientBuilder},
 };
 
-use super::methods::SubscriptionMethod;
+use super::{
+    config::{ClientConfig, ConfigError, Endpoint},
+    methods::{SubscriptionMethod, TransactionMethod},
+    ordered::{OrderedSubscription, OrderingConfig},
+    reconnect::{subscribe_on, Connection, ReconnectConfig, ReconnectingSubscription},
+    recorder::{Recorded, Recorder, RecordingSubscription},
+    subscription::DEFAULT_BUFFER_CAPACITY,
+};
 
-pub type ChainStreamSubscription<T> = Subscription<T>;
+pub use super::subscription::{ChainStreamSubscription, OverflowPolicy};
 
-const CHAINSTREAM_API_URL: &'static str = "wss://chainstream.api.syndica.io";
+/// The underlying jsonrpsee subscription, without buffering or overflow handling.
+pub(crate) type RawSubscription<T> = Subscription<T>;
 
 pub struct ClientBuilder {
     token: String,
+    endpoint: Endpoint,
     ws_client_builder: WsClientBuilder,
+    reconnect: ReconnectConfig,
+    buffer_capacity: usize,
+    overflow_policy: OverflowPolicy,
 }
 
 #[allow(unused)]
 pub type ClientError = jsonrpsee::core::ClientError;
 
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ClientBuilder {
     #[allow(unused)]
     pub fn new() -> Self {
         Self {
             ws_client_builder: WsClientBuilder::default(),
             token: Default::default(),
+            endpoint: Endpoint::default(),
+            reconnect: ReconnectConfig::default(),
+            buffer_capacity: DEFAULT_BUFFER_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
         }
     }
 
@@ -40,6 +62,80 @@ impl ClientBuilder {
         Self {
             token: token.to_string(),
             ws_client_builder: self.ws_client_builder.set_headers(headers),
+            ..self
+        }
+    }
+
+    /// Set the endpoint the client connects to (default is the public ChainStream API).
+    #[allow(unused)]
+    pub fn endpoint(self, endpoint: Endpoint) -> Self {
+        Self { endpoint, ..self }
+    }
+
+    /// Apply every setting that is present in `config`, leaving the others untouched.
+    #[allow(unused)]
+    pub fn config(self, config: ClientConfig) -> Self {
+        let mut builder = self;
+        if let Some(token) = &config.token {
+            builder = builder.token(token);
+        }
+        if let Some(endpoint) = config.endpoint.clone() {
+            builder = builder.endpoint(endpoint);
+        }
+        if let Some(timeout) = config.request_timeout() {
+            builder = builder.request_timeout(timeout);
+        }
+        if let Some(timeout) = config.connection_timeout() {
+            builder = builder.connection_timeout(timeout);
+        }
+        match config.enable_ws_ping {
+            Some(true) => builder = builder.enable_ws_ping(),
+            Some(false) => builder = builder.disable_ws_ping(),
+            None => {}
+        }
+        if let Some(max) = config.max_buffer_capacity_per_subscription {
+            builder = builder.max_buffer_capacity_per_subscription(max);
+        }
+        if let Some(capacity) = config.buffer_capacity {
+            builder = builder.buffer_capacity(capacity);
+        }
+        if let Some(policy) = config.overflow_policy {
+            builder = builder.overflow_policy(policy);
+        }
+        builder
+    }
+
+    /// Create a builder from the config file and environment variables, see
+    /// [`ClientConfig::load`].
+    #[allow(unused)]
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Ok(Self::new().config(ClientConfig::load()?))
+    }
+
+    /// Backoff policy used by [`ChainStreamClient::subscribe_with_reconnect`] when the
+    /// connection drops (see [`ReconnectConfig::default`]).
+    #[allow(unused)]
+    pub fn reconnect(self, reconnect: ReconnectConfig) -> Self {
+        Self { reconnect, ..self }
+    }
+
+    /// Number of notifications each [`ChainStreamSubscription`] buffers for a slow consumer
+    /// (default is 1024).
+    #[allow(unused)]
+    pub fn buffer_capacity(self, buffer_capacity: usize) -> Self {
+        Self {
+            buffer_capacity,
+            ..self
+        }
+    }
+
+    /// What a [`ChainStreamSubscription`] does once its buffer is full (default is
+    /// [`OverflowPolicy::Disconnect`]).
+    #[allow(unused)]
+    pub fn overflow_policy(self, overflow_policy: OverflowPolicy) -> Self {
+        Self {
+            overflow_policy,
+            ..self
         }
     }
 
@@ -140,46 +236,67 @@ impl ClientBuilder {
 
     #[allow(unused)]
     pub async fn build(self) -> Result<ChainStreamClient, ClientError> {
+        let connection =
+            Connection::connect(self.endpoint.url(), self.ws_client_builder, self.reconnect)
+                .await?;
+
         Ok(ChainStreamClient {
-            inner: Arc::new(self.ws_client_builder.build(CHAINSTREAM_API_URL).await?),
+            inner: Arc::new(connection),
             token: self.token,
+            buffer_capacity: self.buffer_capacity,
+            overflow_policy: self.overflow_policy,
         })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct ChainStreamClient {
-    inner: Arc<WsClient>,
+    inner: Arc<Connection>,
 
     #[allow(dead_code)]
     token: String,
+    buffer_capacity: usize,
+    overflow_policy: OverflowPolicy,
 }
 
 impl ChainStreamClient {
-    /// Creates a new ChainStreamClient instance from a given URL and headers.
-    ///
-    /// The `url` parameter should be a valid URL to the ChainStream API. This is expected to be
-    /// wss://chainstream.api.syndica.io
+    /// Creates a new ChainStreamClient instance connected to the default endpoint,
+    /// wss://chainstream.api.syndica.io. Use [`ClientBuilder::endpoint`] to connect elsewhere.
     pub async fn new(token: impl AsRef<str>) -> Result<Self> {
-        let mut map = HeaderMap::new();
-        map.insert("X-Syndica-Api-Token", token.as_ref().parse().unwrap());
-
-        Ok(Self {
-            inner: Arc::new(
-                WsClientBuilder::new()
-                    .set_headers(map)
-                    .build(CHAINSTREAM_API_URL)
-                    .await?,
-            ),
-            token: token.as_ref().to_string(),
-        })
+        Ok(ClientBuilder::new().token(token.as_ref()).build().await?)
     }
 
     pub async fn subscribe<M>(&self, method: M) -> Result<ChainStreamSubscription<M::Output>>
+    where
+        M: SubscriptionMethod,
+        M::Output: Send + 'static,
+    {
+        let subscription = self.subscribe_raw(method).await?;
+
+        Ok(ChainStreamSubscription::new(
+            subscription,
+            self.buffer_capacity,
+            self.overflow_policy,
+        ))
+    }
+
+    /// Subscribes to transactions and yields them deduplicated and in slot order, see
+    /// [`OrderedSubscription`].
+    pub async fn subscribe_ordered(
+        &self,
+        method: TransactionMethod,
+        config: OrderingConfig,
+    ) -> Result<OrderedSubscription> {
+        let subscription = self.subscribe(method).await?;
+
+        Ok(OrderedSubscription::new(subscription, config))
+    }
+
+    pub(crate) async fn subscribe_raw<M>(&self, method: M) -> Result<RawSubscription<M::Output>>
     where
         M: SubscriptionMethod,
     {
-        let inner = self.inner.clone();
+        let inner = self.inner.current().await;
 
         let subscription = inner
             .subscribe(
@@ -191,4 +308,41 @@ impl ChainStreamClient {
 
         Ok(subscription)
     }
+
+    /// Like [`ChainStreamClient::subscribe`], but the returned subscription reconnects the client
+    /// and re-issues `method` whenever the websocket drops, using the backoff policy configured
+    /// with [`ClientBuilder::reconnect`].
+    pub async fn subscribe_with_reconnect<M>(
+        &self,
+        method: M,
+    ) -> Result<ReconnectingSubscription<M>>
+    where
+        M: SubscriptionMethod,
+        M::Output: Send + 'static,
+    {
+        let inner = self.inner.current().await;
+        let subscription = subscribe_on(&inner, &method).await?;
+
+        Ok(ReconnectingSubscription::new(
+            method,
+            self.inner.clone(),
+            ChainStreamSubscription::new(subscription, self.buffer_capacity, self.overflow_policy),
+        ))
+    }
+
+    /// Like [`ChainStreamClient::subscribe_with_reconnect`], but every notification is written to
+    /// `recorder` exactly as received before it is decoded. See
+    /// [`Replay`](super::recorder::Replay) to read the session back.
+    pub async fn subscribe_recorded<M>(
+        &self,
+        method: M,
+        recorder: Recorder,
+    ) -> Result<RecordingSubscription<M>>
+    where
+        M: SubscriptionMethod,
+    {
+        let subscription = self.subscribe_with_reconnect(Recorded(method)).await?;
+
+        Ok(RecordingSubscription::new(subscription, recorder))
+    }
 }
@@ -0,0 +1,538 @@
+//! Follows transactions from processed to finalized.
+//!
+//! [`CommitmentTracker`] combines a processed-level transaction subscription with a slot
+//! subscription. Every transaction is reported as [`CommitmentEvent::Processed`] as soon as it
+//! arrives, then promoted to `Confirmed` and `Finalized` as its slot is, or reported as
+//! `RolledBack` once its slot is dead or provably on an abandoned fork. Transactions whose slot
+//! can't be placed before it falls out of the retained window end as `Unresolved`.
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+use anyhow::Result;
+
+use super::{
+    client::{ChainStreamClient, ChainStreamSubscription},
+    methods::{CommitmentLevel, Method, TransactionMethod},
+    subscription::SubscriptionError,
+    types::{slot::SlotUpdate, transaction::TransactionWrite, SlotStatus},
+};
+
+#[derive(Debug, Clone)]
+pub enum CommitmentEvent {
+    Processed(Box<TransactionWrite>),
+    Confirmed {
+        signature: String,
+        slot: u64,
+    },
+    Finalized {
+        signature: String,
+        slot: u64,
+    },
+    /// The transaction's slot is dead, or on a fork that did not get finalized.
+    RolledBack {
+        signature: String,
+        slot: u64,
+    },
+    /// The transaction's slot is more than [`RETAINED_SLOTS`] behind the latest finalized slot and
+    /// could not be placed on or off the canonical chain, e.g. because slot updates were missed.
+    /// It is no longer tracked.
+    Unresolved {
+        signature: String,
+        slot: u64,
+    },
+}
+
+/// How far behind the latest finalized slot the canonical chain is remembered, to resolve
+/// transactions and parent links that arrive late.
+pub const RETAINED_SLOTS: u64 = 1024;
+
+/// The promotion logic of [`CommitmentTracker`], fed one notification at a time.
+///
+/// A transaction is only rolled back once its slot is dead, or once the chain of parents of a
+/// finalized slot is known all the way down past it and does not include it. Transactions whose
+/// slot can't be placed, e.g. because a slot update was missed, stay pending until a later update
+/// settles it or the slot falls behind the retained window.
+#[derive(Debug, Default)]
+struct Tracker {
+    slots: BTreeMap<u64, SlotStatus>,
+    parents: HashMap<u64, u64>,
+    /// Signatures waiting for their slot to be finalized, and whether they were confirmed.
+    pending: BTreeMap<u64, HashMap<String, bool>>,
+    last_finalized: Option<u64>,
+    /// Finalized slots, including ancestors of finalized slots.
+    canonical: BTreeSet<u64>,
+    /// Ranges `start..=end` over which every canonical slot is in `canonical`, so any other slot
+    /// in them was on another fork. Keyed by `start`.
+    settled: BTreeMap<u64, u64>,
+    /// Signatures already finalized, rolled back or given up on, so redeliveries are ignored.
+    resolved: BTreeMap<u64, HashSet<String>>,
+    events: VecDeque<CommitmentEvent>,
+}
+
+impl Tracker {
+    fn on_transaction(&mut self, transaction: TransactionWrite) {
+        let slot = transaction.value.slot;
+        let signature = transaction.context.signature.clone();
+        let pending = self
+            .pending
+            .get(&slot)
+            .is_some_and(|s| s.contains_key(&signature));
+        let resolved = self
+            .resolved
+            .get(&slot)
+            .is_some_and(|s| s.contains(&signature));
+        if pending || resolved {
+            return;
+        }
+        self.events
+            .push_back(CommitmentEvent::Processed(Box::new(transaction)));
+
+        if self.canonical.contains(&slot) {
+            self.resolve(CommitmentEvent::Finalized { signature, slot });
+            return;
+        }
+        if self.slots.get(&slot) == Some(&SlotStatus::Dead) || self.is_abandoned(slot) {
+            self.resolve(CommitmentEvent::RolledBack { signature, slot });
+            return;
+        }
+        if self.horizon().is_some_and(|horizon| slot < horizon) {
+            self.resolve(CommitmentEvent::Unresolved { signature, slot });
+            return;
+        }
+
+        let confirmed = self.slots.get(&slot) == Some(&SlotStatus::Confirmed);
+        if confirmed {
+            self.events.push_back(CommitmentEvent::Confirmed {
+                signature: signature.clone(),
+                slot,
+            });
+        }
+        self.pending
+            .entry(slot)
+            .or_default()
+            .insert(signature, confirmed);
+    }
+
+    fn on_slot(&mut self, update: SlotUpdate) {
+        let Some(value) = update.value else {
+            return;
+        };
+        let state = value.status;
+        if let Some(parent) = value.parent {
+            let learned = self.parents.insert(value.slot, parent).is_none();
+            // A late link can place slots that a finalized descendant couldn't reach before.
+            if learned && self.canonical.contains(&value.slot) {
+                self.finalize(value.slot);
+            }
+        }
+        if state == SlotStatus::Unknown {
+            return;
+        }
+        let previous = self.slots.get(&value.slot).copied();
+        if previous.is_some_and(|previous| previous >= state) {
+            return;
+        }
+        self.slots.insert(value.slot, state);
+
+        match state {
+            SlotStatus::Processed | SlotStatus::Unknown => {}
+            SlotStatus::Confirmed => self.confirm(value.slot),
+            SlotStatus::Finalized => self.finalize(value.slot),
+            SlotStatus::Dead => self.roll_back(value.slot),
+        }
+    }
+
+    /// Oldest slot still tracked.
+    fn horizon(&self) -> Option<u64> {
+        self.last_finalized
+            .map(|finalized| finalized.saturating_sub(RETAINED_SLOTS))
+    }
+
+    /// Reports the final state of a transaction and remembers it, so a redelivery is ignored.
+    fn resolve(&mut self, event: CommitmentEvent) {
+        let (signature, slot) = match &event {
+            CommitmentEvent::Finalized { signature, slot }
+            | CommitmentEvent::RolledBack { signature, slot }
+            | CommitmentEvent::Unresolved { signature, slot } => (signature, *slot),
+            CommitmentEvent::Processed(_) | CommitmentEvent::Confirmed { .. } => {
+                unreachable!("not a final state")
+            }
+        };
+        self.resolved
+            .entry(slot)
+            .or_default()
+            .insert(signature.clone());
+        self.events.push_back(event);
+    }
+
+    /// Whether `slot` is known not to be on the canonical chain.
+    fn is_abandoned(&self, slot: u64) -> bool {
+        !self.canonical.contains(&slot) && self.settled.range(..=slot).any(|(_, &end)| slot <= end)
+    }
+
+    fn confirm(&mut self, slot: u64) {
+        let Some(signatures) = self.pending.get_mut(&slot) else {
+            return;
+        };
+        for (signature, confirmed) in signatures.iter_mut().filter(|(_, c)| !**c) {
+            *confirmed = true;
+            self.events.push_back(CommitmentEvent::Confirmed {
+                signature: signature.clone(),
+                slot,
+            });
+        }
+    }
+
+    fn finalize(&mut self, slot: u64) {
+        // Ancestors of a finalized slot are finalized too, even if we missed their update. Walk
+        // them down to a slot already known to be finalized, or to the first missing link.
+        let mut current = slot;
+        self.canonical.insert(slot);
+        let start = loop {
+            match self.parents.get(&current) {
+                Some(&parent) if self.canonical.contains(&parent) => break parent + 1,
+                Some(&parent) if parent < current => {
+                    self.canonical.insert(parent);
+                    current = parent;
+                }
+                _ => break current,
+            }
+        };
+        self.settled.insert(start, slot);
+
+        // Anything pending in the settled range either is one of the ancestors or was on another
+        // fork. Older slots can't be placed yet.
+        let settled: Vec<u64> = self.pending.range(start..=slot).map(|(s, _)| *s).collect();
+        for pending_slot in settled {
+            let signatures = self.pending.remove(&pending_slot).unwrap_or_default();
+            let finalized = self.canonical.contains(&pending_slot);
+            for (signature, _) in signatures {
+                let slot = pending_slot;
+                self.resolve(if finalized {
+                    CommitmentEvent::Finalized { signature, slot }
+                } else {
+                    CommitmentEvent::RolledBack { signature, slot }
+                });
+            }
+        }
+        // Pending slots below the range that turned out to be ancestors.
+        let ancestors: Vec<u64> = self
+            .pending
+            .range(..start)
+            .map(|(s, _)| *s)
+            .filter(|s| self.canonical.contains(s))
+            .collect();
+        for slot in ancestors {
+            for (signature, _) in self.pending.remove(&slot).unwrap_or_default() {
+                self.resolve(CommitmentEvent::Finalized { signature, slot });
+            }
+        }
+
+        if self.last_finalized.is_none_or(|finalized| slot > finalized) {
+            self.last_finalized = Some(slot);
+            let horizon = slot.saturating_sub(RETAINED_SLOTS);
+            self.slots = self.slots.split_off(&horizon);
+            self.parents.retain(|s, _| *s >= horizon);
+            self.canonical = self.canonical.split_off(&horizon);
+            self.settled.retain(|_, end| *end >= horizon);
+            self.resolved = self.resolved.split_off(&horizon);
+
+            // Whatever is still pending below the horizon can't be placed anymore.
+            let expired = self.pending.split_off(&horizon);
+            let expired = std::mem::replace(&mut self.pending, expired);
+            for (slot, signatures) in expired {
+                for (signature, _) in signatures {
+                    self.resolve(CommitmentEvent::Unresolved { signature, slot });
+                }
+            }
+        }
+    }
+
+    fn roll_back(&mut self, slot: u64) {
+        for (signature, _) in self.pending.remove(&slot).unwrap_or_default() {
+            self.resolve(CommitmentEvent::RolledBack { signature, slot });
+        }
+    }
+}
+
+/// Emits [`CommitmentEvent`]s for every transaction matching a filter, see the
+/// [module docs](self).
+#[derive(Debug)]
+pub struct CommitmentTracker {
+    transactions: ChainStreamSubscription<TransactionWrite>,
+    slots: ChainStreamSubscription<SlotUpdate>,
+    tracker: Tracker,
+}
+
+impl CommitmentTracker {
+    /// Subscribes to `method` at processed commitment, plus slot updates on the same network.
+    pub async fn subscribe(client: &ChainStreamClient, method: TransactionMethod) -> Result<Self> {
+        let slots = client
+            .subscribe(
+                Method::new_slot_subscription()
+                    .network(method.network)
+                    .verified(method.verified),
+            )
+            .await?;
+        let transactions = client
+            .subscribe(method.commitment_level(CommitmentLevel::Processed))
+            .await?;
+
+        Ok(Self {
+            transactions,
+            slots,
+            tracker: Tracker::default(),
+        })
+    }
+
+    /// Returns the next event, or `None` once either subscription has ended.
+    pub async fn next(&mut self) -> Option<Result<CommitmentEvent, SubscriptionError>> {
+        loop {
+            if let Some(event) = self.tracker.events.pop_front() {
+                return Some(Ok(event));
+            }
+
+            tokio::select! {
+                transaction = self.transactions.next() => match transaction? {
+                    Ok(transaction) => self.tracker.on_transaction(transaction),
+                    Err(e) => return Some(Err(e)),
+                },
+                update = self.slots.next() => match update? {
+                    Ok(update) => self.tracker.on_slot(update),
+                    Err(e) => return Some(Err(e)),
+                },
+            }
+        }
+    }
+
+    /// Number of transactions that are not finalized or rolled back yet.
+    pub fn pending(&self) -> usize {
+        self.tracker.pending.values().map(HashMap::len).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chainstream::{
+        client::ClientBuilder,
+        mock::{fixtures, MockServer, Notification},
+    };
+
+    fn transaction(slot: u64, seed: u8) -> TransactionWrite {
+        fixtures::transaction(slot, fixtures::signature(seed), &["A"], vec![])
+    }
+
+    fn slot(tracker: &mut Tracker, slot: u64, parent: u64, status: SlotStatus) {
+        tracker.on_slot(fixtures::slot_update(slot, Some(parent), status));
+    }
+
+    fn events(tracker: &mut Tracker) -> Vec<String> {
+        tracker
+            .events
+            .drain(..)
+            .map(|event| match event {
+                CommitmentEvent::Processed(tx) => format!("processed {}", tx.value.slot),
+                CommitmentEvent::Confirmed { slot, .. } => format!("confirmed {slot}"),
+                CommitmentEvent::Finalized { slot, .. } => format!("finalized {slot}"),
+                CommitmentEvent::RolledBack { slot, .. } => format!("rolled back {slot}"),
+                CommitmentEvent::Unresolved { slot, .. } => format!("unresolved {slot}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_promotes_and_rolls_back_forks() {
+        let mut tracker = Tracker::default();
+
+        // 10 <- 11 <- 13 is the canonical chain, 12 forks off 11.
+        slot(&mut tracker, 10, 9, SlotStatus::Finalized);
+        tracker.on_transaction(transaction(11, 1));
+        tracker.on_transaction(transaction(12, 2));
+        tracker.on_transaction(transaction(13, 3));
+        slot(&mut tracker, 11, 10, SlotStatus::Processed);
+        slot(&mut tracker, 12, 11, SlotStatus::Processed);
+        slot(&mut tracker, 13, 11, SlotStatus::Confirmed);
+        assert_eq!(
+            events(&mut tracker),
+            [
+                "processed 11",
+                "processed 12",
+                "processed 13",
+                "confirmed 13"
+            ]
+        );
+
+        // Only 13's root notification arrives; 11 is finalized as its ancestor.
+        slot(&mut tracker, 13, 11, SlotStatus::Finalized);
+        assert_eq!(
+            events(&mut tracker),
+            ["finalized 11", "rolled back 12", "finalized 13"]
+        );
+        assert_eq!(tracker.pending.len(), 0);
+
+        // Transactions arriving for settled slots are resolved right away.
+        tracker.on_transaction(transaction(13, 4));
+        tracker.on_transaction(transaction(12, 5));
+        assert_eq!(
+            events(&mut tracker),
+            [
+                "processed 13",
+                "finalized 13",
+                "processed 12",
+                "rolled back 12"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dead_slot() {
+        let mut tracker = Tracker::default();
+
+        tracker.on_transaction(transaction(20, 1));
+        slot(&mut tracker, 20, 19, SlotStatus::Confirmed);
+        slot(&mut tracker, 20, 19, SlotStatus::Processed);
+        slot(&mut tracker, 21, 19, SlotStatus::Dead);
+        tracker.on_transaction(transaction(21, 2));
+        assert_eq!(
+            events(&mut tracker),
+            [
+                "processed 20",
+                "confirmed 20",
+                "processed 21",
+                "rolled back 21"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missed_parent_link() {
+        let mut tracker = Tracker::default();
+
+        // 10 <- 11 <- 12 <- 13, but the update for 12 is missed.
+        slot(&mut tracker, 10, 9, SlotStatus::Finalized);
+        tracker.on_transaction(transaction(11, 1));
+        tracker.on_transaction(transaction(12, 2));
+        slot(&mut tracker, 11, 10, SlotStatus::Processed);
+        slot(&mut tracker, 13, 12, SlotStatus::Finalized);
+        assert_eq!(
+            events(&mut tracker),
+            ["processed 11", "processed 12", "finalized 12"]
+        );
+        // 11 can't be placed without 12's parent.
+        assert_eq!(tracker.pending.len(), 1);
+
+        slot(&mut tracker, 12, 11, SlotStatus::Processed);
+        assert_eq!(events(&mut tracker), ["finalized 11"]);
+
+        // A root without a parent settles nothing below it.
+        tracker.on_transaction(transaction(15, 3));
+        tracker.on_slot(fixtures::slot_update(16, None, SlotStatus::Finalized));
+        assert_eq!(events(&mut tracker), ["processed 15"]);
+        assert_eq!(tracker.pending.len(), 1);
+    }
+
+    #[test]
+    fn test_late_transactions() {
+        let mut tracker = Tracker::default();
+
+        slot(&mut tracker, 10, 9, SlotStatus::Finalized);
+        slot(&mut tracker, 11, 10, SlotStatus::Finalized);
+        slot(&mut tracker, 13, 11, SlotStatus::Finalized);
+        tracker.on_transaction(transaction(10, 1));
+        tracker.on_transaction(transaction(12, 2));
+        tracker.on_transaction(transaction(5, 3));
+        assert_eq!(
+            events(&mut tracker),
+            [
+                "processed 10",
+                "finalized 10",
+                "processed 12",
+                "rolled back 12",
+                "processed 5"
+            ]
+        );
+        assert_eq!(tracker.pending.len(), 1);
+    }
+
+    #[test]
+    fn test_ignores_redelivered_transactions() {
+        let mut tracker = Tracker::default();
+
+        tracker.on_transaction(transaction(10, 1));
+        tracker.on_transaction(transaction(10, 1));
+        slot(&mut tracker, 10, 9, SlotStatus::Finalized);
+        slot(&mut tracker, 12, 10, SlotStatus::Finalized);
+        tracker.on_transaction(transaction(10, 1));
+        tracker.on_transaction(transaction(11, 2));
+        tracker.on_transaction(transaction(11, 2));
+        assert_eq!(
+            events(&mut tracker),
+            [
+                "processed 10",
+                "finalized 10",
+                "processed 11",
+                "rolled back 11"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_expires_unplaceable_transactions() {
+        let mut tracker = Tracker::default();
+
+        // 11's parent link is never seen, so it can't be placed.
+        slot(&mut tracker, 10, 9, SlotStatus::Finalized);
+        tracker.on_transaction(transaction(11, 1));
+        tracker.on_slot(fixtures::slot_update(12, None, SlotStatus::Finalized));
+        assert_eq!(events(&mut tracker), ["processed 11"]);
+
+        let root = 11 + RETAINED_SLOTS + 1;
+        tracker.on_slot(fixtures::slot_update(root, None, SlotStatus::Finalized));
+        assert_eq!(events(&mut tracker), ["unresolved 11"]);
+        assert_eq!(tracker.pending.len(), 0);
+
+        // Late arrivals below the horizon are given up on right away, and only once.
+        tracker.on_transaction(transaction(11, 1));
+        tracker.on_transaction(transaction(5, 2));
+        tracker.on_transaction(transaction(5, 2));
+        assert_eq!(events(&mut tracker), ["processed 5", "unresolved 5"]);
+        assert_eq!(tracker.pending.len(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_tracker_subscription() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .build()
+            .await
+            .unwrap();
+        let mut tracker =
+            CommitmentTracker::subscribe(&client, Method::new_transaction_subscription())
+                .await
+                .unwrap();
+
+        let request = &server.requests()[1];
+        assert_eq!(request.params["filter"]["commitment"], "processed");
+
+        server.send(Notification::Transaction(transaction(30, 1)));
+        assert!(matches!(
+            tracker.next().await,
+            Some(Ok(CommitmentEvent::Processed(_)))
+        ));
+
+        server.send(Notification::Slot(fixtures::slot_update(
+            30,
+            Some(29),
+            SlotStatus::Finalized,
+        )));
+        match tracker.next().await {
+            Some(Ok(CommitmentEvent::Finalized { signature, slot })) => {
+                assert_eq!(signature, fixtures::signature(1));
+                assert_eq!(slot, 30);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+        assert_eq!(tracker.pending(), 0);
+    }
+}
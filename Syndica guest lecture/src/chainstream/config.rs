@@ -0,0 +1,245 @@
+//! Endpoint selection and client configuration loaded from the environment or a config file.
+//!
+//! The client connects to the public ChainStream API by default. An [`Endpoint`] can point it at
+//! any other `ws://`/`wss://` URL instead, such as a staging endpoint, a local recorder, proxy or
+//! mock server.
+use std::{fmt, path::Path, str::FromStr, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use super::subscription::OverflowPolicy;
+
+/// Environment variable holding the Syndica API token.
+pub const TOKEN_ENV: &str = "SYNDICA_TOKEN";
+/// Environment variable holding the websocket URL of the endpoint.
+pub const ENDPOINT_ENV: &str = "CHAINSTREAM_ENDPOINT";
+/// Environment variable holding the path of a TOML config file, see [`ClientConfig::load`].
+pub const CONFIG_PATH_ENV: &str = "CHAINSTREAM_CONFIG";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("Invalid endpoint: {0}")]
+    InvalidEndpoint(String),
+    #[error("Could not read config file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Could not parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// The public ChainStream API.
+pub const CHAINSTREAM_API_URL: &str = "wss://chainstream.api.syndica.io";
+
+/// Where the client connects to.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum Endpoint {
+    /// The public ChainStream API at [`CHAINSTREAM_API_URL`].
+    #[default]
+    ChainStream,
+    /// Any `ws://` or `wss://` URL.
+    Url(String),
+}
+
+impl Endpoint {
+    /// Creates an endpoint for a custom websocket URL, e.g. `ws://127.0.0.1:9000`.
+    pub fn custom(url: impl Into<String>) -> Result<Self, ConfigError> {
+        let url = url.into();
+        if url.starts_with("ws://") || url.starts_with("wss://") {
+            Ok(Endpoint::Url(url))
+        } else {
+            Err(ConfigError::InvalidEndpoint(format!(
+                "{url} (expected a ws:// or wss:// URL)"
+            )))
+        }
+    }
+
+    pub fn url(&self) -> String {
+        match self {
+            Endpoint::ChainStream => CHAINSTREAM_API_URL.to_string(),
+            Endpoint::Url(url) => url.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.url())
+    }
+}
+
+/// Parses a websocket URL (`ws://127.0.0.1:9000`).
+impl FromStr for Endpoint {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Endpoint::custom(s)
+    }
+}
+
+impl Serialize for Endpoint {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.url())
+    }
+}
+
+impl<'de> Deserialize<'de> for Endpoint {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Client settings that can be loaded from a TOML file or the environment and applied with
+/// [`ClientBuilder::config`](super::client::ClientBuilder::config).
+///
+/// ```toml
+/// token = "..."
+/// endpoint = "ws://127.0.0.1:9000"
+/// request_timeout_secs = 30
+/// enable_ws_ping = true
+/// overflow_policy = "drop-oldest"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClientConfig {
+    pub token: Option<String>,
+    pub endpoint: Option<Endpoint>,
+    pub request_timeout_secs: Option<u64>,
+    pub connection_timeout_secs: Option<u64>,
+    pub enable_ws_ping: Option<bool>,
+    pub max_buffer_capacity_per_subscription: Option<usize>,
+    pub buffer_capacity: Option<usize>,
+    pub overflow_policy: Option<OverflowPolicy>,
+}
+
+impl ClientConfig {
+    pub fn from_toml(s: &str) -> Result<Self, ConfigError> {
+        Ok(toml::from_str(s)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Reads [`TOKEN_ENV`] and [`ENDPOINT_ENV`]. Unset variables are left as `None`.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        let endpoint = match std::env::var(ENDPOINT_ENV) {
+            Ok(endpoint) => Some(endpoint.parse()?),
+            Err(_) => None,
+        };
+
+        Ok(Self {
+            token: std::env::var(TOKEN_ENV).ok(),
+            endpoint,
+            ..Default::default()
+        })
+    }
+
+    /// Loads the config file named by [`CONFIG_PATH_ENV`], if set, and overrides it with the
+    /// environment variables read by [`ClientConfig::from_env`].
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match std::env::var(CONFIG_PATH_ENV) {
+            Ok(path) => Self::from_file(path)?,
+            Err(_) => Self::default(),
+        };
+
+        Ok(file.merge(Self::from_env()?))
+    }
+
+    /// Returns `self` with every field that is set in `other` replaced.
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            token: other.token.or(self.token),
+            endpoint: other.endpoint.or(self.endpoint),
+            request_timeout_secs: other.request_timeout_secs.or(self.request_timeout_secs),
+            connection_timeout_secs: other
+                .connection_timeout_secs
+                .or(self.connection_timeout_secs),
+            enable_ws_ping: other.enable_ws_ping.or(self.enable_ws_ping),
+            max_buffer_capacity_per_subscription: other
+                .max_buffer_capacity_per_subscription
+                .or(self.max_buffer_capacity_per_subscription),
+            buffer_capacity: other.buffer_capacity.or(self.buffer_capacity),
+            overflow_policy: other.overflow_policy.or(self.overflow_policy),
+        }
+    }
+
+    pub fn request_timeout(&self) -> Option<Duration> {
+        self.request_timeout_secs.map(Duration::from_secs)
+    }
+
+    pub fn connection_timeout(&self) -> Option<Duration> {
+        self.connection_timeout_secs.map(Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_endpoint() {
+        assert_eq!(
+            "ws://127.0.0.1:9000".parse::<Endpoint>().unwrap(),
+            Endpoint::Url("ws://127.0.0.1:9000".to_string())
+        );
+        assert_eq!(
+            Endpoint::default().url(),
+            "wss://chainstream.api.syndica.io"
+        );
+        assert!(matches!(
+            "https://chainstream.api.syndica.io".parse::<Endpoint>(),
+            Err(ConfigError::InvalidEndpoint(_))
+        ));
+        assert!(matches!(
+            "europe".parse::<Endpoint>(),
+            Err(ConfigError::InvalidEndpoint(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_from_toml() {
+        let config = ClientConfig::from_toml(
+            r#"
+            token = "secret"
+            endpoint = "ws://localhost:9000"
+            request_timeout_secs = 5
+            overflow_policy = "drop-newest"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.token.as_deref(), Some("secret"));
+        assert_eq!(
+            config.endpoint,
+            Some(Endpoint::Url("ws://localhost:9000".to_string()))
+        );
+        assert_eq!(config.request_timeout(), Some(Duration::from_secs(5)));
+        assert_eq!(config.enable_ws_ping, None);
+        assert_eq!(config.overflow_policy, Some(OverflowPolicy::DropNewest));
+
+        assert!(ClientConfig::from_toml("endpoint = \"http://localhost\"").is_err());
+        assert!(ClientConfig::from_toml("tokn = \"typo\"").is_err());
+    }
+
+    #[test]
+    fn test_merge_prefers_other() {
+        let file = ClientConfig {
+            token: Some("file".to_string()),
+            endpoint: Some(Endpoint::Url("ws://localhost:9000".to_string())),
+            enable_ws_ping: Some(true),
+            ..Default::default()
+        };
+        let env = ClientConfig {
+            token: Some("env".to_string()),
+            ..Default::default()
+        };
+
+        let merged = file.merge(env);
+        assert_eq!(merged.token.as_deref(), Some("env"));
+        assert_eq!(
+            merged.endpoint,
+            Some(Endpoint::Url("ws://localhost:9000".to_string()))
+        );
+        assert_eq!(merged.enable_ws_ping, Some(true));
+    }
+}
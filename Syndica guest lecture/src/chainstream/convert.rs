@@ -0,0 +1,458 @@
+//! Conversions from the ChainStream JSON types into `solana-sdk` types.
+//!
+//! ChainStream sends keys, signatures and blockhashes as base58 strings, instruction data as
+//! base64 and transaction errors as JSON. The conversions here parse every field and fail with a
+//! [`ConversionError`] naming the field that could not be parsed.
+use std::str::FromStr;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use solana_sdk::{
+    hash::Hash,
+    instruction::CompiledInstruction,
+    message::{
+        legacy,
+        v0::{self, MessageAddressTableLookup},
+        MessageHeader, VersionedMessage,
+    },
+    pubkey::Pubkey,
+    signature::Signature,
+    transaction::{TransactionError, VersionedTransaction},
+};
+
+use super::types::transaction as types;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConversionError {
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid hash: {0}")]
+    InvalidHash(String),
+    #[error("Invalid instruction data: {0}")]
+    InvalidInstructionData(#[from] base64::DecodeError),
+    #[error("Account index {0} does not fit in a u8")]
+    IndexOutOfRange(u32),
+    #[error("Header count {0} does not fit in a u8")]
+    HeaderCountOutOfRange(u32),
+    #[error("Inner instructions reference top-level instruction {0}, which does not exist")]
+    InnerInstructionIndexOutOfBounds(u32),
+    #[error("Account index {0} is out of bounds")]
+    AccountIndexOutOfBounds(u32),
+    #[error("Missing field: {0}")]
+    MissingField(&'static str),
+    #[error("Invalid transaction error: {0}")]
+    InvalidTransactionError(serde_json::Error),
+}
+
+pub fn pubkey(key: &str) -> Result<Pubkey, ConversionError> {
+    Pubkey::from_str(key).map_err(|_| ConversionError::InvalidPubkey(key.to_string()))
+}
+
+pub fn signature(signature: &str) -> Result<Signature, ConversionError> {
+    Signature::from_str(signature)
+        .map_err(|_| ConversionError::InvalidSignature(signature.to_string()))
+}
+
+pub fn hash(hash: &str) -> Result<Hash, ConversionError> {
+    Hash::from_str(hash).map_err(|_| ConversionError::InvalidHash(hash.to_string()))
+}
+
+fn index(index: u32) -> Result<u8, ConversionError> {
+    u8::try_from(index).map_err(|_| ConversionError::IndexOutOfRange(index))
+}
+
+fn indexes(indexes: &[u32]) -> Result<Vec<u8>, ConversionError> {
+    indexes.iter().copied().map(index).collect()
+}
+
+impl types::CompiledInstruction {
+    /// The instruction data, decoded from base64.
+    pub fn decoded_data(&self) -> Result<Vec<u8>, ConversionError> {
+        Ok(STANDARD.decode(&self.data)?)
+    }
+}
+
+impl TryFrom<&types::CompiledInstruction> for CompiledInstruction {
+    type Error = ConversionError;
+
+    fn try_from(instruction: &types::CompiledInstruction) -> Result<Self, Self::Error> {
+        Ok(CompiledInstruction {
+            program_id_index: index(instruction.program_id_index)?,
+            accounts: indexes(&instruction.accounts)?,
+            data: instruction.decoded_data()?,
+        })
+    }
+}
+
+impl TryFrom<&types::Header> for MessageHeader {
+    type Error = ConversionError;
+
+    fn try_from(header: &types::Header) -> Result<Self, Self::Error> {
+        let count = |count: u32| {
+            u8::try_from(count).map_err(|_| ConversionError::HeaderCountOutOfRange(count))
+        };
+        Ok(MessageHeader {
+            num_required_signatures: count(header.num_required_signatures)?,
+            num_readonly_signed_accounts: count(header.num_readonly_signed_accounts)?,
+            num_readonly_unsigned_accounts: count(header.num_readonly_unsigned_accounts)?,
+        })
+    }
+}
+
+impl TryFrom<&types::AddressTableLookup> for MessageAddressTableLookup {
+    type Error = ConversionError;
+
+    fn try_from(lookup: &types::AddressTableLookup) -> Result<Self, Self::Error> {
+        Ok(MessageAddressTableLookup {
+            account_key: pubkey(&lookup.account_key)?,
+            writable_indexes: indexes(&lookup.writable_indexes)?,
+            readonly_indexes: indexes(&lookup.readonly_indexes)?,
+        })
+    }
+}
+
+/// Messages with address table lookups become [`VersionedMessage::V0`], all others
+/// [`VersionedMessage::Legacy`]. The message alone does not say which version a message without
+/// lookups was sent as; convert the whole [`types::Body`] instead, which tells them apart by the
+/// message hash. Only the version the message was signed as verifies against its signatures.
+impl TryFrom<&types::Message> for VersionedMessage {
+    type Error = ConversionError;
+
+    fn try_from(message: &types::Message) -> Result<Self, Self::Error> {
+        let header = message
+            .header
+            .as_ref()
+            .ok_or(ConversionError::MissingField("message.header"))?
+            .try_into()?;
+        let account_keys = message
+            .account_keys
+            .iter()
+            .map(|key| pubkey(key))
+            .collect::<Result<Vec<_>, _>>()?;
+        let recent_blockhash = hash(&message.recent_blockhash)?;
+        let instructions = message
+            .instructions
+            .iter()
+            .map(CompiledInstruction::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if message.address_table_lookups.is_empty() {
+            return Ok(VersionedMessage::Legacy(legacy::Message {
+                header,
+                account_keys,
+                recent_blockhash,
+                instructions,
+            }));
+        }
+
+        let address_table_lookups = message
+            .address_table_lookups
+            .iter()
+            .map(MessageAddressTableLookup::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(VersionedMessage::V0(v0::Message {
+            header,
+            account_keys,
+            recent_blockhash,
+            instructions,
+            address_table_lookups,
+        }))
+    }
+}
+
+impl TryFrom<&types::Body> for VersionedTransaction {
+    type Error = ConversionError;
+
+    fn try_from(body: &types::Body) -> Result<Self, Self::Error> {
+        let message = body
+            .message
+            .as_ref()
+            .ok_or(ConversionError::MissingField("transaction.message"))?;
+
+        Ok(VersionedTransaction {
+            signatures: body
+                .signatures
+                .iter()
+                .map(|s| signature(s))
+                .collect::<Result<_, _>>()?,
+            message: versioned_message(body, message)?,
+        })
+    }
+}
+
+/// Converts `message`, keeping a v0 message without lookups as v0 when its hash says so. Assumes
+/// `messageHash` is the hash Solana computes over the serialized message, see
+/// [`VersionedMessage::hash`]; if neither version matches, the message is taken as legacy.
+fn versioned_message(
+    body: &types::Body,
+    message: &types::Message,
+) -> Result<VersionedMessage, ConversionError> {
+    let converted = VersionedMessage::try_from(message)?;
+    let VersionedMessage::Legacy(legacy) = &converted else {
+        return Ok(converted);
+    };
+    // Without a usable hash there is nothing to tell the versions apart by.
+    let Ok(expected) = hash(&body.message_hash) else {
+        return Ok(converted);
+    };
+    if converted.hash() == expected {
+        return Ok(converted);
+    }
+
+    let v0 = VersionedMessage::V0(v0::Message {
+        header: legacy.header,
+        account_keys: legacy.account_keys.clone(),
+        recent_blockhash: legacy.recent_blockhash,
+        instructions: legacy.instructions.clone(),
+        address_table_lookups: vec![],
+    });
+    Ok(if v0.hash() == expected { v0 } else { converted })
+}
+
+impl types::Meta {
+    /// The error the transaction failed with, or `None` if it succeeded. Read from `err`, falling
+    /// back to `status`.
+    pub fn transaction_error(&self) -> Result<Option<TransactionError>, ConversionError> {
+        match (&self.err, &self.status) {
+            (Some(err), _) if !err.is_null() => serde_json::from_value(err.clone())
+                .map(Some)
+                .map_err(ConversionError::InvalidTransactionError),
+            (_, Some(status)) if !status.is_null() => {
+                serde_json::from_value::<types::TransactionStatus>(status.clone())
+                    .map(|status| status.error().cloned())
+                    .map_err(ConversionError::InvalidTransactionError)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Whether the transaction succeeded, see [`Self::transaction_error`].
+    pub fn transaction_status(&self) -> Result<types::TransactionStatus, ConversionError> {
+        Ok(match self.transaction_error()? {
+            Some(err) => types::TransactionStatus::Err(err),
+            None => types::TransactionStatus::Ok(()),
+        })
+    }
+
+    /// Inner instructions grouped by the index of the top-level instruction that invoked them.
+    pub fn decoded_inner_instructions(
+        &self,
+    ) -> Result<Vec<(u8, Vec<CompiledInstruction>)>, ConversionError> {
+        self.inner_instructions
+            .iter()
+            .map(|inner| {
+                let instructions = inner
+                    .instructions
+                    .iter()
+                    .map(CompiledInstruction::try_from)
+                    .collect::<Result<_, _>>()?;
+                Ok((index(inner.index)?, instructions))
+            })
+            .collect()
+    }
+}
+
+impl types::TransactionWrite {
+    /// The transaction as sent, with every field parsed.
+    pub fn versioned_transaction(&self) -> Result<VersionedTransaction, ConversionError> {
+        self.value
+            .transaction
+            .as_ref()
+            .ok_or(ConversionError::MissingField("transaction"))?
+            .try_into()
+    }
+
+    pub fn versioned_message(&self) -> Result<VersionedMessage, ConversionError> {
+        let body = self
+            .value
+            .transaction
+            .as_ref()
+            .ok_or(ConversionError::MissingField("transaction"))?;
+        let message = body
+            .message
+            .as_ref()
+            .ok_or(ConversionError::MissingField("transaction.message"))?;
+        versioned_message(body, message)
+    }
+
+    /// The error the transaction failed with, or `None` if it succeeded.
+    pub fn transaction_error(&self) -> Result<Option<TransactionError>, ConversionError> {
+        self.value
+            .meta
+            .as_ref()
+            .ok_or(ConversionError::MissingField("meta"))?
+            .transaction_error()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::{
+        instruction::InstructionError,
+        signature::{Keypair, Signer},
+    };
+
+    use super::*;
+    use crate::chainstream::mock::fixtures;
+
+    fn transaction(lookups: bool) -> types::TransactionWrite {
+        let [payer, program] = [Pubkey::new_unique(), Pubkey::new_unique()];
+        let mut transaction = fixtures::transaction(
+            1,
+            fixtures::signature(1),
+            &[&payer.to_string(), &program.to_string()],
+            vec![],
+        );
+        let message = transaction
+            .value
+            .transaction
+            .as_mut()
+            .unwrap()
+            .message
+            .as_mut()
+            .unwrap();
+        message.header = Some(types::Header {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        });
+        message.recent_blockhash = Hash::new_unique().to_string();
+        message.instructions = vec![types::CompiledInstruction {
+            program_id_index: 1,
+            accounts: vec![0],
+            data: STANDARD.encode([1, 2, 3]),
+            stack_height: None,
+        }];
+        if lookups {
+            message.address_table_lookups = vec![types::AddressTableLookup {
+                account_key: Pubkey::new_unique().to_string(),
+                writable_indexes: vec![0],
+                readonly_indexes: vec![3],
+            }];
+        }
+        transaction
+    }
+
+    #[test]
+    fn test_versioned_transaction() {
+        let converted = transaction(false).versioned_transaction().unwrap();
+
+        assert_eq!(
+            converted.signatures,
+            [signature(&fixtures::signature(1)).unwrap()]
+        );
+        let VersionedMessage::Legacy(message) = &converted.message else {
+            panic!("expected a legacy message");
+        };
+        assert_eq!(message.header.num_readonly_unsigned_accounts, 1);
+        assert_eq!(message.instructions[0].data, [1, 2, 3]);
+        assert_eq!(message.instructions[0].program_id_index, 1);
+
+        let v0 = transaction(true).versioned_message().unwrap();
+        assert_eq!(v0.address_table_lookups().unwrap()[0].readonly_indexes, [3]);
+    }
+
+    #[test]
+    fn test_keeps_v0_message_without_lookups() {
+        let payer = Keypair::new();
+        let mut transaction = transaction(false);
+        let body = transaction.value.transaction.as_mut().unwrap();
+        body.message.as_mut().unwrap().account_keys[0] = payer.pubkey().to_string();
+
+        let VersionedMessage::Legacy(legacy) =
+            VersionedMessage::try_from(body.message.as_ref().unwrap()).unwrap()
+        else {
+            panic!("expected a legacy message");
+        };
+        let v0 = VersionedMessage::V0(v0::Message {
+            header: legacy.header,
+            account_keys: legacy.account_keys.clone(),
+            recent_blockhash: legacy.recent_blockhash,
+            instructions: legacy.instructions.clone(),
+            address_table_lookups: vec![],
+        });
+
+        for signed in [VersionedMessage::Legacy(legacy), v0] {
+            let signed = VersionedTransaction::try_new(signed, &[&payer]).unwrap();
+            let body = transaction.value.transaction.as_mut().unwrap();
+            body.signatures = vec![signed.signatures[0].to_string()];
+            body.message_hash = signed.message.hash().to_string();
+
+            let converted = transaction.versioned_transaction().unwrap();
+            assert_eq!(converted.message, signed.message);
+            assert!(converted.verify_with_results().into_iter().all(|ok| ok));
+            assert_eq!(transaction.versioned_message().unwrap(), signed.message);
+        }
+
+        // Without a hash, a message without lookups is taken as legacy.
+        transaction.value.transaction.as_mut().unwrap().message_hash = String::new();
+        assert!(matches!(
+            transaction.versioned_message().unwrap(),
+            VersionedMessage::Legacy(_)
+        ));
+    }
+
+    #[test]
+    fn test_conversion_errors() {
+        let mut bad_key = transaction(false);
+        let body = bad_key.value.transaction.as_mut().unwrap();
+        body.message.as_mut().unwrap().account_keys[0] = "not-a-key".to_string();
+        assert!(matches!(
+            bad_key.versioned_message(),
+            Err(ConversionError::InvalidPubkey(key)) if key == "not-a-key"
+        ));
+
+        let mut bad_data = transaction(false);
+        let body = bad_data.value.transaction.as_mut().unwrap();
+        body.message.as_mut().unwrap().instructions[0].data = "%%%".to_string();
+        assert!(matches!(
+            bad_data.versioned_message(),
+            Err(ConversionError::InvalidInstructionData(_))
+        ));
+
+        let mut bad_index = transaction(false);
+        let body = bad_index.value.transaction.as_mut().unwrap();
+        body.message.as_mut().unwrap().instructions[0].accounts = vec![256];
+        assert!(matches!(
+            bad_index.versioned_message(),
+            Err(ConversionError::IndexOutOfRange(256))
+        ));
+
+        let mut bad_header = transaction(false);
+        let body = bad_header.value.transaction.as_mut().unwrap();
+        let message = body.message.as_mut().unwrap();
+        message.header.as_mut().unwrap().num_required_signatures = 300;
+        assert!(matches!(
+            bad_header.versioned_message(),
+            Err(ConversionError::HeaderCountOutOfRange(300))
+        ));
+    }
+
+    #[test]
+    fn test_transaction_error() {
+        let mut transaction = transaction(false);
+        assert_eq!(transaction.transaction_error().unwrap(), None);
+
+        let err = TransactionError::InstructionError(0, InstructionError::Custom(6022));
+        transaction.value.meta.as_mut().unwrap().status =
+            Some(serde_json::to_value(types::TransactionStatus::Err(err.clone())).unwrap());
+        assert_eq!(transaction.transaction_error().unwrap(), Some(err.clone()));
+
+        transaction.value.meta.as_mut().unwrap().err = Some(serde_json::to_value(&err).unwrap());
+        transaction.value.meta.as_mut().unwrap().status = None;
+        assert_eq!(transaction.transaction_error().unwrap(), Some(err));
+
+        transaction.value.meta.as_mut().unwrap().err = Some(serde_json::json!("NotAnError"));
+        assert!(matches!(
+            transaction.transaction_error(),
+            Err(ConversionError::InvalidTransactionError(_))
+        ));
+
+        transaction.value.meta = None;
+        assert!(matches!(
+            transaction.transaction_error(),
+            Err(ConversionError::MissingField("meta"))
+        ));
+    }
+}
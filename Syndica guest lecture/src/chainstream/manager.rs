@@ -0,0 +1,374 @@
+//! Shares upstream transaction subscriptions between many local consumers.
+//!
+//! [`SubscriptionManager`] opens one `transactionsSubscribe` per distinct [`TransactionMethod`]
+//! and fans its notifications out to every [`ManagedSubscription`] with the same filter through a
+//! bounded broadcast channel. Upstreams are [`ReconnectingSubscription`]s, so they survive
+//! connection drops and pass their lag and reconnect markers on to every consumer. The upstream
+//! subscription is only unsubscribed once the last consumer drops.
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, Weak},
+};
+
+use anyhow::Result;
+use tokio::sync::{broadcast, oneshot};
+
+use super::{
+    client::ChainStreamClient,
+    methods::TransactionMethod,
+    reconnect::{ReconnectingSubscription, StreamEvent},
+    types::transaction::TransactionWrite,
+};
+
+/// Default number of notifications buffered per upstream before slow consumers start lagging.
+pub const DEFAULT_CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum FanOutError {
+    /// This consumer fell behind the others sharing the upstream. Lag of the upstream itself is
+    /// reported as [`StreamEvent::Lagged`].
+    #[error("Consumer lagged behind and missed {0} notifications")]
+    Lagged(u64),
+    #[error("Upstream subscription error: {0}")]
+    Upstream(String),
+}
+
+type Item = Result<StreamEvent<Arc<TransactionWrite>>, FanOutError>;
+type Upstreams = Arc<Mutex<HashMap<String, Weak<Upstream>>>>;
+
+#[derive(Debug, Clone)]
+pub struct SubscriptionManager {
+    client: ChainStreamClient,
+    capacity: usize,
+    upstreams: Upstreams,
+    /// Serializes upstream subscribe calls so that two consumers racing on the same filter
+    /// don't both open one.
+    subscribing: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl SubscriptionManager {
+    pub fn new(client: ChainStreamClient) -> Self {
+        Self {
+            client,
+            capacity: DEFAULT_CHANNEL_CAPACITY,
+            upstreams: Default::default(),
+            subscribing: Default::default(),
+        }
+    }
+
+    /// Number of notifications buffered per upstream subscription (default is 1024). Consumers
+    /// that fall further behind get [`FanOutError::Lagged`].
+    #[allow(unused)]
+    pub fn capacity(self, capacity: usize) -> Self {
+        Self { capacity, ..self }
+    }
+
+    /// Subscribes to `method`, reusing the upstream subscription of any live consumer with an
+    /// identical method and filter.
+    pub async fn subscribe(&self, method: TransactionMethod) -> Result<ManagedSubscription> {
+        let key = upstream_key(&method)?;
+        let _guard = self.subscribing.lock().await;
+
+        if let Some(subscription) = self.join(&key) {
+            return Ok(subscription);
+        }
+
+        let subscription = self.client.subscribe_with_reconnect(method).await?;
+        let (sender, receiver) = broadcast::channel(self.capacity);
+        let (shutdown, shutdown_rx) = oneshot::channel();
+        let upstream = Arc::new(Upstream {
+            key: key.clone(),
+            sender: sender.downgrade(),
+            upstreams: self.upstreams.clone(),
+            _shutdown: shutdown,
+        });
+        tokio::spawn(pump(subscription, sender, shutdown_rx));
+
+        self.upstreams
+            .lock()
+            .unwrap()
+            .insert(key, Arc::downgrade(&upstream));
+
+        Ok(ManagedSubscription { receiver, upstream })
+    }
+
+    /// Number of upstream subscriptions currently open.
+    #[allow(unused)]
+    pub fn upstream_count(&self) -> usize {
+        self.upstreams
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|upstream| upstream.strong_count() > 0)
+            .count()
+    }
+
+    fn join(&self, key: &str) -> Option<ManagedSubscription> {
+        let upstream = self.upstreams.lock().unwrap().get(key)?.upgrade()?;
+        // The sender is gone once the upstream subscription has ended, in which case a new one
+        // is opened instead.
+        let receiver = upstream.sender.upgrade()?.subscribe();
+        Some(ManagedSubscription { receiver, upstream })
+    }
+}
+
+/// Identifies the upstream subscription for `method`. Key sets are sorted and deduplicated, as
+/// their order doesn't change what the server sends.
+fn upstream_key(method: &TransactionMethod) -> Result<String> {
+    let mut method = method.clone();
+    if let Some(selector) = method.filter.account_keys.as_mut() {
+        let sets = [
+            &mut selector.all,
+            &mut selector.one_of,
+            &mut selector.exclude,
+        ];
+        for keys in sets.into_iter().flatten() {
+            keys.sort();
+            keys.dedup();
+        }
+    }
+    Ok(serde_json::to_string(&method)?)
+}
+
+/// One upstream subscription, kept alive by the [`ManagedSubscription`]s sharing it.
+#[derive(Debug)]
+struct Upstream {
+    key: String,
+    sender: broadcast::WeakSender<Item>,
+    upstreams: Upstreams,
+    /// Dropping this tells the pump task to drop the upstream subscription, which unsubscribes.
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl Drop for Upstream {
+    fn drop(&mut self) {
+        let mut upstreams = self.upstreams.lock().unwrap();
+        // A replacement may already have been registered under the same key.
+        if upstreams
+            .get(&self.key)
+            .is_some_and(|upstream| upstream.strong_count() == 0)
+        {
+            upstreams.remove(&self.key);
+        }
+    }
+}
+
+/// Forwards upstream events to every receiver until the upstream subscription gives up or the
+/// last consumer drops, in which case it unsubscribes.
+async fn pump(
+    mut subscription: ReconnectingSubscription<TransactionMethod>,
+    sender: broadcast::Sender<Item>,
+    mut shutdown: oneshot::Receiver<()>,
+) {
+    loop {
+        tokio::select! {
+            _ = &mut shutdown => return,
+            event = subscription.next() => match event {
+                Some(Ok(event)) => {
+                    let event = match event {
+                        StreamEvent::Notification(transaction) => {
+                            StreamEvent::Notification(Arc::new(transaction))
+                        }
+                        StreamEvent::Lagged { missed } => StreamEvent::Lagged { missed },
+                        StreamEvent::Reconnected { attempts, downtime } => {
+                            StreamEvent::Reconnected { attempts, downtime }
+                        }
+                    };
+                    let _ = sender.send(Ok(event));
+                }
+                Some(Err(e)) => {
+                    let _ = sender.send(Err(FanOutError::Upstream(e.to_string())));
+                }
+                None => return,
+            },
+        }
+    }
+}
+
+/// A consumer's handle on a shared upstream subscription.
+#[derive(Debug)]
+pub struct ManagedSubscription {
+    receiver: broadcast::Receiver<Item>,
+    upstream: Arc<Upstream>,
+}
+
+impl ManagedSubscription {
+    /// Returns the next event of the upstream subscription, or `None` once it has given up
+    /// reconnecting.
+    pub async fn next(&mut self) -> Option<Item> {
+        match self.receiver.recv().await {
+            Ok(item) => Some(item),
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                Some(Err(FanOutError::Lagged(missed)))
+            }
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    }
+
+    /// Number of consumers sharing this upstream subscription, including this one.
+    #[allow(unused)]
+    pub fn consumer_count(&self) -> usize {
+        Arc::strong_count(&self.upstream)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use solana_sdk::pubkey::Pubkey;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::chainstream::{
+        client::ClientBuilder,
+        methods::Method,
+        mock::{fixtures, MockServer, Notification},
+        reconnect::ReconnectConfig,
+    };
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn manager(server: &MockServer) -> SubscriptionManager {
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .reconnect(
+                ReconnectConfig::default()
+                    .initial_backoff(Duration::from_millis(10))
+                    .jitter(0.0),
+            )
+            .build()
+            .await
+            .unwrap();
+        SubscriptionManager::new(client).capacity(4)
+    }
+
+    async fn next_slot(subscription: &mut ManagedSubscription) -> u64 {
+        match timeout(TIMEOUT, subscription.next()).await.unwrap() {
+            Some(Ok(StreamEvent::Notification(transaction))) => transaction.value.slot,
+            other => panic!("expected a notification, got {other:?}"),
+        }
+    }
+
+    fn transaction(slot: u64, account: &str) -> Notification {
+        Notification::Transaction(fixtures::transaction(
+            slot,
+            fixtures::signature(slot as u8),
+            &[account],
+            vec![],
+        ))
+    }
+
+    #[tokio::test]
+    async fn test_dedupes_identical_filters() {
+        let server = MockServer::builder().start().await.unwrap();
+        let manager = manager(&server).await;
+        let method =
+            || Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]);
+
+        let mut first = manager.subscribe(method()).await.unwrap();
+        let mut second = manager.subscribe(method()).await.unwrap();
+        let mut other = manager
+            .subscribe(method().exclude_votes(true))
+            .await
+            .unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(manager.upstream_count(), 2);
+        assert_eq!(first.consumer_count(), 2);
+
+        // The order of the keys in a set doesn't matter.
+        let (a, b) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let _ab = manager
+            .subscribe(Method::new_transaction_subscription().one_of_account_keys(&[a, b]))
+            .await
+            .unwrap();
+        let ba = manager
+            .subscribe(Method::new_transaction_subscription().one_of_account_keys(&[b, a, b]))
+            .await
+            .unwrap();
+        assert_eq!(ba.consumer_count(), 2);
+        assert_eq!(server.requests().len(), 3);
+
+        server.send(transaction(1, &fixtures::pubkey(1)));
+        for subscription in [&mut first, &mut second, &mut other] {
+            assert_eq!(next_slot(subscription).await, 1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_unsubscribes_after_last_consumer_drops() {
+        let server = MockServer::builder().start().await.unwrap();
+        let manager = manager(&server).await;
+        let method =
+            || Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]);
+
+        let first = manager.subscribe(method()).await.unwrap();
+        let mut second = manager.subscribe(method()).await.unwrap();
+        assert_eq!(server.active_subscriptions(), 1);
+
+        drop(first);
+        server.send(transaction(1, &fixtures::pubkey(1)));
+        assert_eq!(next_slot(&mut second).await, 1);
+        assert_eq!(server.active_subscriptions(), 1);
+
+        drop(second);
+        assert_eq!(manager.upstream_count(), 0);
+        timeout(TIMEOUT, server.wait_for_active_subscriptions(0))
+            .await
+            .unwrap();
+
+        // A new consumer opens a fresh upstream subscription.
+        let _third = manager.subscribe(method()).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_slow_consumer_lags() {
+        let server = MockServer::builder().start().await.unwrap();
+        let manager = manager(&server).await;
+        let method =
+            Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]);
+        let mut subscription = manager.subscribe(method.clone()).await.unwrap();
+        let mut fast = manager.subscribe(method).await.unwrap();
+
+        // A consumer that reads every notification as it arrives shows when the pump has
+        // forwarded it.
+        for slot in 0..6 {
+            server.send(transaction(slot, &fixtures::pubkey(1)));
+            assert_eq!(next_slot(&mut fast).await, slot);
+        }
+
+        assert!(matches!(
+            subscription.next().await,
+            Some(Err(FanOutError::Lagged(2)))
+        ));
+        assert_eq!(next_slot(&mut subscription).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_upstream_survives_reconnect() {
+        let mut server = MockServer::builder().start().await.unwrap();
+        let manager = manager(&server).await;
+        let method =
+            || Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]);
+        let mut first = manager.subscribe(method()).await.unwrap();
+        let mut second = manager.subscribe(method()).await.unwrap();
+
+        server.drop_connections().await.unwrap();
+        for subscription in [&mut first, &mut second] {
+            assert!(matches!(
+                timeout(TIMEOUT, subscription.next()).await.unwrap(),
+                Some(Ok(StreamEvent::Reconnected { .. }))
+            ));
+        }
+        timeout(TIMEOUT, server.wait_for_requests(2)).await.unwrap();
+
+        // Both consumers still share one upstream, now on the new connection.
+        server.send(transaction(1, &fixtures::pubkey(1)));
+        assert_eq!(next_slot(&mut first).await, 1);
+        assert_eq!(next_slot(&mut second).await, 1);
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -1,11 +1,21 @@
 #![allow(unused)]
 //! Provides the necessary types required to build Chainstream RPC requests.
+//!
+//! There is no full-block subscription yet. [`BlockMethod`] yields block headers only, and the
+//! [`FullBlock`](super::types::full_block::FullBlock) type is not produced by any method: the
+//! `blocksSubscribe` params for requesting transactions with every block are not documented,
+//! and without them the variant can't be built or tested against what the server sends.
+use std::str::FromStr;
+
 use jsonrpsee::core::params::{self, ObjectParams};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json;
+use solana_sdk::pubkey::Pubkey;
 use thiserror;
 
-use super::types::transaction::TransactionWrite;
+use super::types::{
+    account::AccountUpdate, block::BlockUpdate, slot::SlotUpdate, transaction::TransactionWrite,
+};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Network {
@@ -38,6 +48,7 @@ pub enum RpcError {
     ParamsError(String),
 }
 
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Method {
     #[serde(rename = "transactionsSubscribe")]
@@ -46,6 +57,8 @@ pub enum Method {
     BlockSubscribe(BlockMethod),
     #[serde(rename = "slotUpdatesSubscribe")]
     SlotSubscribe(SlotMethod),
+    #[serde(rename = "accountsSubscribe")]
+    AccountSubscribe(AccountMethod),
 }
 
 pub trait SubscriptionMethod {
@@ -69,6 +82,11 @@ impl Method {
     pub fn new_slot_subscription() -> SlotMethod {
         SlotMethod::default()
     }
+
+    #[allow(unused)]
+    pub fn new_account_subscription() -> AccountMethod {
+        AccountMethod::default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -80,6 +98,8 @@ pub struct TransactionMethod {
 }
 
 impl TransactionMethod {
+    /// Replace the whole filter, see [`TransactionFilter::builder`]. The `*_account_keys`
+    /// shorthands below take keys as strings, they are validated by [`Self::build_params`].
     #[allow(unused)]
     pub fn filter(self, filter: TransactionFilter) -> Self {
         Self { filter, ..self }
@@ -150,6 +170,9 @@ impl TransactionMethod {
     }
 
     pub fn build_params(&self) -> Result<ObjectParams, RpcError> {
+        self.filter
+            .validate()
+            .map_err(|e| RpcError::ParamsError(e.to_string()))?;
         let mut params = params::ObjectParams::new();
         params
             .insert("network", self.network.as_str())
@@ -200,26 +223,190 @@ impl SubscriptionMethod for TransactionMethod {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum FilterError {
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("Key {0} is both required and excluded")]
+    Conflict(String),
+}
+
+/// Server-side transaction filter. Build one with [`TransactionFilter::builder`].
+///
+/// The number of keys per set is not limited here: the server's limits are not documented, so an
+/// oversized filter is only rejected when subscribing.
+// TODO: enforce the key limits in `validate` once the API documents them.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct TransactionFilter {
     #[serde(skip_serializing_if = "Option::is_none")]
-    exclude_votes: Option<bool>,
+    pub(crate) exclude_votes: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    account_keys: Option<PubKeySelector>,
+    pub(crate) account_keys: Option<PubKeySelector>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    commitment: Option<CommitmentLevel>,
+    pub(crate) commitment: Option<CommitmentLevel>,
+}
+
+impl TransactionFilter {
+    pub fn builder() -> TransactionFilterBuilder {
+        TransactionFilterBuilder::default()
+    }
+
+    pub fn exclude_votes(&self) -> Option<bool> {
+        self.exclude_votes
+    }
+
+    pub fn account_keys(&self) -> Option<&PubKeySelector> {
+        self.account_keys.as_ref()
+    }
+
+    pub fn commitment(&self) -> Option<CommitmentLevel> {
+        self.commitment
+    }
+
+    /// Checks that every key is a valid pubkey and that no key is both required and excluded.
+    /// Filters built with [`TransactionFilter::builder`] always are.
+    pub fn validate(&self) -> Result<(), FilterError> {
+        let Some(selector) = &self.account_keys else {
+            return Ok(());
+        };
+
+        let keys = selector
+            .all()
+            .iter()
+            .chain(selector.one_of())
+            .chain(selector.exclude());
+        if let Some(key) = keys.into_iter().find(|k| Pubkey::from_str(k).is_err()) {
+            return Err(FilterError::InvalidPubkey(key.clone()));
+        }
+
+        let excluded = selector.exclude();
+        if let Some(key) = selector
+            .all()
+            .iter()
+            .chain(selector.one_of())
+            .find(|k| excluded.contains(k))
+        {
+            return Err(FilterError::Conflict(key.clone()));
+        }
+
+        Ok(())
+    }
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Account key sets a transaction has to match. Every set that is present has to be satisfied.
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", deny_unknown_fields)]
-struct PubKeySelector {
+pub struct PubKeySelector {
     #[serde(skip_serializing_if = "Option::is_none")]
-    exclude: Option<Vec<String>>,
+    pub(crate) exclude: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    all: Option<Vec<String>>,
+    pub(crate) all: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    one_of: Option<Vec<String>>,
+    pub(crate) one_of: Option<Vec<String>>,
+}
+
+impl PubKeySelector {
+    /// Keys that must all be present.
+    pub fn all(&self) -> &[String] {
+        self.all.as_deref().unwrap_or_default()
+    }
+
+    /// Keys of which at least one must be present.
+    pub fn one_of(&self) -> &[String] {
+        self.one_of.as_deref().unwrap_or_default()
+    }
+
+    /// Keys that must not be present.
+    pub fn exclude(&self) -> &[String] {
+        self.exclude.as_deref().unwrap_or_default()
+    }
+}
+
+/// Builds a validated [`TransactionFilter`]. Key sets can be extended by repeated calls;
+/// duplicates are ignored.
+#[derive(Default, Debug, Clone)]
+pub struct TransactionFilterBuilder {
+    exclude_votes: Option<bool>,
+    commitment: Option<CommitmentLevel>,
+    all: Vec<Pubkey>,
+    one_of: Vec<Pubkey>,
+    exclude: Vec<Pubkey>,
+}
+
+impl TransactionFilterBuilder {
+    #[allow(unused)]
+    pub fn exclude_votes(self, exclude_votes: bool) -> Self {
+        Self {
+            exclude_votes: Some(exclude_votes),
+            ..self
+        }
+    }
+
+    #[allow(unused)]
+    pub fn commitment(self, commitment: CommitmentLevel) -> Self {
+        Self {
+            commitment: Some(commitment),
+            ..self
+        }
+    }
+
+    /// Require every one of `keys`.
+    #[allow(unused)]
+    pub fn all(self, keys: impl IntoIterator<Item = Pubkey>) -> Self {
+        Self {
+            all: extend_unique(self.all, keys),
+            ..self
+        }
+    }
+
+    /// Require at least one of `keys`.
+    #[allow(unused)]
+    pub fn one_of(self, keys: impl IntoIterator<Item = Pubkey>) -> Self {
+        Self {
+            one_of: extend_unique(self.one_of, keys),
+            ..self
+        }
+    }
+
+    /// Reject transactions touching any of `keys`.
+    #[allow(unused)]
+    pub fn exclude(self, keys: impl IntoIterator<Item = Pubkey>) -> Self {
+        Self {
+            exclude: extend_unique(self.exclude, keys),
+            ..self
+        }
+    }
+
+    pub fn build(self) -> Result<TransactionFilter, FilterError> {
+        let to_strings = |keys: Vec<Pubkey>| -> Option<Vec<String>> {
+            (!keys.is_empty()).then(|| keys.iter().map(Pubkey::to_string).collect())
+        };
+        let selector = PubKeySelector {
+            exclude: to_strings(self.exclude),
+            all: to_strings(self.all),
+            one_of: to_strings(self.one_of),
+        };
+        let account_keys = (selector != PubKeySelector::default()).then_some(selector);
+
+        let filter = TransactionFilter {
+            exclude_votes: self.exclude_votes,
+            account_keys,
+            commitment: self.commitment,
+        };
+        filter.validate()?;
+
+        Ok(filter)
+    }
+}
+
+fn extend_unique(mut keys: Vec<Pubkey>, more: impl IntoIterator<Item = Pubkey>) -> Vec<Pubkey> {
+    for key in more {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    keys
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -268,7 +455,7 @@ impl Default for BlockMethod {
 }
 
 impl SubscriptionMethod for BlockMethod {
-    type Output = serde_json::Value;
+    type Output = BlockUpdate;
 
     fn subscribe_method(&self) -> &'static str {
         "blocksSubscribe"
@@ -329,7 +516,7 @@ impl Default for SlotMethod {
 }
 
 impl SubscriptionMethod for SlotMethod {
-    type Output = serde_json::Value;
+    type Output = SlotUpdate;
 
     fn subscribe_method(&self) -> &'static str {
         "slotUpdatesSubscribe"
@@ -343,3 +530,218 @@ impl SubscriptionMethod for SlotMethod {
         self.build_params()
     }
 }
+
+/// Subscribes to writes of specific accounts, or of every account owned by a program.
+///
+/// **Experimental.** The `accountsSubscribe` params and notification shape used here, a filter of
+/// `accounts`, `owners` and `commitment` and updates carrying `writeVersion` and `txnSignature`,
+/// are not taken from the ChainStream API documentation and may not match what the server
+/// accepts or sends.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountMethod {
+    pub network: Network,
+    pub verified: bool,
+    pub filter: AccountFilter,
+}
+
+impl AccountMethod {
+    #[allow(unused)]
+    pub fn network(self, network: Network) -> Self {
+        Self { network, ..self }
+    }
+
+    #[allow(unused)]
+    pub fn verified(self, verified: bool) -> Self {
+        Self { verified, ..self }
+    }
+
+    /// Only send writes to these accounts.
+    #[allow(unused)]
+    pub fn accounts(self, accounts: &[Pubkey]) -> Self {
+        let filter = AccountFilter {
+            accounts: Some(accounts.iter().map(Pubkey::to_string).collect()),
+            ..self.filter
+        };
+        Self { filter, ..self }
+    }
+
+    /// Only send writes to accounts owned by these programs.
+    #[allow(unused)]
+    pub fn owners(self, owners: &[Pubkey]) -> Self {
+        let filter = AccountFilter {
+            owners: Some(owners.iter().map(Pubkey::to_string).collect()),
+            ..self.filter
+        };
+        Self { filter, ..self }
+    }
+
+    #[allow(unused)]
+    pub fn commitment_level(self, commitment: CommitmentLevel) -> Self {
+        let filter = AccountFilter {
+            commitment: Some(commitment),
+            ..self.filter
+        };
+        Self { filter, ..self }
+    }
+
+    pub fn build_params(&self) -> Result<ObjectParams, RpcError> {
+        if self.filter.accounts.is_none() && self.filter.owners.is_none() {
+            return Err(RpcError::ParamsError(
+                "account subscriptions need accounts or owners".to_string(),
+            ));
+        }
+
+        let mut params = params::ObjectParams::new();
+        params
+            .insert("network", self.network.as_str())
+            .map_err(|e| RpcError::ParamsError(e.to_string()))?;
+        params
+            .insert("verified", self.verified)
+            .map_err(|e| RpcError::ParamsError(e.to_string()))?;
+        params
+            .insert("filter", serde_json::to_value(&self.filter).unwrap())
+            .map_err(|e| RpcError::ParamsError(e.to_string()))?;
+
+        Ok(params)
+    }
+
+    #[allow(unused)]
+    pub fn build(self) -> Method {
+        Method::AccountSubscribe(self)
+    }
+}
+
+impl Default for AccountMethod {
+    fn default() -> Self {
+        Self {
+            network: Network::SolanaMainnet,
+            verified: false,
+            filter: AccountFilter::default(),
+        }
+    }
+}
+
+impl SubscriptionMethod for AccountMethod {
+    type Output = AccountUpdate;
+
+    fn subscribe_method(&self) -> &'static str {
+        "accountsSubscribe"
+    }
+
+    fn unsubscribe_method(&self) -> &'static str {
+        "accountsUnsubscribe"
+    }
+
+    fn params(&self) -> Result<ObjectParams, RpcError> {
+        self.build_params()
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AccountFilter {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) accounts: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) owners: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) commitment: Option<CommitmentLevel>,
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpsee::core::traits::ToRpcParams;
+
+    use super::*;
+
+    fn params_json(method: &TransactionMethod) -> serde_json::Value {
+        let params = method.build_params().unwrap().to_rpc_params().unwrap();
+        serde_json::from_str(params.unwrap().get()).unwrap()
+    }
+
+    #[test]
+    fn test_filter_builder_round_trip() {
+        let [a, b, c] = [(); 3].map(|_| Pubkey::new_unique());
+        let filter = TransactionFilter::builder()
+            .exclude_votes(true)
+            .commitment(CommitmentLevel::Confirmed)
+            .one_of([a])
+            .one_of([b, a])
+            .exclude([c])
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            filter.account_keys().unwrap().one_of(),
+            [a.to_string(), b.to_string()]
+        );
+        assert!(filter.account_keys().unwrap().all().is_empty());
+
+        let method = Method::new_transaction_subscription().filter(filter.clone());
+        let params = params_json(&method);
+        assert_eq!(
+            params["filter"],
+            serde_json::json!({
+                "excludeVotes": true,
+                "accountKeys": {
+                    "exclude": [c.to_string()],
+                    "oneOf": [a.to_string(), b.to_string()]
+                },
+                "commitment": "confirmed"
+            })
+        );
+
+        let parsed: TransactionFilter = serde_json::from_value(params["filter"].clone()).unwrap();
+        assert_eq!(parsed, filter);
+        assert_eq!(parsed.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_empty_filter_round_trip() {
+        let filter = TransactionFilter::builder().build().unwrap();
+        assert_eq!(filter, TransactionFilter::default());
+
+        let params = params_json(&Method::new_transaction_subscription().filter(filter.clone()));
+        assert_eq!(params["filter"], serde_json::json!({}));
+        assert_eq!(
+            serde_json::from_value::<TransactionFilter>(params["filter"].clone()).unwrap(),
+            filter
+        );
+    }
+
+    #[test]
+    fn test_filter_validation() {
+        let key = Pubkey::new_unique();
+        assert_eq!(
+            TransactionFilter::builder()
+                .all([key])
+                .exclude([key])
+                .build(),
+            Err(FilterError::Conflict(key.to_string()))
+        );
+
+        let unchecked = Method::new_transaction_subscription().all_account_keys(&["not-a-key"]);
+        assert_eq!(
+            unchecked.filter.validate(),
+            Err(FilterError::InvalidPubkey("not-a-key".to_string()))
+        );
+        assert!(unchecked.build_params().is_err());
+    }
+
+    #[test]
+    fn test_account_params() {
+        let owner = Pubkey::new_unique();
+        let method = Method::new_account_subscription()
+            .owners(&[owner])
+            .commitment_level(CommitmentLevel::Processed);
+        let params = method.build_params().unwrap().to_rpc_params().unwrap();
+        let params: serde_json::Value = serde_json::from_str(params.unwrap().get()).unwrap();
+        assert_eq!(
+            params["filter"],
+            serde_json::json!({ "owners": [owner.to_string()], "commitment": "processed" })
+        );
+
+        assert!(Method::new_account_subscription().build_params().is_err());
+    }
+}
@@ -0,0 +1,900 @@
+//! In-process mock of the ChainStream API for offline tests.
+//!
+//! [`MockServer`] runs a local jsonrpsee websocket server that speaks `transactionsSubscribe`,
+//! `blocksSubscribe`, `slotUpdatesSubscribe` and `accountsSubscribe`. It checks the
+//! `X-Syndica-Api-Token` header and the shape of the subscription params this crate sends,
+//! replays scripted fixtures to every new subscriber and can push further notifications or drop
+//! every connection on demand. Its checks are an approximation written for these tests, not a
+//! model of the real API's validation; `accountsSubscribe` in particular follows the
+//! experimental [`AccountMethod`](super::methods::AccountMethod).
+//!
+//! Only compiled for tests or with the `mock-server` feature.
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use jsonrpsee::{
+    core::SubscriptionResult,
+    server::{HttpRequest, Server, ServerHandle, SubscriptionMessage, SubscriptionSink},
+    types::{ErrorObject, ErrorObjectOwned, Params},
+    Extensions, RpcModule,
+};
+use serde::Serialize;
+use tokio::sync::{broadcast, Notify};
+
+use super::{
+    config::Endpoint,
+    methods::{AccountFilter, Network, TransactionFilter},
+    types::{
+        account::AccountUpdate, block::BlockUpdate, slot::SlotUpdate, transaction::TransactionWrite,
+    },
+};
+
+const API_TOKEN_HEADER: &str = "X-Syndica-Api-Token";
+
+/// JSON-RPC error code returned for a missing or wrong API token.
+pub const UNAUTHORIZED: i32 = -32001;
+/// JSON-RPC error code returned for malformed subscription params.
+pub const INVALID_PARAMS: i32 = -32602;
+
+/// The API token sent by a connection, copied out of the handshake headers.
+#[derive(Debug, Clone)]
+struct ApiToken(Option<String>);
+
+/// A notification that can be pushed to live subscribers with [`MockServer::send`].
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone)]
+pub enum Notification {
+    Transaction(TransactionWrite),
+    Block(BlockUpdate),
+    Slot(SlotUpdate),
+    Account(AccountUpdate),
+}
+
+/// A subscribe call received by the server.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubscribeRequest {
+    pub method: String,
+    pub params: serde_json::Value,
+}
+
+#[derive(Debug)]
+struct State {
+    token: Option<String>,
+    transactions: Vec<TransactionWrite>,
+    blocks: Vec<BlockUpdate>,
+    slots: Vec<SlotUpdate>,
+    accounts: Vec<AccountUpdate>,
+    live: broadcast::Sender<Notification>,
+    requests: Mutex<Vec<SubscribeRequest>>,
+    /// Woken when a subscribe call arrives and when a subscription opens or closes.
+    changed: Notify,
+}
+
+#[derive(Debug, Default)]
+pub struct MockServerBuilder {
+    token: Option<String>,
+    transactions: Vec<TransactionWrite>,
+    blocks: Vec<BlockUpdate>,
+    slots: Vec<SlotUpdate>,
+    accounts: Vec<AccountUpdate>,
+}
+
+impl MockServerBuilder {
+    /// Require every connection to send this API token. Without it any token is accepted.
+    pub fn token(self, token: &str) -> Self {
+        Self {
+            token: Some(token.to_string()),
+            ..self
+        }
+    }
+
+    /// Transactions replayed to every transaction subscriber whose filter matches them.
+    pub fn transactions(self, transactions: Vec<TransactionWrite>) -> Self {
+        Self {
+            transactions,
+            ..self
+        }
+    }
+
+    /// Block updates replayed to every block subscriber.
+    pub fn blocks(self, blocks: Vec<BlockUpdate>) -> Self {
+        Self { blocks, ..self }
+    }
+
+    /// Slot updates replayed to every slot subscriber.
+    pub fn slots(self, slots: Vec<SlotUpdate>) -> Self {
+        Self { slots, ..self }
+    }
+
+    /// Account updates replayed to every account subscriber whose filter matches them.
+    pub fn accounts(self, accounts: Vec<AccountUpdate>) -> Self {
+        Self { accounts, ..self }
+    }
+
+    pub async fn start(self) -> anyhow::Result<MockServer> {
+        let (live, _) = broadcast::channel(1024);
+        let state = Arc::new(State {
+            token: self.token,
+            transactions: self.transactions,
+            blocks: self.blocks,
+            slots: self.slots,
+            accounts: self.accounts,
+            live,
+            requests: Mutex::new(Vec::new()),
+            changed: Notify::new(),
+        });
+
+        let (addr, handle) = serve("127.0.0.1:0".parse()?, state.clone()).await?;
+        Ok(MockServer {
+            addr,
+            handle,
+            state,
+        })
+    }
+}
+
+/// A running mock server. It is stopped when dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    handle: ServerHandle,
+    state: Arc<State>,
+}
+
+impl MockServer {
+    pub fn builder() -> MockServerBuilder {
+        MockServerBuilder::default()
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The endpoint to pass to [`ClientBuilder::endpoint`](super::client::ClientBuilder::endpoint).
+    pub fn endpoint(&self) -> Endpoint {
+        Endpoint::Url(format!("ws://{}", self.addr))
+    }
+
+    /// Every subscribe call received so far, including rejected ones.
+    pub fn requests(&self) -> Vec<SubscribeRequest> {
+        self.state.requests.lock().unwrap().clone()
+    }
+
+    /// Push a notification to every live subscriber of the matching kind. Transactions are only
+    /// sent to subscribers whose filter matches. Returns the number of subscriptions reached.
+    pub fn send(&self, notification: Notification) -> usize {
+        self.state.live.send(notification).unwrap_or(0)
+    }
+
+    /// Number of subscriptions currently open on the server, of any kind.
+    pub fn active_subscriptions(&self) -> usize {
+        self.state.live.receiver_count()
+    }
+
+    /// Waits until at least `count` subscribe calls were received.
+    pub async fn wait_for_requests(&self, count: usize) {
+        self.wait_until(|server| server.requests().len() >= count)
+            .await
+    }
+
+    /// Waits until exactly `count` subscriptions are open.
+    pub async fn wait_for_active_subscriptions(&self, count: usize) {
+        self.wait_until(|server| server.active_subscriptions() == count)
+            .await
+    }
+
+    async fn wait_until(&self, condition: impl Fn(&Self) -> bool) {
+        loop {
+            let changed = self.state.changed.notified();
+            tokio::pin!(changed);
+            changed.as_mut().enable();
+            if condition(self) {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    /// Close every open connection, then accept new ones on the same address again.
+    pub async fn drop_connections(&mut self) -> anyhow::Result<()> {
+        self.handle.stop()?;
+        self.handle.clone().stopped().await;
+
+        let (_, handle) = serve(self.addr, self.state.clone()).await?;
+        self.handle = handle;
+        Ok(())
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        let _ = self.handle.stop();
+    }
+}
+
+async fn serve(addr: SocketAddr, state: Arc<State>) -> anyhow::Result<(SocketAddr, ServerHandle)> {
+    let http_middleware = tower::ServiceBuilder::new().map_request(|mut request: HttpRequest| {
+        let token = request
+            .headers()
+            .get(API_TOKEN_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        request.extensions_mut().insert(ApiToken(token));
+        request
+    });
+
+    let server = Server::builder()
+        .set_http_middleware(http_middleware)
+        .build(addr)
+        .await?;
+
+    let mut module = RpcModule::from_arc(state);
+    module.register_subscription(
+        "transactionsSubscribe",
+        "transactionsNotification",
+        "transactionsUnsubscribe",
+        |params, pending, state, extensions| async move {
+            let filter = match check(&state, &extensions, "transactionsSubscribe", &params) {
+                Ok(params) => params.filter,
+                Err(error) => {
+                    pending.reject(error).await;
+                    return Ok(());
+                }
+            };
+
+            let live = Live::new(&state);
+            let sink = pending.accept().await?;
+            let replay: Vec<_> = state
+                .transactions
+                .iter()
+                .filter(|tx| filter_matches(&filter, tx))
+                .cloned()
+                .collect();
+            stream(&sink, live, replay, |n| match n {
+                Notification::Transaction(tx) if filter_matches(&filter, &tx) => Some(tx),
+                _ => None,
+            })
+            .await
+        },
+    )?;
+    module.register_subscription(
+        "blocksSubscribe",
+        "blocksNotification",
+        "blocksUnsubscribe",
+        |params, pending, state, extensions| async move {
+            if let Err(error) = check(&state, &extensions, "blocksSubscribe", &params) {
+                pending.reject(error).await;
+                return Ok(());
+            }
+
+            let live = Live::new(&state);
+            let sink = pending.accept().await?;
+            stream(&sink, live, state.blocks.clone(), |n| match n {
+                Notification::Block(block) => Some(block),
+                _ => None,
+            })
+            .await
+        },
+    )?;
+    module.register_subscription(
+        "slotUpdatesSubscribe",
+        "slotUpdatesNotification",
+        "slotUpdatesUnsubscribe",
+        |params, pending, state, extensions| async move {
+            if let Err(error) = check(&state, &extensions, "slotUpdatesSubscribe", &params) {
+                pending.reject(error).await;
+                return Ok(());
+            }
+
+            let live = Live::new(&state);
+            let sink = pending.accept().await?;
+            stream(&sink, live, state.slots.clone(), |n| match n {
+                Notification::Slot(slot) => Some(slot),
+                _ => None,
+            })
+            .await
+        },
+    )?;
+
+    module.register_subscription(
+        "accountsSubscribe",
+        "accountsNotification",
+        "accountsUnsubscribe",
+        |params, pending, state, extensions| async move {
+            let filter = match check(&state, &extensions, "accountsSubscribe", &params) {
+                Ok(params) => params.account_filter,
+                Err(error) => {
+                    pending.reject(error).await;
+                    return Ok(());
+                }
+            };
+
+            let live = Live::new(&state);
+            let sink = pending.accept().await?;
+            let replay: Vec<_> = state
+                .accounts
+                .iter()
+                .filter(|update| account_filter_matches(&filter, update))
+                .cloned()
+                .collect();
+            stream(&sink, live, replay, |n| match n {
+                Notification::Account(update) if account_filter_matches(&filter, &update) => {
+                    Some(update)
+                }
+                _ => None,
+            })
+            .await
+        },
+    )?;
+
+    let addr = server.local_addr()?;
+    Ok((addr, server.start(module)))
+}
+
+/// The parts of the subscription params that change what the server sends.
+#[derive(Debug, Default)]
+struct SubscribeParams {
+    filter: TransactionFilter,
+    account_filter: AccountFilter,
+}
+
+/// Records the subscribe call, then checks the API token and the params.
+fn check(
+    state: &State,
+    extensions: &Extensions,
+    method: &str,
+    params: &Params<'_>,
+) -> Result<SubscribeParams, ErrorObjectOwned> {
+    let value = params.parse::<serde_json::Value>().unwrap_or_default();
+    state.requests.lock().unwrap().push(SubscribeRequest {
+        method: method.to_string(),
+        params: value.clone(),
+    });
+    state.changed.notify_waiters();
+
+    if let Some(expected) = &state.token {
+        let token = extensions.get::<ApiToken>().and_then(|t| t.0.as_deref());
+        if token != Some(expected.as_str()) {
+            return Err(ErrorObject::owned(UNAUTHORIZED, "Unauthorized", None::<()>));
+        }
+    }
+
+    validate_params(&value, method)
+        .map_err(|reason| ErrorObject::owned(INVALID_PARAMS, reason, None::<()>))
+}
+
+/// Checks the params object for a known `network`, a boolean `verified` and only the params
+/// specific to `method`, e.g. a transaction filter with only the fields this crate sends.
+fn validate_params(params: &serde_json::Value, method: &str) -> Result<SubscribeParams, String> {
+    let extra_params: &[&str] = match method {
+        "transactionsSubscribe" | "accountsSubscribe" => &["filter"],
+        _ => &[],
+    };
+
+    let object = params
+        .as_object()
+        .ok_or_else(|| "params must be an object".to_string())?;
+
+    for key in object.keys() {
+        let known = key == "network" || key == "verified" || extra_params.contains(&key.as_str());
+        if !known {
+            return Err(format!("unknown param: {key}"));
+        }
+    }
+
+    let network = object.get("network").and_then(|n| n.as_str());
+    let networks = [Network::SolanaMainnet, Network::SolanaTestnet];
+    if !networks.iter().any(|n| Some(n.as_str()) == network) {
+        return Err(format!("invalid network: {network:?}"));
+    }
+
+    if object.get("verified").is_some_and(|v| !v.is_boolean()) {
+        return Err("verified must be a boolean".to_string());
+    }
+
+    let filter = match object.get("filter") {
+        Some(filter) if method == "transactionsSubscribe" => {
+            serde_json::from_value(filter.clone()).map_err(|e| format!("invalid filter: {e}"))?
+        }
+        _ => TransactionFilter::default(),
+    };
+
+    let account_filter: AccountFilter = match object.get("filter") {
+        Some(filter) if method == "accountsSubscribe" => {
+            serde_json::from_value(filter.clone()).map_err(|e| format!("invalid filter: {e}"))?
+        }
+        _ => AccountFilter::default(),
+    };
+    // Mirrors `AccountMethod::build_params`, not a documented server rule.
+    if method == "accountsSubscribe"
+        && account_filter.accounts.is_none()
+        && account_filter.owners.is_none()
+    {
+        return Err("filter needs accounts or owners".to_string());
+    }
+
+    Ok(SubscribeParams {
+        filter,
+        account_filter,
+    })
+}
+
+/// The live notifications of one open subscription. Opening and closing it wakes
+/// [`MockServer::wait_until`].
+struct Live {
+    receiver: Option<broadcast::Receiver<Notification>>,
+    state: Arc<State>,
+}
+
+impl Live {
+    fn new(state: &Arc<State>) -> Self {
+        let receiver = state.live.subscribe();
+        state.changed.notify_waiters();
+        Self {
+            receiver: Some(receiver),
+            state: state.clone(),
+        }
+    }
+
+    async fn recv(&mut self) -> Result<Notification, broadcast::error::RecvError> {
+        self.receiver.as_mut().unwrap().recv().await
+    }
+}
+
+impl Drop for Live {
+    fn drop(&mut self) {
+        // Drop the receiver first so waiters see the new subscription count.
+        self.receiver.take();
+        self.state.changed.notify_waiters();
+    }
+}
+
+/// Sends the replayed notifications, then forwards live ones until the client unsubscribes.
+async fn stream<T, I, F>(
+    sink: &SubscriptionSink,
+    mut live: Live,
+    replay: I,
+    select: F,
+) -> SubscriptionResult
+where
+    T: Serialize,
+    I: IntoIterator<Item = T>,
+    F: Fn(Notification) -> Option<T>,
+{
+    for notification in replay {
+        sink.send(SubscriptionMessage::from_json(&notification)?)
+            .await?;
+    }
+
+    loop {
+        tokio::select! {
+            _ = sink.closed() => return Ok(()),
+            notification = live.recv() => match notification {
+                Ok(notification) => {
+                    if let Some(notification) = select(notification) {
+                        sink.send(SubscriptionMessage::from_json(&notification)?).await?;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            },
+        }
+    }
+}
+
+/// Server-side filter semantics: votes are dropped if requested, and the transaction's static
+/// and loaded account keys must satisfy every account key selector that is set.
+fn filter_matches(filter: &TransactionFilter, tx: &TransactionWrite) -> bool {
+    if filter.exclude_votes == Some(true) && tx.context.is_vote {
+        return false;
+    }
+
+    let Some(selector) = &filter.account_keys else {
+        return true;
+    };
+
+    let mut keys: Vec<&str> = Vec::new();
+    if let Some(message) = tx
+        .value
+        .transaction
+        .as_ref()
+        .and_then(|t| t.message.as_ref())
+    {
+        keys.extend(message.account_keys.iter().map(String::as_str));
+    }
+    if let Some(loaded) = tx
+        .value
+        .meta
+        .as_ref()
+        .and_then(|m| m.loaded_addresses.as_ref())
+    {
+        keys.extend(loaded.writable.iter().map(String::as_str));
+        keys.extend(loaded.readonly.iter().map(String::as_str));
+    }
+    let contains = |key: &String| keys.contains(&key.as_str());
+
+    let all = selector
+        .all
+        .as_ref()
+        .is_none_or(|all| all.iter().all(contains));
+    let one_of = selector
+        .one_of
+        .as_ref()
+        .is_none_or(|one_of| one_of.iter().any(contains));
+    let exclude = selector
+        .exclude
+        .as_ref()
+        .is_none_or(|exclude| !exclude.iter().any(contains));
+    all && one_of && exclude
+}
+
+/// An account update matches if its pubkey or its owner is listed.
+fn account_filter_matches(filter: &AccountFilter, update: &AccountUpdate) -> bool {
+    let listed = |keys: &Option<Vec<String>>, key: &str| {
+        keys.as_ref()
+            .is_some_and(|keys| keys.iter().any(|k| k == key))
+    };
+    listed(&filter.accounts, &update.value.pubkey) || listed(&filter.owners, &update.value.owner)
+}
+
+/// Helpers to build notifications for scripting a [`MockServer`].
+pub mod fixtures {
+    use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+    use crate::chainstream::types::{
+        account, slot,
+        transaction::{Body, Context, Message, Meta, Transaction, TransactionWrite},
+        SlotStatus,
+    };
+
+    /// A deterministic, valid base58 signature derived from `seed`.
+    pub fn signature(seed: u8) -> String {
+        Signature::from([seed; 64]).to_string()
+    }
+
+    /// A deterministic, valid base58 pubkey derived from `seed`.
+    pub fn pubkey(seed: u8) -> String {
+        Pubkey::new_from_array([seed; 32]).to_string()
+    }
+
+    /// A successful, non-vote transaction touching `account_keys` and emitting `logs`.
+    pub fn transaction(
+        slot: u64,
+        signature: String,
+        account_keys: &[&str],
+        logs: Vec<String>,
+    ) -> TransactionWrite {
+        TransactionWrite {
+            context: Context {
+                slot_status: SlotStatus::Confirmed,
+                node_time: None,
+                is_vote: false,
+                signature: signature.clone(),
+                index: Some(0),
+            },
+            value: Transaction {
+                block_time: None,
+                meta: Some(Meta {
+                    err: None,
+                    fee: 5000,
+                    inner_instructions: vec![],
+                    loaded_addresses: None,
+                    log_messages: logs,
+                    post_balances: vec![],
+                    post_token_balances: vec![],
+                    pre_balances: vec![],
+                    pre_token_balances: vec![],
+                    rewards: vec![],
+                    status: None,
+                }),
+                slot,
+                transaction: Some(Body {
+                    message: Some(Message {
+                        account_keys: account_keys.iter().map(|k| k.to_string()).collect(),
+                        address_table_lookups: vec![],
+                        header: None,
+                        instructions: vec![],
+                        recent_blockhash: String::new(),
+                    }),
+                    message_hash: String::new(),
+                    signatures: vec![signature],
+                }),
+            },
+        }
+    }
+
+    /// A write of `data` to `pubkey`, owned by `owner`.
+    pub fn account_update(
+        slot: u64,
+        pubkey: &str,
+        owner: &str,
+        data: Vec<u8>,
+    ) -> account::AccountUpdate {
+        account::AccountUpdate {
+            context: None,
+            value: account::Value {
+                slot,
+                pubkey: pubkey.to_string(),
+                lamports: 1_000_000,
+                owner: owner.to_string(),
+                data,
+                executable: false,
+                rent_epoch: u64::MAX,
+                write_version: slot,
+                txn_signature: None,
+            },
+        }
+    }
+
+    pub fn slot_update(slot: u64, parent: Option<u64>, status: SlotStatus) -> slot::SlotUpdate {
+        slot::SlotUpdate {
+            context: None,
+            value: Some(slot::Value {
+                slot,
+                parent,
+                status,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use anchor_lang::Event;
+    use jsonrpsee::core::traits::ToRpcParams;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::{
+        chainstream::{
+            client::ClientBuilder, methods::Method, reconnect::StreamEvent, types::SlotStatus,
+        },
+        raydium::{
+            anchor_events::{RaydiumCLMMEvent, SwapEvent},
+            parse::parse_raydium_anchor_events,
+        },
+    };
+
+    const RAYDIUM_CLMM_PROGRAM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+    const TOKEN: &str = "test-token";
+
+    fn swap_logs(event: &SwapEvent) -> Vec<String> {
+        use base64::Engine;
+        vec![
+            format!("Program {RAYDIUM_CLMM_PROGRAM} invoke [1]"),
+            "Program log: Instruction: Swap".to_string(),
+            format!(
+                "Program data: {}",
+                base64::engine::general_purpose::STANDARD.encode(event.data())
+            ),
+            format!("Program {RAYDIUM_CLMM_PROGRAM} consumed 50000 of 200000 compute units"),
+            format!("Program {RAYDIUM_CLMM_PROGRAM} success"),
+        ]
+    }
+
+    fn swap_event(amount_0: u64) -> SwapEvent {
+        SwapEvent {
+            pool_state: Default::default(),
+            sender: Default::default(),
+            token_account_0: Default::default(),
+            token_account_1: Default::default(),
+            amount_0,
+            transfer_fee_0: 0,
+            amount_1: 42,
+            transfer_fee_1: 0,
+            zero_for_one: true,
+            sqrt_price_x64: 1 << 64,
+            liquidity: 1_000_000,
+            tick: 0,
+        }
+    }
+
+    async fn client(server: &MockServer) -> crate::chainstream::client::ChainStreamClient {
+        ClientBuilder::new()
+            .token(TOKEN)
+            .endpoint(server.endpoint())
+            .build()
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_replays_matching_transactions() {
+        let other = Pubkey::new_unique().to_string();
+        let server = MockServer::builder()
+            .token(TOKEN)
+            .transactions(vec![
+                fixtures::transaction(1, fixtures::signature(1), &[&other], vec![]),
+                fixtures::transaction(
+                    2,
+                    fixtures::signature(2),
+                    &[RAYDIUM_CLMM_PROGRAM],
+                    swap_logs(&swap_event(7)),
+                ),
+            ])
+            .start()
+            .await
+            .unwrap();
+
+        let method = Method::new_transaction_subscription()
+            .one_of_account_keys(&[RAYDIUM_CLMM_PROGRAM])
+            .exclude_votes(true);
+        let mut subscription = client(&server).await.subscribe(method).await.unwrap();
+
+        let transaction = subscription.next().await.unwrap().unwrap();
+        assert_eq!(transaction.value.slot, 2);
+        assert_eq!(transaction.context.signature, fixtures::signature(2));
+
+        let events = parse_raydium_anchor_events(&transaction).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].location.slot, 2);
+        match &events[0].event {
+            RaydiumCLMMEvent::Swap(swap) => {
+                assert_eq!(swap.amount_0, 7);
+                assert_eq!(swap.amount_1, 42);
+            }
+            other => panic!("unexpected event: {other:?}"),
+        }
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "transactionsSubscribe");
+        assert_eq!(
+            requests[0].params["filter"],
+            serde_json::json!({
+                "excludeVotes": true,
+                "accountKeys": { "oneOf": [RAYDIUM_CLMM_PROGRAM] }
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pushes_live_notifications() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = client(&server).await;
+
+        let mut slots = client
+            .subscribe(Method::new_slot_subscription())
+            .await
+            .unwrap();
+        let mut transactions = client
+            .subscribe(
+                Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]),
+            )
+            .await
+            .unwrap();
+
+        let slot = fixtures::slot_update(10, Some(9), SlotStatus::Confirmed);
+        assert_eq!(server.send(Notification::Slot(slot)), 2);
+        let received = slots.next().await.unwrap().unwrap();
+        assert_eq!(received.value.unwrap().slot, 10);
+
+        // Filtered out server-side, then a matching one.
+        let (a, b) = (fixtures::pubkey(1), fixtures::pubkey(2));
+        let skipped = fixtures::transaction(1, fixtures::signature(1), &[&b], vec![]);
+        let matching = fixtures::transaction(2, fixtures::signature(2), &[&a], vec![]);
+        server.send(Notification::Transaction(skipped));
+        server.send(Notification::Transaction(matching));
+        let received = transactions.next().await.unwrap().unwrap();
+        assert_eq!(received.value.slot, 2);
+    }
+
+    #[tokio::test]
+    async fn test_account_subscription() {
+        let pool = Pubkey::new_unique().to_string();
+        let server = MockServer::builder()
+            .accounts(vec![
+                fixtures::account_update(3, &pool, RAYDIUM_CLMM_PROGRAM, vec![1, 2, 3]),
+                fixtures::account_update(4, &pool, "11111111111111111111111111111111", vec![]),
+            ])
+            .start()
+            .await
+            .unwrap();
+        let client = client(&server).await;
+
+        let owner = Pubkey::from_str_const(RAYDIUM_CLMM_PROGRAM);
+        let mut accounts = client
+            .subscribe(Method::new_account_subscription().owners(&[owner]))
+            .await
+            .unwrap();
+        let update = accounts.next().await.unwrap().unwrap();
+        assert_eq!(update.value.slot, 3);
+        assert_eq!(update.value.data, [1, 2, 3]);
+
+        server.send(Notification::Account(fixtures::account_update(
+            5,
+            &pool,
+            RAYDIUM_CLMM_PROGRAM,
+            vec![9],
+        )));
+        assert_eq!(accounts.next().await.unwrap().unwrap().value.slot, 5);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_wrong_token() {
+        let server = MockServer::builder().token(TOKEN).start().await.unwrap();
+        let client = ClientBuilder::new()
+            .token("wrong")
+            .endpoint(server.endpoint())
+            .build()
+            .await
+            .unwrap();
+
+        let result = client.subscribe(Method::new_block_subscription()).await;
+        assert!(result.unwrap_err().to_string().contains("Unauthorized"));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_validate_params() {
+        let params = Method::new_transaction_subscription()
+            .all_account_keys(&[fixtures::pubkey(1), fixtures::pubkey(2)])
+            .build_params()
+            .unwrap()
+            .to_rpc_params()
+            .unwrap()
+            .unwrap();
+        let value: serde_json::Value = serde_json::from_str(params.get()).unwrap();
+        let valid = validate_params(&value, "transactionsSubscribe").unwrap();
+        assert!(valid.filter.account_keys.is_some());
+
+        let bad_network = serde_json::json!({ "network": "ethereum", "verified": false });
+        assert!(validate_params(&bad_network, "slotUpdatesSubscribe").is_err());
+
+        let unknown_filter = serde_json::json!({
+            "network": "solana-mainnet",
+            "verified": false,
+            "filter": { "programs": ["A"] }
+        });
+        assert!(validate_params(&unknown_filter, "transactionsSubscribe").is_err());
+
+        let filter_on_slots = serde_json::json!({
+            "network": "solana-mainnet",
+            "filter": {}
+        });
+        assert!(validate_params(&filter_on_slots, "slotUpdatesSubscribe").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_drop_connections_triggers_reconnect() {
+        let mut server = MockServer::builder().start().await.unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .reconnect(
+                crate::chainstream::reconnect::ReconnectConfig::default()
+                    .initial_backoff(Duration::from_millis(10))
+                    .jitter(0.0),
+            )
+            .build()
+            .await
+            .unwrap();
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        server.drop_connections().await.unwrap();
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Reconnected { .. }))
+        ));
+
+        // Wait for the new subscription to be registered before pushing to it.
+        tokio::time::timeout(Duration::from_secs(5), server.wait_for_requests(2))
+            .await
+            .unwrap();
+        server.send(Notification::Slot(fixtures::slot_update(
+            5,
+            None,
+            SlotStatus::Processed,
+        )));
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Notification(_)))
+        ));
+    }
+}
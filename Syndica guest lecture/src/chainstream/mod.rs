@@ -1,3 +1,15 @@
+pub mod balances;
 pub mod client;
+pub mod commitment;
+pub mod config;
+pub mod convert;
+pub mod manager;
 pub mod methods;
+#[cfg(any(test, feature = "mock-server"))]
+pub mod mock;
+pub mod ordered;
+pub mod reconnect;
+pub mod recorder;
+pub mod resolve;
+pub mod subscription;
 pub mod types;
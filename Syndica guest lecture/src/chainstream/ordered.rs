@@ -0,0 +1,335 @@
+//! Slot-ordered, deduplicated transaction stream.
+//!
+//! ChainStream delivers transactions as soon as a node sees them, so notifications can arrive out
+//! of slot order, and the same transaction can be delivered again after a reconnect or a
+//! commitment upgrade. [`OrderedSubscription`] holds every slot back for a configurable delay
+//! after its first transaction arrives, then releases the slots in order, each sorted by
+//! `Context.index` and followed by an [`OrderedEvent::SlotComplete`] boundary.
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+
+use super::{
+    subscription::{ChainStreamSubscription, SubscriptionError},
+    types::transaction::TransactionWrite,
+};
+
+#[derive(Debug, Clone)]
+pub struct OrderingConfig {
+    /// How long a slot is held back after its first transaction arrived, waiting for stragglers.
+    pub delay: Duration,
+    /// Number of recent signatures remembered to drop duplicates.
+    pub dedup_window: usize,
+}
+
+impl Default for OrderingConfig {
+    fn default() -> Self {
+        Self {
+            delay: Duration::from_millis(800),
+            dedup_window: 100_000,
+        }
+    }
+}
+
+impl OrderingConfig {
+    #[allow(unused)]
+    pub fn delay(self, delay: Duration) -> Self {
+        Self { delay, ..self }
+    }
+
+    #[allow(unused)]
+    pub fn dedup_window(self, dedup_window: usize) -> Self {
+        Self {
+            dedup_window,
+            ..self
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum OrderedEvent {
+    Transaction(TransactionWrite),
+    /// Every transaction of this slot that arrived in time has been emitted.
+    SlotComplete(u64),
+    /// A transaction for a slot that was already completed. It is not part of the ordered
+    /// stream; consumers that need strict ordering should treat it as a gap.
+    Late(TransactionWrite),
+}
+
+/// Transactions of one slot waiting to be released.
+#[derive(Debug)]
+struct PendingSlot {
+    deadline: Instant,
+    /// Keyed by index, with transactions lacking one sorted last.
+    transactions: BTreeMap<(u64, String), TransactionWrite>,
+}
+
+/// The ordering logic of [`OrderedSubscription`], driven by explicit timestamps.
+#[derive(Debug)]
+struct Reorderer {
+    config: OrderingConfig,
+    seen: HashSet<String>,
+    seen_order: VecDeque<String>,
+    pending: BTreeMap<u64, PendingSlot>,
+    ready: VecDeque<OrderedEvent>,
+    /// Highest slot that was completed.
+    completed: Option<u64>,
+    duplicates: u64,
+}
+
+impl Reorderer {
+    fn new(config: OrderingConfig) -> Self {
+        Self {
+            config,
+            seen: HashSet::new(),
+            seen_order: VecDeque::new(),
+            pending: BTreeMap::new(),
+            ready: VecDeque::new(),
+            completed: None,
+            duplicates: 0,
+        }
+    }
+
+    fn push(&mut self, transaction: TransactionWrite, now: Instant) {
+        let signature = transaction.context.signature.clone();
+        if !self.remember(&signature) {
+            self.duplicates += 1;
+            return;
+        }
+
+        let slot = transaction.value.slot;
+        if self.completed.is_some_and(|completed| slot <= completed) {
+            self.ready.push_back(OrderedEvent::Late(transaction));
+            return;
+        }
+
+        let delay = self.config.delay;
+        let index = transaction.context.index.unwrap_or(u64::MAX);
+        self.pending
+            .entry(slot)
+            .or_insert_with(|| PendingSlot {
+                deadline: now + delay,
+                transactions: BTreeMap::new(),
+            })
+            .transactions
+            .insert((index, signature), transaction);
+    }
+
+    /// Returns `false` if `signature` is already in the window.
+    fn remember(&mut self, signature: &str) -> bool {
+        if self.config.dedup_window == 0 {
+            return true;
+        }
+        if !self.seen.insert(signature.to_string()) {
+            return false;
+        }
+        self.seen_order.push_back(signature.to_string());
+        while self.seen_order.len() > self.config.dedup_window {
+            if let Some(oldest) = self.seen_order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Returns the next event that is due at `now`, or, with `flush`, any remaining event.
+    fn pop(&mut self, now: Instant, flush: bool) -> Option<OrderedEvent> {
+        if self.ready.is_empty() {
+            // Slots are released strictly in order, so a lower slot that is still waiting holds
+            // back higher ones even if their own delay has passed.
+            let entry = self.pending.first_entry()?;
+            if !flush && entry.get().deadline > now {
+                return None;
+            }
+            let (slot, pending) = entry.remove_entry();
+            self.ready.extend(
+                pending
+                    .transactions
+                    .into_values()
+                    .map(OrderedEvent::Transaction),
+            );
+            self.ready.push_back(OrderedEvent::SlotComplete(slot));
+            self.completed = Some(slot);
+        }
+        self.ready.pop_front()
+    }
+
+    fn next_deadline(&self) -> Option<Instant> {
+        self.pending.first_key_value().map(|(_, p)| p.deadline)
+    }
+}
+
+/// Wraps a transaction subscription and yields its notifications deduplicated and in slot order,
+/// see the [module docs](self).
+#[derive(Debug)]
+pub struct OrderedSubscription {
+    subscription: ChainStreamSubscription<TransactionWrite>,
+    reorderer: Reorderer,
+    ended: bool,
+}
+
+impl OrderedSubscription {
+    pub fn new(
+        subscription: ChainStreamSubscription<TransactionWrite>,
+        config: OrderingConfig,
+    ) -> Self {
+        Self {
+            subscription,
+            reorderer: Reorderer::new(config),
+            ended: false,
+        }
+    }
+
+    /// Returns the next event, or `None` once the subscription has ended and everything still
+    /// held back has been released.
+    pub async fn next(&mut self) -> Option<Result<OrderedEvent, SubscriptionError>> {
+        loop {
+            if let Some(event) = self.reorderer.pop(Instant::now(), self.ended) {
+                return Some(Ok(event));
+            }
+            if self.ended {
+                return None;
+            }
+
+            let deadline = self.reorderer.next_deadline();
+            tokio::select! {
+                notification = self.subscription.next() => match notification {
+                    Some(Ok(transaction)) => self.reorderer.push(transaction, Instant::now()),
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => self.ended = true,
+                },
+                _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                    if deadline.is_some() => {}
+            }
+        }
+    }
+
+    /// Number of notifications dropped as duplicates so far.
+    pub fn duplicates(&self) -> u64 {
+        self.reorderer.duplicates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chainstream::{
+        client::ClientBuilder,
+        methods::Method,
+        mock::{fixtures, MockServer},
+    };
+
+    fn transaction(slot: u64, index: u64, seed: u8) -> TransactionWrite {
+        let mut transaction =
+            fixtures::transaction(slot, fixtures::signature(seed), &["A"], vec![]);
+        transaction.context.index = Some(index);
+        transaction
+    }
+
+    fn drain(reorderer: &mut Reorderer, now: Instant) -> Vec<String> {
+        std::iter::from_fn(|| reorderer.pop(now, false))
+            .map(|event| match event {
+                OrderedEvent::Transaction(tx) => {
+                    format!("{}/{}", tx.value.slot, tx.context.index.unwrap())
+                }
+                OrderedEvent::SlotComplete(slot) => format!("done {slot}"),
+                OrderedEvent::Late(tx) => format!("late {}", tx.value.slot),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reorders_within_delay() {
+        let mut reorderer = Reorderer::new(OrderingConfig::default().delay(Duration::from_secs(1)));
+        let start = Instant::now();
+
+        reorderer.push(transaction(11, 0, 1), start);
+        reorderer.push(transaction(10, 1, 2), start + Duration::from_millis(100));
+        reorderer.push(transaction(10, 0, 3), start + Duration::from_millis(200));
+        assert!(drain(&mut reorderer, start + Duration::from_millis(500)).is_empty());
+
+        // Slot 11 is due first, but waits for slot 10.
+        assert!(drain(&mut reorderer, start + Duration::from_millis(1050)).is_empty());
+        assert_eq!(
+            drain(&mut reorderer, start + Duration::from_millis(1100)),
+            ["10/0", "10/1", "done 10", "11/0", "done 11"]
+        );
+
+        reorderer.push(transaction(10, 2, 4), start + Duration::from_secs(2));
+        assert_eq!(
+            drain(&mut reorderer, start + Duration::from_secs(2)),
+            ["late 10"]
+        );
+    }
+
+    #[test]
+    fn test_dedupes_within_window() {
+        let mut reorderer = Reorderer::new(
+            OrderingConfig::default()
+                .delay(Duration::ZERO)
+                .dedup_window(2),
+        );
+        let now = Instant::now();
+
+        reorderer.push(transaction(1, 0, 1), now);
+        reorderer.push(transaction(1, 0, 1), now);
+        reorderer.push(transaction(1, 1, 2), now);
+        assert_eq!(reorderer.duplicates, 1);
+        assert_eq!(drain(&mut reorderer, now), ["1/0", "1/1", "done 1"]);
+
+        // Signature 1 falls out of the window once a third one is seen.
+        reorderer.push(transaction(2, 0, 3), now);
+        reorderer.push(transaction(2, 1, 1), now);
+        assert_eq!(reorderer.duplicates, 1);
+        assert_eq!(drain(&mut reorderer, now), ["2/0", "2/1", "done 2"]);
+    }
+
+    #[tokio::test]
+    async fn test_ordered_subscription() {
+        let server = MockServer::builder()
+            .transactions(vec![
+                transaction(6, 0, 1),
+                transaction(5, 1, 2),
+                transaction(6, 0, 1),
+                transaction(5, 0, 3),
+            ])
+            .start()
+            .await
+            .unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .build()
+            .await
+            .unwrap();
+        let mut ordered = client
+            .subscribe_ordered(
+                Method::new_transaction_subscription(),
+                OrderingConfig::default().delay(Duration::from_millis(50)),
+            )
+            .await
+            .unwrap();
+
+        let mut events = Vec::new();
+        for _ in 0..5 {
+            events.push(match ordered.next().await.unwrap().unwrap() {
+                OrderedEvent::Transaction(tx) => tx.context.signature,
+                OrderedEvent::SlotComplete(slot) => slot.to_string(),
+                OrderedEvent::Late(_) => panic!("unexpected late transaction"),
+            });
+        }
+        assert_eq!(
+            events,
+            [
+                fixtures::signature(3),
+                fixtures::signature(2),
+                "5".to_string(),
+                fixtures::signature(1),
+                "6".to_string(),
+            ]
+        );
+        assert_eq!(ordered.duplicates(), 1);
+    }
+}
@@ -0,0 +1,698 @@
+//! Automatic reconnect and resubscribe support for the ChainStream client.
+//!
+//! A [`ReconnectingSubscription`] wraps a [`ChainStreamSubscription`] and, when the websocket
+//! behind it drops, reconnects the shared client with exponential backoff and re-issues the
+//! original [`SubscriptionMethod`]. Consumers see one continuous stream with an explicit
+//! [`StreamEvent::Reconnected`] marker wherever notifications may have been missed.
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use jsonrpsee::{
+    core::client::SubscriptionClientT,
+    ws_client::{WsClient, WsClientBuilder},
+};
+use rand::Rng;
+use tokio::sync::RwLock;
+
+use super::{
+    client::{ClientError, RawSubscription},
+    methods::SubscriptionMethod,
+    subscription::{ChainStreamSubscription, SubscriptionError, SubscriptionStats},
+};
+
+/// Backoff policy used when the connection to ChainStream is lost.
+#[derive(Debug, Clone)]
+pub struct ReconnectConfig {
+    /// Delay before the first reconnect attempt.
+    pub initial_backoff: Duration,
+    /// Upper bound for the delay between two attempts.
+    pub max_backoff: Duration,
+    /// Factor the delay is multiplied by after every failed attempt.
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, e.g. `0.2` spreads attempts over +/- 20%.
+    pub jitter: f64,
+    /// Give up after this many consecutive failed attempts. `None` retries forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+        }
+    }
+}
+
+impl ReconnectConfig {
+    #[allow(unused)]
+    pub fn initial_backoff(self, initial_backoff: Duration) -> Self {
+        Self {
+            initial_backoff,
+            ..self
+        }
+    }
+
+    #[allow(unused)]
+    pub fn max_backoff(self, max_backoff: Duration) -> Self {
+        Self {
+            max_backoff,
+            ..self
+        }
+    }
+
+    #[allow(unused)]
+    pub fn multiplier(self, multiplier: f64) -> Self {
+        Self { multiplier, ..self }
+    }
+
+    #[allow(unused)]
+    pub fn jitter(self, jitter: f64) -> Self {
+        Self {
+            jitter: jitter.clamp(0.0, 1.0),
+            ..self
+        }
+    }
+
+    #[allow(unused)]
+    pub fn max_attempts(self, max_attempts: Option<u32>) -> Self {
+        Self {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Delay to wait before reconnect attempt number `attempt` (starting at 0).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let base = self.initial_backoff.as_secs_f64() * self.multiplier.powi(attempt as i32);
+        let capped = base.min(self.max_backoff.as_secs_f64());
+        let jittered = if self.jitter > 0.0 {
+            let spread = capped * self.jitter;
+            capped + rand::thread_rng().gen_range(-spread..=spread)
+        } else {
+            capped
+        };
+        Duration::from_secs_f64(jittered.max(0.0))
+    }
+}
+
+/// An item yielded by a [`ReconnectingSubscription`].
+#[derive(Debug, Clone)]
+pub enum StreamEvent<T> {
+    /// A notification received from the server.
+    Notification(T),
+    /// Notifications were dropped at this point because the consumer fell behind, see
+    /// [`OverflowPolicy`](super::subscription::OverflowPolicy). `missed` is `None` when the
+    /// websocket client's own buffer overflowed and closed the subscription, which does not tell
+    /// how many were lost; the subscription is re-established right after.
+    Lagged { missed: Option<u64> },
+    /// The connection was lost and the subscription has been re-established. Notifications
+    /// published while disconnected were not delivered, so a gap is possible at this point.
+    Reconnected {
+        /// Number of attempts it took to reconnect and resubscribe.
+        attempts: u32,
+        /// Time between noticing the disconnect and resubscribing.
+        downtime: Duration,
+    },
+}
+
+/// The websocket connection shared by a client and all of its subscriptions.
+///
+/// Reconnects happen under the write lock, so subscriptions that notice the same disconnect
+/// agree on a single new connection instead of racing each other.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    url: String,
+    ws_client_builder: WsClientBuilder,
+    reconnect: ReconnectConfig,
+    client: RwLock<Arc<WsClient>>,
+}
+
+impl Connection {
+    pub(crate) async fn connect(
+        url: String,
+        ws_client_builder: WsClientBuilder,
+        reconnect: ReconnectConfig,
+    ) -> Result<Self, ClientError> {
+        let client = ws_client_builder.clone().build(&url).await?;
+
+        Ok(Self {
+            url,
+            ws_client_builder,
+            reconnect,
+            client: RwLock::new(Arc::new(client)),
+        })
+    }
+
+    /// Returns the client currently in use.
+    pub(crate) async fn current(&self) -> Arc<WsClient> {
+        self.client.read().await.clone()
+    }
+
+    pub(crate) fn config(&self) -> &ReconnectConfig {
+        &self.reconnect
+    }
+
+    /// Replaces a disconnected client with a freshly connected one, in a single attempt. If the
+    /// current client is still connected, e.g. because another subscription already reconnected,
+    /// it is returned as is.
+    async fn reconnect(&self) -> Result<Arc<WsClient>, ClientError> {
+        let mut client = self.client.write().await;
+        if !client.is_connected() {
+            *client = Arc::new(self.ws_client_builder.clone().build(&self.url).await?);
+        }
+        Ok(client.clone())
+    }
+}
+
+pub(crate) async fn subscribe_on<M>(
+    client: &WsClient,
+    method: &M,
+) -> Result<RawSubscription<M::Output>, ClientError>
+where
+    M: SubscriptionMethod,
+{
+    let params = method
+        .params()
+        .map_err(|e| ClientError::Custom(e.to_string()))?;
+
+    client
+        .subscribe(
+            method.subscribe_method(),
+            params,
+            method.unsubscribe_method(),
+        )
+        .await
+}
+
+/// Whether a failed subscribe call is worth retrying: the connection dropped before the
+/// subscription went through, or the server did not answer in time. Anything else, e.g. the
+/// server rejecting the params, fails the same way on every attempt.
+fn is_transient(error: &ClientError) -> bool {
+    matches!(
+        error,
+        ClientError::RestartNeeded(_) | ClientError::Transport(_) | ClientError::RequestTimeout
+    )
+}
+
+/// A subscription that survives connection drops.
+///
+/// Notifications are buffered like in any [`ChainStreamSubscription`], and the buffer and its
+/// counters carry over reconnects. A subscription ended by
+/// [`OverflowPolicy::Disconnect`](super::subscription::OverflowPolicy::Disconnect) is not
+/// re-established.
+///
+/// Created with [`ChainStreamClient::subscribe_with_reconnect`](super::client::ChainStreamClient::subscribe_with_reconnect).
+pub struct ReconnectingSubscription<M: SubscriptionMethod> {
+    method: M,
+    connection: Arc<Connection>,
+    subscription: ChainStreamSubscription<M::Output>,
+    overflowed: bool,
+    terminated: bool,
+}
+
+impl<M> ReconnectingSubscription<M>
+where
+    M: SubscriptionMethod,
+    M::Output: Send + 'static,
+{
+    pub(crate) fn new(
+        method: M,
+        connection: Arc<Connection>,
+        subscription: ChainStreamSubscription<M::Output>,
+    ) -> Self {
+        Self {
+            method,
+            connection,
+            subscription,
+            overflowed: false,
+            terminated: false,
+        }
+    }
+
+    /// The method this subscription re-issues after every reconnect.
+    #[allow(unused)]
+    pub fn method(&self) -> &M {
+        &self.method
+    }
+
+    /// Counters of the subscription, kept across reconnects.
+    pub fn stats(&self) -> SubscriptionStats<M::Output> {
+        self.subscription.stats()
+    }
+
+    /// Returns the next event, reconnecting and resubscribing as needed.
+    ///
+    /// Returns `None` once reconnecting has been given up on, see
+    /// [`ReconnectConfig::max_attempts`], with the error that caused it returned right before. Also
+    /// returns `None` after the overflow policy disconnected the subscription.
+    pub async fn next(&mut self) -> Option<Result<StreamEvent<M::Output>, ClientError>> {
+        if self.terminated {
+            return None;
+        }
+
+        // After reporting an overflow, go straight to resubscribing.
+        if !std::mem::take(&mut self.overflowed) {
+            match self.subscription.next().await {
+                Some(Ok(notification)) => return Some(Ok(StreamEvent::Notification(notification))),
+                Some(Err(SubscriptionError::Lagged { missed })) => {
+                    return Some(Ok(StreamEvent::Lagged {
+                        missed: Some(missed),
+                    }))
+                }
+                Some(Err(SubscriptionError::Parse(e))) => {
+                    return Some(Err(ClientError::ParseError(e)))
+                }
+                // The websocket client closed the subscription because its buffer was full.
+                // Report the gap, then resubscribe on the next call.
+                Some(Err(SubscriptionError::Overflowed)) => {
+                    self.overflowed = true;
+                    return Some(Ok(StreamEvent::Lagged { missed: None }));
+                }
+                None if self.subscription.disconnected() => {
+                    self.terminated = true;
+                    return None;
+                }
+                None => {}
+            }
+        }
+
+        let disconnected_at = Instant::now();
+        let config = self.connection.config().clone();
+        let mut attempt = 0;
+        loop {
+            // Back off before every attempt, also when the server ended only the subscription and
+            // the connection is still up, so a server that keeps closing it is not hammered.
+            tokio::time::sleep(config.backoff(attempt)).await;
+            attempt += 1;
+
+            let error = match self.connection.reconnect().await {
+                Ok(client) => match subscribe_on(&client, &self.method).await {
+                    Ok(subscription) => {
+                        self.subscription.resubscribe(subscription);
+                        return Some(Ok(StreamEvent::Reconnected {
+                            attempts: attempt,
+                            downtime: disconnected_at.elapsed(),
+                        }));
+                    }
+                    Err(e) if is_transient(&e) => e,
+                    Err(e) => {
+                        self.terminated = true;
+                        return Some(Err(e));
+                    }
+                },
+                Err(e) => e,
+            };
+
+            if config.max_attempts.is_some_and(|max| attempt >= max) {
+                self.terminated = true;
+                return Some(Err(error));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::SocketAddr,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use jsonrpsee::{
+        server::{Server, ServerHandle, SubscriptionMessage},
+        RpcModule,
+    };
+
+    use super::*;
+    use crate::chainstream::{
+        client::ClientBuilder,
+        config::Endpoint,
+        methods::{Method, SlotMethod},
+        mock::{fixtures, MockServer, Notification},
+        subscription::OverflowPolicy,
+        types::SlotStatus,
+    };
+
+    /// Starts a server on `addr` that sends increasing slots to every slot subscriber.
+    async fn start_server(addr: SocketAddr) -> (SocketAddr, ServerHandle) {
+        let server = Server::builder().build(addr).await.unwrap();
+        let mut module = RpcModule::new(());
+        module
+            .register_subscription(
+                "slotUpdatesSubscribe",
+                "slotUpdatesNotification",
+                "slotUpdatesUnsubscribe",
+                |_, pending, _, _| async move {
+                    let sink = pending.accept().await?;
+                    for i in 0u64.. {
+                        let update = serde_json::json!({
+                            "value": { "slot": i, "parent": null, "status": "processed" }
+                        });
+                        let msg = SubscriptionMessage::from_json(&update)?;
+                        sink.send(msg).await?;
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                    }
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        let addr = server.local_addr().unwrap();
+        (addr, server.start(module))
+    }
+
+    /// Starts a server that sends a single slot to every slot subscriber, then ends the
+    /// subscription while keeping the connection open.
+    async fn start_closing_server() -> (SocketAddr, ServerHandle) {
+        let server = Server::builder()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let mut module = RpcModule::new(());
+        module
+            .register_subscription(
+                "slotUpdatesSubscribe",
+                "slotUpdatesNotification",
+                "slotUpdatesUnsubscribe",
+                |_, pending, _, _| async move {
+                    let sink = pending.accept().await?;
+                    let update = serde_json::json!({
+                        "value": { "slot": 0, "parent": null, "status": "processed" }
+                    });
+                    sink.send(SubscriptionMessage::from_json(&update)?).await?;
+                    Err("subscription ended".into())
+                },
+            )
+            .unwrap();
+
+        let addr = server.local_addr().unwrap();
+        (addr, server.start(module))
+    }
+
+    /// Starts a server that ends the first slot subscription after one slot, leaves the second
+    /// subscribe call unanswered for `delay` and keeps every later subscription open.
+    async fn start_slow_server(delay: Duration) -> (SocketAddr, ServerHandle) {
+        let server = Server::builder()
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let mut module = RpcModule::new(AtomicUsize::new(0));
+        module
+            .register_subscription(
+                "slotUpdatesSubscribe",
+                "slotUpdatesNotification",
+                "slotUpdatesUnsubscribe",
+                move |_, pending, calls, _| async move {
+                    let call = calls.fetch_add(1, Ordering::Relaxed);
+                    if call == 1 {
+                        tokio::time::sleep(delay).await;
+                    }
+                    let sink = pending.accept().await?;
+                    let update = serde_json::json!({
+                        "value": { "slot": call, "parent": null, "status": "processed" }
+                    });
+                    sink.send(SubscriptionMessage::from_json(&update)?).await?;
+                    if call == 0 {
+                        return Err("subscription ended".into());
+                    }
+                    sink.closed().await;
+                    Ok(())
+                },
+            )
+            .unwrap();
+
+        let addr = server.local_addr().unwrap();
+        (addr, server.start(module))
+    }
+
+    fn fast_reconnect() -> ReconnectConfig {
+        ReconnectConfig::default()
+            .initial_backoff(Duration::from_millis(10))
+            .max_backoff(Duration::from_millis(50))
+            .jitter(0.0)
+    }
+
+    #[test]
+    fn test_backoff_is_exponential_and_capped() {
+        let config = ReconnectConfig::default()
+            .initial_backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_secs(1))
+            .jitter(0.0);
+
+        assert_eq!(config.backoff(0), Duration::from_millis(100));
+        assert_eq!(config.backoff(1), Duration::from_millis(200));
+        assert_eq!(config.backoff(3), Duration::from_millis(800));
+        assert_eq!(config.backoff(4), Duration::from_secs(1));
+        assert_eq!(config.backoff(30), Duration::from_secs(1));
+
+        let jittered = config.jitter(0.5);
+        for _ in 0..100 {
+            let backoff = jittered.backoff(1);
+            assert!(backoff >= Duration::from_millis(100) && backoff <= Duration::from_millis(300));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resubscribes_after_server_drops_connection() {
+        let (addr, handle) = start_server("127.0.0.1:0".parse().unwrap()).await;
+        let client = ClientBuilder::new()
+            .endpoint(Endpoint::custom(format!("ws://{addr}")).unwrap())
+            .reconnect(fast_reconnect())
+            .build()
+            .await
+            .unwrap();
+
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Notification(_)))
+        ));
+
+        // Kill every connection, then bring the server back on the same port.
+        handle.stop().unwrap();
+        handle.stopped().await;
+        let (_, _handle) = start_server(addr).await;
+
+        let mut reconnected = false;
+        while let Some(event) = subscription.next().await {
+            match event.unwrap() {
+                StreamEvent::Reconnected { attempts, .. } => {
+                    assert!(attempts >= 1);
+                    reconnected = true;
+                }
+                StreamEvent::Notification(_) if reconnected => break,
+                StreamEvent::Notification(_) | StreamEvent::Lagged { .. } => {}
+            }
+        }
+        assert!(reconnected);
+    }
+
+    #[tokio::test]
+    async fn test_backs_off_when_server_ends_subscription() {
+        let (addr, _handle) = start_closing_server().await;
+        let backoff = Duration::from_millis(50);
+        let client = ClientBuilder::new()
+            .endpoint(Endpoint::custom(format!("ws://{addr}")).unwrap())
+            .reconnect(
+                fast_reconnect()
+                    .initial_backoff(backoff)
+                    .max_attempts(Some(1)),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        let mut resubscribes = 0;
+        while resubscribes < 3 {
+            match subscription.next().await.unwrap().unwrap() {
+                StreamEvent::Reconnected { attempts, downtime } => {
+                    assert_eq!(attempts, 1);
+                    assert!(downtime >= backoff);
+                    resubscribes += 1;
+                }
+                StreamEvent::Notification(_) | StreamEvent::Lagged { .. } => {}
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let (addr, handle) = start_server("127.0.0.1:0".parse().unwrap()).await;
+        let client = ClientBuilder::new()
+            .endpoint(Endpoint::custom(format!("ws://{addr}")).unwrap())
+            .reconnect(fast_reconnect().max_attempts(Some(3)))
+            .build()
+            .await
+            .unwrap();
+
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        handle.stop().unwrap();
+        handle.stopped().await;
+
+        let mut failed = false;
+        while let Some(event) = subscription.next().await {
+            if event.is_err() {
+                failed = true;
+            }
+        }
+        assert!(failed);
+    }
+
+    #[tokio::test]
+    async fn test_retries_timed_out_subscribe() {
+        let (addr, _handle) = start_slow_server(Duration::from_millis(500)).await;
+        let client = ClientBuilder::new()
+            .endpoint(Endpoint::custom(format!("ws://{addr}")).unwrap())
+            .request_timeout(Duration::from_millis(100))
+            .reconnect(fast_reconnect().max_attempts(Some(5)))
+            .build()
+            .await
+            .unwrap();
+
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+        assert_eq!(next_slot(&mut subscription).await, 0);
+
+        // The second subscribe call times out and is retried instead of ending the stream.
+        match subscription.next().await {
+            Some(Ok(StreamEvent::Reconnected { attempts, .. })) => assert!(attempts >= 2),
+            other => panic!("expected a reconnect, got {other:?}"),
+        }
+        assert!(next_slot(&mut subscription).await >= 2);
+    }
+
+    #[tokio::test]
+    async fn test_reports_websocket_overflow_as_lag() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .buffer_capacity(2)
+            .max_buffer_capacity_per_subscription(2)
+            .overflow_policy(OverflowPolicy::Block)
+            .reconnect(fast_reconnect())
+            .build()
+            .await
+            .unwrap();
+        let mut subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        // The websocket client's own buffer overflows and it unsubscribes.
+        for slot in 0..20 {
+            server.send(Notification::Slot(fixtures::slot_update(
+                slot,
+                None,
+                SlotStatus::Processed,
+            )));
+        }
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            server.wait_for_active_subscriptions(0),
+        )
+        .await
+        .unwrap();
+
+        let event = loop {
+            match subscription.next().await.unwrap().unwrap() {
+                StreamEvent::Notification(_) => continue,
+                event => break event,
+            }
+        };
+        assert!(matches!(event, StreamEvent::Lagged { missed: None }));
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Reconnected { .. }))
+        ));
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    async fn flooded_subscription(
+        server: &MockServer,
+        policy: OverflowPolicy,
+    ) -> ReconnectingSubscription<SlotMethod> {
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .buffer_capacity(2)
+            .overflow_policy(policy)
+            .build()
+            .await
+            .unwrap();
+        let subscription = client
+            .subscribe_with_reconnect(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        for slot in 0..5 {
+            server.send(Notification::Slot(fixtures::slot_update(
+                slot,
+                None,
+                SlotStatus::Processed,
+            )));
+        }
+        // Disconnecting stops reading at the first notification that does not fit.
+        let expected = match policy {
+            OverflowPolicy::Disconnect => 3,
+            _ => 5,
+        };
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            subscription.stats().wait_received(expected),
+        )
+        .await
+        .unwrap();
+        subscription
+    }
+
+    async fn next_slot(subscription: &mut ReconnectingSubscription<SlotMethod>) -> u64 {
+        match subscription.next().await {
+            Some(Ok(StreamEvent::Notification(update))) => update.value.unwrap().slot,
+            other => panic!("expected a notification, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_applies_overflow_policy() {
+        let server = MockServer::builder().start().await.unwrap();
+
+        let mut subscription = flooded_subscription(&server, OverflowPolicy::DropNewest).await;
+        assert_eq!(subscription.stats().dropped(), 3);
+        assert_eq!(next_slot(&mut subscription).await, 0);
+        assert_eq!(next_slot(&mut subscription).await, 1);
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Lagged { missed: Some(3) }))
+        ));
+
+        // A subscription the policy disconnected is not re-established.
+        let mut subscription = flooded_subscription(&server, OverflowPolicy::Disconnect).await;
+        assert_eq!(next_slot(&mut subscription).await, 0);
+        assert_eq!(next_slot(&mut subscription).await, 1);
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Lagged { missed: Some(1) }))
+        ));
+        assert!(subscription.next().await.is_none());
+        assert_eq!(server.requests().len(), 2);
+    }
+}
@@ -0,0 +1,587 @@
+//! Record ChainStream notifications to disk and replay them later.
+//!
+//! Sessions are stored as newline-delimited JSON, one [`RecordedNotification`] per line, holding
+//! the raw notification exactly as the server sent it together with the time it was received.
+//! Files ending in `.zst` are zstd-compressed. A [`Replay`] reads a session back through the same
+//! typed outputs the live subscriptions produce, either as fast as possible or paced like the
+//! original session.
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    marker::PhantomData,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use jsonrpsee::core::params::ObjectParams;
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use tokio::time::Instant;
+
+use super::{
+    methods::{RpcError, SubscriptionMethod},
+    reconnect::{ReconnectingSubscription, StreamEvent},
+    subscription::SubscriptionStats,
+};
+
+/// zstd level used when compressing recordings.
+const ZSTD_LEVEL: i32 = 3;
+
+/// One line of a recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedNotification {
+    /// Microseconds since the unix epoch at which the notification was received.
+    pub received_at_us: u64,
+    /// Subscribe method of the subscription the notification belongs to.
+    pub method: String,
+    /// The notification as sent by the server.
+    pub notification: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Zstd,
+}
+
+impl Compression {
+    /// `Zstd` for paths ending in `.zst`, `None` otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// The file a recording is written to.
+enum Sink {
+    Plain(BufWriter<File>),
+    Zstd(zstd::Encoder<'static, BufWriter<File>>),
+}
+
+impl Sink {
+    /// Writes out everything buffered, ending the zstd frame if compressed.
+    fn finish(self) -> io::Result<()> {
+        let mut file = match self {
+            Sink::Plain(file) => file,
+            Sink::Zstd(encoder) => encoder.finish()?,
+        };
+        file.flush()?;
+        file.get_ref().sync_all()
+    }
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(file) => file.write(buf),
+            Sink::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(file) => file.flush(),
+            Sink::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// The sink shared by the clones of a [`Recorder`], `None` once finished.
+struct Writer(Mutex<Option<Sink>>);
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        let sink = self.0.get_mut().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(sink) = sink {
+            let _ = sink.finish();
+        }
+    }
+}
+
+/// Appends notifications to a recording. Clones share the same file, so one recorder can capture
+/// several subscriptions into a single session.
+#[derive(Clone)]
+pub struct Recorder {
+    writer: Arc<Writer>,
+}
+
+impl Recorder {
+    /// Creates (or truncates) the recording at `path`, compressing it if the path ends in `.zst`.
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::create_with(path, Compression::from_path(path))
+    }
+
+    pub fn create_with(path: impl AsRef<Path>, compression: Compression) -> Result<Self> {
+        let file = BufWriter::new(File::create(path)?);
+        let sink = match compression {
+            Compression::None => Sink::Plain(file),
+            Compression::Zstd => Sink::Zstd(zstd::Encoder::new(file, ZSTD_LEVEL)?),
+        };
+
+        Ok(Self {
+            writer: Arc::new(Writer(Mutex::new(Some(sink)))),
+        })
+    }
+
+    /// Appends a notification received now.
+    pub fn record(&self, method: &str, notification: &serde_json::Value) -> Result<()> {
+        let received_at_us = SystemTime::now().duration_since(UNIX_EPOCH)?.as_micros() as u64;
+        self.write(&RecordedNotification {
+            received_at_us,
+            method: method.to_string(),
+            notification: notification.clone(),
+        })
+    }
+
+    pub fn write(&self, record: &RecordedNotification) -> Result<()> {
+        let mut writer = self.writer.0.lock().unwrap();
+        let writer = writer
+            .as_mut()
+            .ok_or_else(|| anyhow!("recorder already finished"))?;
+
+        serde_json::to_writer(&mut *writer, record)?;
+        writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Writes out and closes the recording, ending the zstd frame if compressed. Dropping the last
+    /// clone does the same, but swallows errors.
+    pub fn finish(&self) -> Result<()> {
+        if let Some(sink) = self.writer.0.lock().unwrap().take() {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+}
+
+/// A raw notification, stamped with the time it was taken off the websocket.
+#[derive(Debug)]
+pub struct Received {
+    received_at_us: u64,
+    notification: serde_json::Value,
+}
+
+impl<'de> Deserialize<'de> for Received {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Decoded as soon as the subscription reads it, before it is buffered.
+        let notification = serde_json::Value::deserialize(deserializer)?;
+        let received_at_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        Ok(Self {
+            received_at_us,
+            notification,
+        })
+    }
+}
+
+/// Subscribes like `M`, but yields the raw notifications so they can be recorded as sent.
+#[derive(Debug, Clone)]
+pub(crate) struct Recorded<M>(pub(crate) M);
+
+impl<M: SubscriptionMethod> SubscriptionMethod for Recorded<M> {
+    type Output = Received;
+
+    fn subscribe_method(&self) -> &'static str {
+        self.0.subscribe_method()
+    }
+
+    fn unsubscribe_method(&self) -> &'static str {
+        self.0.unsubscribe_method()
+    }
+
+    fn params(&self) -> Result<ObjectParams, RpcError> {
+        self.0.params()
+    }
+}
+
+/// A [`ReconnectingSubscription`] that writes every raw notification to a [`Recorder`] before
+/// decoding it. Notifications are stamped when they arrive, not when they are read, so a
+/// session replayed at [`Speed::Original`] keeps the server's pacing even if the consumer fell
+/// behind while recording.
+///
+/// Created with [`ChainStreamClient::subscribe_recorded`](super::client::ChainStreamClient::subscribe_recorded).
+pub struct RecordingSubscription<M: SubscriptionMethod> {
+    subscription: ReconnectingSubscription<Recorded<M>>,
+    recorder: Recorder,
+}
+
+impl<M: SubscriptionMethod> RecordingSubscription<M> {
+    pub(crate) fn new(
+        subscription: ReconnectingSubscription<Recorded<M>>,
+        recorder: Recorder,
+    ) -> Self {
+        Self {
+            subscription,
+            recorder,
+        }
+    }
+
+    /// Counters of the subscription, kept across reconnects.
+    pub fn stats(&self) -> SubscriptionStats<Received> {
+        self.subscription.stats()
+    }
+
+    /// Returns the next event like [`ReconnectingSubscription::next`]. Notifications are written
+    /// out on the blocking thread pool, so a slow disk does not stall the runtime.
+    pub async fn next(&mut self) -> Option<Result<StreamEvent<M::Output>>> {
+        let received = match self.subscription.next().await? {
+            Ok(StreamEvent::Notification(received)) => received,
+            Ok(StreamEvent::Lagged { missed }) => return Some(Ok(StreamEvent::Lagged { missed })),
+            Ok(StreamEvent::Reconnected { attempts, downtime }) => {
+                return Some(Ok(StreamEvent::Reconnected { attempts, downtime }))
+            }
+            Err(e) => return Some(Err(e.into())),
+        };
+
+        let record = RecordedNotification {
+            received_at_us: received.received_at_us,
+            method: self.subscription.method().subscribe_method().to_string(),
+            notification: received.notification,
+        };
+        let recorder = self.recorder.clone();
+        let written = tokio::task::spawn_blocking(move || recorder.write(&record).map(|_| record));
+        let record = match written.await {
+            Ok(Ok(record)) => record,
+            Ok(Err(e)) => return Some(Err(e)),
+            Err(e) => return Some(Err(e.into())),
+        };
+        Some(
+            serde_json::from_value(record.notification)
+                .map(StreamEvent::Notification)
+                .map_err(Into::into),
+        )
+    }
+}
+
+/// Pacing of a [`Replay`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    /// Yield notifications as fast as they can be read.
+    Max,
+    /// Keep the original gaps between notifications.
+    Original,
+    /// Keep the original gaps divided by the factor, e.g. `10.0` replays ten times as fast. The
+    /// factor must be positive and finite.
+    Accelerated(f64),
+}
+
+/// Reads a recording back as typed notifications.
+pub struct Replay<T> {
+    lines: Box<dyn BufRead + Send>,
+    method: Option<String>,
+    speed: Speed,
+    /// Receive time of the first replayed notification and when it was replayed.
+    clock: Option<(u64, Instant)>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: DeserializeOwned> Replay<T> {
+    /// Opens the recording at `path`, decompressing it if the path ends in `.zst`.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::open_with(path, Compression::from_path(path))
+    }
+
+    pub fn open_with(path: impl AsRef<Path>, compression: Compression) -> Result<Self> {
+        let file = File::open(path)?;
+        let lines: Box<dyn BufRead + Send> = match compression {
+            Compression::None => Box::new(BufReader::new(file)),
+            Compression::Zstd => Box::new(BufReader::new(zstd::Decoder::new(file)?)),
+        };
+
+        Ok(Self {
+            lines,
+            method: None,
+            speed: Speed::Max,
+            clock: None,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Only replay notifications recorded for this subscribe method, e.g.
+    /// `"transactionsSubscribe"`.
+    pub fn method(self, method: &str) -> Self {
+        Self {
+            method: Some(method.to_string()),
+            ..self
+        }
+    }
+
+    /// Fails if an [`Speed::Accelerated`] factor is not positive and finite.
+    pub fn speed(self, speed: Speed) -> Result<Self> {
+        if let Speed::Accelerated(factor) = speed {
+            if !(factor.is_finite() && factor > 0.0) {
+                return Err(anyhow!("invalid replay speed factor {factor}"));
+            }
+        }
+        Ok(Self { speed, ..self })
+    }
+
+    /// Returns the next raw record, without pacing or filtering.
+    pub fn next_record(&mut self) -> Option<Result<RecordedNotification>> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.lines.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) if line.trim().is_empty() => continue,
+                Ok(_) => return Some(serde_json::from_str(&line).map_err(Into::into)),
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+    }
+
+    /// Returns the next notification, waiting as long as [`Speed`] requires.
+    pub async fn next(&mut self) -> Option<Result<T>> {
+        let record = loop {
+            match self.next_record()? {
+                Ok(record) if self.method.as_ref().is_some_and(|m| *m != record.method) => continue,
+                Ok(record) => break record,
+                Err(e) => return Some(Err(e)),
+            }
+        };
+
+        match self.delay(record.received_at_us) {
+            Ok(Some(delay)) => tokio::time::sleep_until(delay).await,
+            Ok(None) => {}
+            Err(e) => return Some(Err(e)),
+        }
+        Some(serde_json::from_value(record.notification).map_err(Into::into))
+    }
+
+    /// When a notification received at `received_at_us` is due.
+    fn delay(&mut self, received_at_us: u64) -> Result<Option<Instant>> {
+        let divisor = match self.speed {
+            Speed::Max => return Ok(None),
+            Speed::Original => 1.0,
+            Speed::Accelerated(factor) => factor,
+        };
+
+        let (first_us, started) = *self
+            .clock
+            .get_or_insert_with(|| (received_at_us, Instant::now()));
+        let elapsed = Duration::from_micros(received_at_us.saturating_sub(first_us));
+        Duration::try_from_secs_f64(elapsed.as_secs_f64() / divisor)
+            .ok()
+            .and_then(|delay| started.checked_add(delay))
+            .map(Some)
+            .ok_or_else(|| anyhow!("notification at {received_at_us}us is too far out to replay"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chainstream::{
+            client::ClientBuilder,
+            methods::Method,
+            mock::{fixtures, MockServer, Notification},
+            reconnect::ReconnectConfig,
+            types::{transaction::TransactionWrite, SlotStatus},
+        },
+        raydium::parse::parse_raydium_anchor_events,
+        test_util::TempDir,
+    };
+
+    fn record(received_at_us: u64, slot: u64) -> RecordedNotification {
+        RecordedNotification {
+            received_at_us,
+            method: "slotUpdatesSubscribe".to_string(),
+            notification: serde_json::to_value(fixtures::slot_update(
+                slot,
+                None,
+                SlotStatus::Processed,
+            ))
+            .unwrap(),
+        }
+    }
+
+    async fn record_session(path: &Path) {
+        let server = MockServer::builder()
+            .transactions(vec![
+                fixtures::transaction(1, fixtures::signature(1), &[&fixtures::pubkey(1)], vec![]),
+                fixtures::transaction(2, fixtures::signature(2), &[&fixtures::pubkey(1)], vec![]),
+            ])
+            .start()
+            .await
+            .unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .build()
+            .await
+            .unwrap();
+
+        let recorder = Recorder::create(path).unwrap();
+        let method =
+            Method::new_transaction_subscription().one_of_account_keys(&[fixtures::pubkey(1)]);
+        let mut subscription = client
+            .subscribe_recorded(method, recorder.clone())
+            .await
+            .unwrap();
+        for slot in [1, 2] {
+            match subscription.next().await.unwrap().unwrap() {
+                StreamEvent::Notification(transaction) => assert_eq!(transaction.value.slot, slot),
+                other => panic!("expected a notification, got {other:?}"),
+            }
+        }
+        recorder.finish().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let dir = TempDir::new("chainstream-recorder-session");
+        for name in ["session.jsonl", "session.jsonl.zst"] {
+            let path = dir.path(name);
+            record_session(&path).await;
+
+            let mut replay = Replay::<TransactionWrite>::open(&path)
+                .unwrap()
+                .method("transactionsSubscribe");
+            let mut slots = vec![];
+            while let Some(transaction) = replay.next().await {
+                let transaction = transaction.unwrap();
+                parse_raydium_anchor_events(&transaction).unwrap();
+                slots.push(transaction.value.slot);
+            }
+            assert_eq!(slots, vec![1, 2]);
+        }
+
+        // The compressed recording is not plain JSON.
+        let compressed = std::fs::read(dir.path("session.jsonl.zst")).unwrap();
+        assert!(serde_json::from_slice::<RecordedNotification>(&compressed).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_recording_survives_reconnect() {
+        let dir = TempDir::new("chainstream-recorder-reconnect");
+        let path = dir.path("reconnect.jsonl");
+        let mut server = MockServer::builder().start().await.unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .reconnect(
+                ReconnectConfig::default()
+                    .initial_backoff(Duration::from_millis(10))
+                    .jitter(0.0),
+            )
+            .build()
+            .await
+            .unwrap();
+
+        let recorder = Recorder::create(&path).unwrap();
+        let mut subscription = client
+            .subscribe_recorded(Method::new_slot_subscription(), recorder.clone())
+            .await
+            .unwrap();
+        let timeout = Duration::from_secs(5);
+        let send_slot = |server: &MockServer, slot| {
+            server.send(Notification::Slot(fixtures::slot_update(
+                slot,
+                None,
+                SlotStatus::Processed,
+            )))
+        };
+
+        send_slot(&server, 1);
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Notification(_)))
+        ));
+        server.drop_connections().await.unwrap();
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Reconnected { .. }))
+        ));
+        tokio::time::timeout(timeout, server.wait_for_requests(2))
+            .await
+            .unwrap();
+        send_slot(&server, 2);
+        assert!(matches!(
+            subscription.next().await,
+            Some(Ok(StreamEvent::Notification(_)))
+        ));
+        recorder.finish().unwrap();
+
+        let mut replay = Replay::<serde_json::Value>::open(&path).unwrap();
+        let mut slots = vec![];
+        while let Some(notification) = replay.next().await {
+            slots.push(notification.unwrap()["value"]["slot"].as_u64().unwrap());
+        }
+        assert_eq!(slots, vec![1, 2]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_replay_pacing() {
+        let dir = TempDir::new("chainstream-recorder-paced");
+        let path = dir.path("paced.jsonl");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.write(&record(1_000_000, 1)).unwrap();
+        recorder.write(&record(3_000_000, 2)).unwrap();
+        recorder.write(&record(5_000_000, 3)).unwrap();
+        recorder.finish().unwrap();
+
+        for (speed, expected) in [
+            (Speed::Original, Duration::from_secs(4)),
+            (Speed::Accelerated(4.0), Duration::from_secs(1)),
+            (Speed::Max, Duration::ZERO),
+        ] {
+            let mut replay = Replay::<serde_json::Value>::open(&path)
+                .unwrap()
+                .speed(speed)
+                .unwrap();
+            let start = Instant::now();
+            let mut count = 0;
+            while let Some(notification) = replay.next().await {
+                notification.unwrap();
+                count += 1;
+            }
+            assert_eq!(count, 3);
+            assert_eq!(start.elapsed(), expected);
+        }
+
+        for factor in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            let replay = Replay::<serde_json::Value>::open(&path).unwrap();
+            assert!(replay.speed(Speed::Accelerated(factor)).is_err());
+        }
+
+        // A tiny factor stretches the gaps beyond what can be waited for.
+        let mut replay = Replay::<serde_json::Value>::open(&path)
+            .unwrap()
+            .speed(Speed::Accelerated(1e-300))
+            .unwrap();
+        assert!(replay.next().await.unwrap().is_ok());
+        assert!(replay.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_replay_filters_by_method() {
+        let dir = TempDir::new("chainstream-recorder-mixed");
+        let path = dir.path("mixed.jsonl");
+        let recorder = Recorder::create(&path).unwrap();
+        recorder.write(&record(0, 1)).unwrap();
+        recorder
+            .record("blocksSubscribe", &serde_json::json!({ "value": null }))
+            .unwrap();
+        recorder.write(&record(0, 2)).unwrap();
+        recorder.finish().unwrap();
+        assert!(recorder
+            .record("blocksSubscribe", &serde_json::Value::Null)
+            .is_err());
+
+        let mut replay = Replay::<serde_json::Value>::open(&path)
+            .unwrap()
+            .method("slotUpdatesSubscribe");
+        let mut slots = vec![];
+        while let Some(notification) = replay.next().await {
+            slots.push(notification.unwrap()["value"]["slot"].as_u64().unwrap());
+        }
+        assert_eq!(slots, vec![1, 2]);
+    }
+}
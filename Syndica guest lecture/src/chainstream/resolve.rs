@@ -0,0 +1,330 @@
+//! Resolves the account indexes of a transaction into pubkeys.
+//!
+//! `Message.account_keys` only holds the static keys. Versioned transactions load more accounts
+//! through address lookup tables, which ChainStream reports in `Meta.loaded_addresses`. Instruction
+//! account indexes refer to the concatenation of both, in the order static keys, loaded writable
+//! keys, loaded readonly keys.
+use solana_sdk::{instruction::AccountMeta, pubkey::Pubkey};
+
+use super::{
+    convert::{pubkey, ConversionError},
+    types::transaction::{CompiledInstruction, TransactionWrite},
+};
+
+/// An instruction with its program id and accounts resolved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedInstruction {
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    /// Instruction data, decoded from base64.
+    pub data: Vec<u8>,
+    /// Index of the top-level instruction this instruction is, or was invoked by.
+    pub top_level_index: usize,
+    /// Position among the inner instructions of the top-level instruction, `None` for the
+    /// top-level instruction itself.
+    pub inner_index: Option<usize>,
+    /// Invoke depth, if ChainStream reported it for this inner instruction.
+    pub stack_height: Option<u32>,
+}
+
+impl TransactionWrite {
+    /// Every account the transaction references, in the order instruction account indexes refer
+    /// to them, with signer and writable flags.
+    ///
+    /// The writable flags are the ones requested by the message. The runtime may still demote
+    /// some of them, e.g. for program ids and reserved accounts.
+    pub fn resolved_accounts(&self) -> Result<Vec<AccountMeta>, ConversionError> {
+        let message = self
+            .value
+            .transaction
+            .as_ref()
+            .and_then(|body| body.message.as_ref())
+            .ok_or(ConversionError::MissingField("transaction.message"))?;
+        let header = message
+            .header
+            .as_ref()
+            .ok_or(ConversionError::MissingField("message.header"))?;
+
+        let static_keys = message.account_keys.len();
+        let signed = header.num_required_signatures as usize;
+        let writable_signed = signed.saturating_sub(header.num_readonly_signed_accounts as usize);
+        let writable_unsigned =
+            static_keys.saturating_sub(header.num_readonly_unsigned_accounts as usize);
+
+        let mut accounts = message
+            .account_keys
+            .iter()
+            .enumerate()
+            .map(|(i, key)| {
+                let is_signer = i < signed;
+                let is_writable = if is_signer {
+                    i < writable_signed
+                } else {
+                    i < writable_unsigned
+                };
+                Ok(AccountMeta {
+                    pubkey: pubkey(key)?,
+                    is_signer,
+                    is_writable,
+                })
+            })
+            .collect::<Result<Vec<_>, ConversionError>>()?;
+
+        if message.address_table_lookups.is_empty() {
+            return Ok(accounts);
+        }
+
+        let loaded = self
+            .value
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.loaded_addresses.as_ref())
+            .ok_or(ConversionError::MissingField("meta.loadedAddresses"))?;
+        for (keys, is_writable) in [(&loaded.writable, true), (&loaded.readonly, false)] {
+            for key in keys {
+                accounts.push(AccountMeta {
+                    pubkey: pubkey(key)?,
+                    is_signer: false,
+                    is_writable,
+                });
+            }
+        }
+
+        Ok(accounts)
+    }
+
+    /// Top-level instructions, each followed by the inner instructions it invoked, with program
+    /// ids and accounts resolved. Fails if an inner instruction group points past the top-level
+    /// instructions.
+    pub fn instructions(&self) -> Result<Instructions<'_>, ConversionError> {
+        let accounts = self
+            .resolved_accounts()?
+            .into_iter()
+            .map(|account| account.pubkey)
+            .collect();
+        let top_level = self
+            .value
+            .transaction
+            .as_ref()
+            .and_then(|body| body.message.as_ref())
+            .map(|message| message.instructions.as_slice())
+            .unwrap_or_default();
+
+        let mut inner: Vec<(usize, usize, &CompiledInstruction)> = Vec::new();
+        if let Some(meta) = &self.value.meta {
+            for group in &meta.inner_instructions {
+                inner.extend(
+                    group
+                        .instructions
+                        .iter()
+                        .enumerate()
+                        .map(|(i, instruction)| (group.index as usize, i, instruction)),
+                );
+            }
+        }
+        // Groups are reported in execution order already, this only guards against reordering.
+        inner.sort_by_key(|(top_level_index, i, _)| (*top_level_index, *i));
+
+        let mut order = Vec::with_capacity(top_level.len() + inner.len());
+        let mut inner = inner.into_iter().peekable();
+        for (index, instruction) in top_level.iter().enumerate() {
+            order.push((index, None, instruction));
+            while let Some((_, i, instruction)) = inner.next_if(|(top, _, _)| *top == index) {
+                order.push((index, Some(i), instruction));
+            }
+        }
+        if let Some((index, _, _)) = inner.next() {
+            return Err(ConversionError::InnerInstructionIndexOutOfBounds(
+                index as u32,
+            ));
+        }
+
+        Ok(Instructions {
+            accounts,
+            order: order.into_iter(),
+        })
+    }
+}
+
+/// Iterator returned by [`TransactionWrite::instructions`].
+#[derive(Debug)]
+pub struct Instructions<'a> {
+    accounts: Vec<Pubkey>,
+    order: std::vec::IntoIter<(usize, Option<usize>, &'a CompiledInstruction)>,
+}
+
+impl Instructions<'_> {
+    fn account(&self, index: u32) -> Result<Pubkey, ConversionError> {
+        self.accounts
+            .get(index as usize)
+            .copied()
+            .ok_or(ConversionError::AccountIndexOutOfBounds(index))
+    }
+
+    fn resolve(
+        &self,
+        top_level_index: usize,
+        inner_index: Option<usize>,
+        instruction: &CompiledInstruction,
+    ) -> Result<ResolvedInstruction, ConversionError> {
+        Ok(ResolvedInstruction {
+            program_id: self.account(instruction.program_id_index)?,
+            accounts: instruction
+                .accounts
+                .iter()
+                .map(|index| self.account(*index))
+                .collect::<Result<_, _>>()?,
+            data: instruction.decoded_data()?,
+            top_level_index,
+            inner_index,
+            stack_height: instruction.stack_height,
+        })
+    }
+}
+
+impl Iterator for Instructions<'_> {
+    type Item = Result<ResolvedInstruction, ConversionError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (top_level_index, inner_index, instruction) = self.order.next()?;
+        Some(self.resolve(top_level_index, inner_index, instruction))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    use super::*;
+    use crate::chainstream::{
+        mock::fixtures,
+        types::transaction::{AddressTableLookup, Header, InnerInstructions, LoadedAddresses},
+    };
+
+    fn instruction(program_id_index: u32, accounts: Vec<u32>, data: &[u8]) -> CompiledInstruction {
+        CompiledInstruction {
+            program_id_index,
+            accounts,
+            data: STANDARD.encode(data),
+            stack_height: None,
+        }
+    }
+
+    /// Keys: payer (signer, writable), pool (writable), program (readonly), then one loaded
+    /// writable and one loaded readonly key.
+    fn transaction(keys: &[Pubkey; 5]) -> TransactionWrite {
+        let static_keys: Vec<String> = keys[..3].iter().map(Pubkey::to_string).collect();
+        let static_keys: Vec<&str> = static_keys.iter().map(String::as_str).collect();
+        let mut transaction =
+            fixtures::transaction(1, fixtures::signature(1), &static_keys, vec![]);
+
+        let message = transaction
+            .value
+            .transaction
+            .as_mut()
+            .unwrap()
+            .message
+            .as_mut()
+            .unwrap();
+        message.header = Some(Header {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        });
+        message.address_table_lookups = vec![AddressTableLookup {
+            account_key: Pubkey::new_unique().to_string(),
+            writable_indexes: vec![0],
+            readonly_indexes: vec![1],
+        }];
+        message.instructions = vec![
+            instruction(2, vec![0, 1, 3], &[1]),
+            instruction(4, vec![], &[2]),
+        ];
+
+        let meta = transaction.value.meta.as_mut().unwrap();
+        meta.loaded_addresses = Some(LoadedAddresses {
+            writable: vec![keys[3].to_string()],
+            readonly: vec![keys[4].to_string()],
+        });
+        meta.inner_instructions = vec![InnerInstructions {
+            index: 0,
+            instructions: vec![instruction(4, vec![3], &[3]), instruction(2, vec![1], &[4])],
+        }];
+        transaction
+    }
+
+    #[test]
+    fn test_resolved_accounts() {
+        let keys = [(); 5].map(|_| Pubkey::new_unique());
+        let accounts = transaction(&keys).resolved_accounts().unwrap();
+
+        let flags: Vec<_> = accounts
+            .iter()
+            .map(|a| (a.pubkey, a.is_signer, a.is_writable))
+            .collect();
+        assert_eq!(
+            flags,
+            [
+                (keys[0], true, true),
+                (keys[1], false, true),
+                (keys[2], false, false),
+                (keys[3], false, true),
+                (keys[4], false, false),
+            ]
+        );
+
+        let mut missing = transaction(&keys);
+        missing.value.meta.as_mut().unwrap().loaded_addresses = None;
+        assert!(matches!(
+            missing.resolved_accounts(),
+            Err(ConversionError::MissingField("meta.loadedAddresses"))
+        ));
+    }
+
+    #[test]
+    fn test_instructions_in_execution_order() {
+        let keys = [(); 5].map(|_| Pubkey::new_unique());
+        let transaction = transaction(&keys);
+        let instructions: Vec<_> = transaction
+            .instructions()
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+
+        let order: Vec<_> = instructions
+            .iter()
+            .map(|ix| (ix.data[0], ix.top_level_index, ix.inner_index))
+            .collect();
+        assert_eq!(
+            order,
+            [(1, 0, None), (3, 0, Some(0)), (4, 0, Some(1)), (2, 1, None)]
+        );
+        assert_eq!(instructions[0].program_id, keys[2]);
+        assert_eq!(instructions[0].accounts, [keys[0], keys[1], keys[3]]);
+        assert_eq!(instructions[1].program_id, keys[4]);
+
+        let mut out_of_bounds = transaction.clone();
+        let message = out_of_bounds
+            .value
+            .transaction
+            .as_mut()
+            .unwrap()
+            .message
+            .as_mut()
+            .unwrap();
+        message.instructions[1].accounts = vec![5];
+        let last = out_of_bounds.instructions().unwrap().last().unwrap();
+        assert!(matches!(
+            last,
+            Err(ConversionError::AccountIndexOutOfBounds(5))
+        ));
+
+        let mut orphaned = transaction.clone();
+        let meta = orphaned.value.meta.as_mut().unwrap();
+        meta.inner_instructions[0].index = 2;
+        assert!(matches!(
+            orphaned.instructions(),
+            Err(ConversionError::InnerInstructionIndexOutOfBounds(2))
+        ));
+    }
+}
@@ -0,0 +1,497 @@
+//! Bounded buffering between the websocket and a slow consumer.
+//!
+//! A [`ChainStreamSubscription`] drains the underlying jsonrpsee subscription into its own queue
+//! as fast as notifications arrive. When the consumer falls behind and the queue is full, the
+//! configured [`OverflowPolicy`] decides what happens, and any notifications that were dropped
+//! are reported in order as a [`SubscriptionError::Lagged`].
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use jsonrpsee::core::client::{Subscription, SubscriptionCloseReason};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tokio::{sync::Notify, task::JoinHandle};
+
+/// Default number of notifications buffered per subscription.
+pub const DEFAULT_BUFFER_CAPACITY: usize = 1024;
+
+/// What a subscription does when its buffer is full and another notification arrives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverflowPolicy {
+    /// Drop the oldest buffered notification to make room.
+    DropOldest,
+    /// Drop the incoming notification.
+    DropNewest,
+    /// Stop reading until the consumer catches up. Notifications then queue up in the websocket
+    /// client, which closes the subscription with [`SubscriptionError::Overflowed`] once its own
+    /// buffer (see `max_buffer_capacity_per_subscription`) is full as well.
+    Block,
+    /// Unsubscribe. The consumer receives what was buffered, then a
+    /// [`SubscriptionError::Lagged`], then the end of the stream.
+    #[default]
+    Disconnect,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubscriptionError {
+    #[error("Subscription lagged behind and missed {missed} notifications")]
+    Lagged { missed: u64 },
+    #[error("Subscription was closed because the client could not keep up")]
+    Overflowed,
+    #[error("Could not parse notification: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Counters of a [`ChainStreamSubscription`]. Cheap to clone and safe to read from another task.
+#[derive(Debug)]
+pub struct SubscriptionStats<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for SubscriptionStats<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+        }
+    }
+}
+
+impl<T> SubscriptionStats<T> {
+    /// Notifications received from the server.
+    pub fn received(&self) -> u64 {
+        self.shared.received.load(Ordering::Relaxed)
+    }
+
+    /// Notifications dropped because the buffer was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Notifications currently buffered and not yet read by the consumer.
+    pub fn queue_depth(&self) -> usize {
+        self.shared.state.lock().unwrap().len
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.capacity
+    }
+
+    /// Waits until at least `count` notifications were received, or the stream was closed.
+    #[cfg(test)]
+    pub(crate) async fn wait_received(&self, count: u64) {
+        loop {
+            let progress = self.shared.progress.notified();
+            tokio::pin!(progress);
+            progress.as_mut().enable();
+            if self.received() >= count || self.shared.state.lock().unwrap().closed {
+                return;
+            }
+            progress.await;
+        }
+    }
+}
+
+enum Entry<T> {
+    Item(Result<T, SubscriptionError>),
+    /// This many notifications were dropped at this point of the stream.
+    Lagged(u64),
+}
+
+struct State<T> {
+    queue: VecDeque<Entry<T>>,
+    /// Number of [`Entry::Item`]s in `queue`.
+    len: usize,
+    closed: bool,
+    /// Closed by [`OverflowPolicy::Disconnect`] rather than by the server or the connection.
+    disconnected: bool,
+}
+
+struct Shared<T> {
+    capacity: usize,
+    policy: OverflowPolicy,
+    state: Mutex<State<T>>,
+    readable: Notify,
+    writable: Notify,
+    /// Woken after every notification that was handled and when the stream is closed.
+    progress: Notify,
+    received: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl<T> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Shared")
+            .field("capacity", &self.capacity)
+            .field("policy", &self.policy)
+            .field("received", &self.received)
+            .field("dropped", &self.dropped)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<T> Shared<T> {
+    /// Queues `item` according to the overflow policy. Returns `false` once the subscription
+    /// should stop reading.
+    async fn push(&self, item: Result<T, SubscriptionError>) -> bool {
+        let keep_reading = self.queue(item).await;
+        // Counted once the item is queued or dropped, so `received` never runs ahead of the queue.
+        self.received.fetch_add(1, Ordering::Relaxed);
+        self.progress.notify_waiters();
+        keep_reading
+    }
+
+    async fn queue(&self, item: Result<T, SubscriptionError>) -> bool {
+        let mut item = Some(item);
+
+        loop {
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.len < self.capacity {
+                    state.queue.push_back(Entry::Item(item.take().unwrap()));
+                    state.len += 1;
+                    self.readable.notify_one();
+                    return true;
+                }
+
+                match self.policy {
+                    OverflowPolicy::DropOldest => {
+                        drop_oldest(&mut state.queue);
+                        state.queue.push_back(Entry::Item(item.take().unwrap()));
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        self.readable.notify_one();
+                        return true;
+                    }
+                    OverflowPolicy::DropNewest => {
+                        push_lagged(&mut state.queue, 1);
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        self.readable.notify_one();
+                        return true;
+                    }
+                    OverflowPolicy::Disconnect => {
+                        push_lagged(&mut state.queue, 1);
+                        state.closed = true;
+                        state.disconnected = true;
+                        self.dropped.fetch_add(1, Ordering::Relaxed);
+                        self.readable.notify_one();
+                        return false;
+                    }
+                    OverflowPolicy::Block => {}
+                }
+            }
+
+            self.writable.notified().await;
+        }
+    }
+
+    /// Ends the stream after everything that is buffered, optionally followed by `error`.
+    fn close(&self, error: Option<SubscriptionError>) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(error) = error {
+            state.queue.push_back(Entry::Item(Err(error)));
+            state.len += 1;
+        }
+        state.closed = true;
+        self.readable.notify_one();
+        self.progress.notify_waiters();
+    }
+}
+
+/// Replaces the oldest buffered notification with a lag marker, merging it with its neighbours.
+fn drop_oldest<T>(queue: &mut VecDeque<Entry<T>>) {
+    let Some(i) = queue.iter().position(|e| matches!(e, Entry::Item(_))) else {
+        return;
+    };
+    let mut missed = 1;
+    if let Some(Entry::Lagged(n)) = queue.get(i + 1) {
+        missed += n;
+        queue.remove(i + 1);
+    }
+    match i.checked_sub(1).and_then(|j| queue.get_mut(j)) {
+        Some(Entry::Lagged(n)) => {
+            *n += missed;
+            queue.remove(i);
+        }
+        _ => queue[i] = Entry::Lagged(missed),
+    }
+}
+
+fn push_lagged<T>(queue: &mut VecDeque<Entry<T>>, missed: u64) {
+    match queue.back_mut() {
+        Some(Entry::Lagged(n)) => *n += missed,
+        _ => queue.push_back(Entry::Lagged(missed)),
+    }
+}
+
+/// A typed ChainStream subscription with a bounded buffer, see the [module docs](self).
+///
+/// Dropping it unsubscribes.
+#[derive(Debug)]
+pub struct ChainStreamSubscription<T> {
+    shared: Arc<Shared<T>>,
+    pump: JoinHandle<()>,
+}
+
+impl<T: DeserializeOwned + Send + 'static> ChainStreamSubscription<T> {
+    pub(crate) fn new(
+        subscription: Subscription<T>,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            capacity: capacity.max(1),
+            policy,
+            state: Mutex::new(State {
+                queue: VecDeque::new(),
+                len: 0,
+                closed: false,
+                disconnected: false,
+            }),
+            readable: Notify::new(),
+            writable: Notify::new(),
+            progress: Notify::new(),
+            received: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        });
+        let pump = tokio::spawn(pump(subscription, shared.clone()));
+
+        Self { shared, pump }
+    }
+
+    /// Continues the stream with a new underlying subscription once the previous one has ended,
+    /// keeping the buffer and counters.
+    pub(crate) fn resubscribe(&mut self, subscription: Subscription<T>) {
+        self.pump.abort();
+        self.shared.state.lock().unwrap().closed = false;
+        self.pump = tokio::spawn(pump(subscription, self.shared.clone()));
+    }
+}
+
+impl<T> ChainStreamSubscription<T> {
+    /// Returns the next notification, `Err(SubscriptionError::Lagged)` where notifications were
+    /// dropped, or `None` once the subscription has ended.
+    pub async fn next(&mut self) -> Option<Result<T, SubscriptionError>> {
+        loop {
+            {
+                let mut state = self.shared.state.lock().unwrap();
+                match state.queue.pop_front() {
+                    Some(Entry::Item(item)) => {
+                        state.len -= 1;
+                        self.shared.writable.notify_one();
+                        return Some(item);
+                    }
+                    Some(Entry::Lagged(missed)) => {
+                        return Some(Err(SubscriptionError::Lagged { missed }));
+                    }
+                    None if state.closed => return None,
+                    None => {}
+                }
+            }
+
+            self.shared.readable.notified().await;
+        }
+    }
+
+    pub fn stats(&self) -> SubscriptionStats<T> {
+        SubscriptionStats {
+            shared: self.shared.clone(),
+        }
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.shared.policy
+    }
+
+    /// Whether the stream was ended by [`OverflowPolicy::Disconnect`].
+    pub(crate) fn disconnected(&self) -> bool {
+        self.shared.state.lock().unwrap().disconnected
+    }
+}
+
+impl<T> Drop for ChainStreamSubscription<T> {
+    fn drop(&mut self) {
+        // Dropping the jsonrpsee subscription inside the task unsubscribes.
+        self.pump.abort();
+    }
+}
+
+async fn pump<T: DeserializeOwned>(mut subscription: Subscription<T>, shared: Arc<Shared<T>>) {
+    while let Some(notification) = subscription.next().await {
+        if !shared.push(notification.map_err(Into::into)).await {
+            let _ = subscription.unsubscribe().await;
+            return;
+        }
+    }
+
+    let error = match subscription.close_reason() {
+        Some(SubscriptionCloseReason::Lagged) => Some(SubscriptionError::Overflowed),
+        _ => None,
+    };
+    shared.close(error);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::time::timeout;
+
+    use super::*;
+    use crate::chainstream::{
+        client::{ChainStreamClient, ClientBuilder},
+        methods::Method,
+        mock::{fixtures, MockServer, Notification},
+        types::{slot::SlotUpdate, SlotStatus},
+    };
+
+    async fn client(server: &MockServer, policy: OverflowPolicy) -> ChainStreamClient {
+        ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .buffer_capacity(2)
+            .overflow_policy(policy)
+            .build()
+            .await
+            .unwrap()
+    }
+
+    fn send_slots(server: &MockServer, count: u64) {
+        for slot in 0..count {
+            server.send(Notification::Slot(fixtures::slot_update(
+                slot,
+                None,
+                SlotStatus::Processed,
+            )));
+        }
+    }
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Sends slots `0..count` and waits until the subscription has received them all, without
+    /// reading any.
+    async fn flood<T>(server: &MockServer, subscription: &ChainStreamSubscription<T>, count: u64) {
+        send_slots(server, count);
+        timeout(TIMEOUT, subscription.stats().wait_received(count))
+            .await
+            .unwrap();
+    }
+
+    async fn next_slot(subscription: &mut ChainStreamSubscription<SlotUpdate>) -> u64 {
+        let update = subscription.next().await.unwrap().unwrap();
+        update.value.unwrap().slot
+    }
+
+    fn assert_lagged(item: Option<Result<SlotUpdate, SubscriptionError>>, expected: u64) {
+        match item {
+            Some(Err(SubscriptionError::Lagged { missed })) => assert_eq!(missed, expected),
+            other => panic!("expected lag, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = client(&server, OverflowPolicy::DropOldest).await;
+        let mut slots = client
+            .subscribe(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        flood(&server, &slots, 5).await;
+        let stats = slots.stats();
+        assert_eq!(stats.dropped(), 3);
+        assert_eq!(stats.queue_depth(), 2);
+
+        assert_lagged(slots.next().await, 3);
+        assert_eq!(next_slot(&mut slots).await, 3);
+        assert_eq!(next_slot(&mut slots).await, 4);
+        assert_eq!(stats.queue_depth(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_drop_newest() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = client(&server, OverflowPolicy::DropNewest).await;
+        let mut slots = client
+            .subscribe(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        flood(&server, &slots, 5).await;
+        assert_eq!(slots.stats().dropped(), 3);
+
+        assert_eq!(next_slot(&mut slots).await, 0);
+        assert_eq!(next_slot(&mut slots).await, 1);
+        assert_lagged(slots.next().await, 3);
+
+        // Keeps streaming afterwards.
+        server.send(Notification::Slot(fixtures::slot_update(
+            9,
+            None,
+            SlotStatus::Processed,
+        )));
+        assert_eq!(next_slot(&mut slots).await, 9);
+    }
+
+    #[tokio::test]
+    async fn test_block() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = ClientBuilder::new()
+            .endpoint(server.endpoint())
+            .buffer_capacity(2)
+            .max_buffer_capacity_per_subscription(2)
+            .overflow_policy(OverflowPolicy::Block)
+            .build()
+            .await
+            .unwrap();
+        let mut slots = client
+            .subscribe(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        // Nothing is dropped here, but the websocket client's own buffer overflows and it
+        // unsubscribes.
+        send_slots(&server, 20);
+        timeout(TIMEOUT, server.wait_for_active_subscriptions(0))
+            .await
+            .unwrap();
+        assert_eq!(slots.stats().dropped(), 0);
+
+        let error = loop {
+            match slots.next().await.unwrap() {
+                Ok(_) => continue,
+                Err(error) => break error,
+            }
+        };
+        assert!(matches!(error, SubscriptionError::Overflowed));
+        assert!(slots.next().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let server = MockServer::builder().start().await.unwrap();
+        let client = client(&server, OverflowPolicy::Disconnect).await;
+        let mut slots = client
+            .subscribe(Method::new_slot_subscription())
+            .await
+            .unwrap();
+
+        // Reading stops at the first notification that does not fit.
+        send_slots(&server, 5);
+        timeout(TIMEOUT, slots.stats().wait_received(3))
+            .await
+            .unwrap();
+
+        assert_eq!(next_slot(&mut slots).await, 0);
+        assert_eq!(next_slot(&mut slots).await, 1);
+        assert_lagged(slots.next().await, 1);
+        assert!(slots.next().await.is_none());
+
+        timeout(TIMEOUT, server.wait_for_active_subscriptions(0))
+            .await
+            .unwrap();
+    }
+}
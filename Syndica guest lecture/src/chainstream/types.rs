@@ -1,7 +1,22 @@
 //! Types that Chainstream emits.
 
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum TypesError {
+    #[error("Transaction has no meta")]
+    MissingMeta,
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("Invalid timestamp: {0}")]
+    InvalidTimestamp(String),
+    #[error("Unknown slot status: {0}")]
+    UnknownSlotStatus(String),
+}
+
 /// [`jsonrpsee`] implements Ethereum pub sub model (https://geth.ethereum.org/docs/interacting-with-geth/rpc/pubsub)
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -20,15 +35,69 @@ pub struct Reward {
     pub commission: Option<u32>,
 }
 
-#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
-pub struct Timestamp(String);
+/// An RFC 3339 timestamp, e.g. `2025-01-21T14:02:11.482907113Z`. A string that doesn't parse is
+/// kept as sent rather than failing the whole notification.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(untagged)]
+pub enum Timestamp {
+    Time(DateTime<Utc>),
+    Unparsed(String),
+}
+
+impl Timestamp {
+    pub fn time(&self) -> Result<DateTime<Utc>, TypesError> {
+        match self {
+            Timestamp::Time(time) => Ok(*time),
+            Timestamp::Unparsed(raw) => Err(TypesError::InvalidTimestamp(raw.clone())),
+        }
+    }
+}
+
+impl From<DateTime<Utc>> for Timestamp {
+    fn from(time: DateTime<Utc>) -> Self {
+        Self::Time(time)
+    }
+}
+
+/// Commitment of the slot a notification belongs to.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    #[serde(alias = "rooted")]
+    Finalized,
+    /// The slot was abandoned and will never be confirmed.
+    Dead,
+    /// A status this version doesn't know about.
+    #[serde(other)]
+    Unknown,
+}
+
+impl FromStr for SlotStatus {
+    type Err = TypesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "processed" => Ok(SlotStatus::Processed),
+            "confirmed" => Ok(SlotStatus::Confirmed),
+            "finalized" | "rooted" => Ok(SlotStatus::Finalized),
+            "dead" => Ok(SlotStatus::Dead),
+            _ => Err(TypesError::UnknownSlotStatus(s.to_string())),
+        }
+    }
+}
 
 pub mod transaction {
     use std::str::FromStr;
 
-    use solana_sdk::signature::Signature;
+    use solana_sdk::{
+        instruction::InstructionError, signature::Signature, transaction::TransactionError,
+    };
 
-    use super::Timestamp;
+    use super::{SlotStatus, Timestamp, TypesError};
 
     #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     pub struct TransactionWrite {
@@ -38,25 +107,27 @@ pub mod transaction {
 
     impl TransactionWrite {
         #[allow(unused)]
-        pub fn signature(&self) -> Signature {
-            Signature::from_str(self.context.signature.as_str()).unwrap()
+        pub fn signature(&self) -> Result<Signature, TypesError> {
+            Signature::from_str(self.context.signature.as_str())
+                .map_err(|_| TypesError::InvalidSignature(self.context.signature.clone()))
         }
 
+        /// Log messages, or `None` if the notification carries no meta.
         #[allow(unused)]
-        pub fn logs(&self) -> &[String] {
-            self.value.meta.as_ref().unwrap().log_messages.as_slice()
+        pub fn logs(&self) -> Option<&[String]> {
+            self.value.meta.as_ref().map(|m| m.log_messages.as_slice())
         }
 
         #[allow(unused)]
-        pub fn meta(&self) -> Meta {
-            self.value.meta.as_ref().unwrap().clone()
+        pub fn meta(&self) -> Result<Meta, TypesError> {
+            self.value.meta.clone().ok_or(TypesError::MissingMeta)
         }
     }
 
     #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Context {
-        pub slot_status: String,
+        pub slot_status: SlotStatus,
         pub node_time: Option<Timestamp>,
         pub is_vote: bool,
         pub signature: String,
@@ -76,6 +147,7 @@ pub mod transaction {
     #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Meta {
+        /// Kept as sent, see [`Meta::transaction_error`].
         pub err: Option<serde_json::Value>,
         pub fee: u64,
         pub inner_instructions: Vec<InnerInstructions>,
@@ -86,6 +158,7 @@ pub mod transaction {
         pub pre_balances: Vec<u64>,
         pub pre_token_balances: Vec<TokenBalance>,
         pub rewards: Vec<super::Reward>,
+        /// Kept as sent, see [`Meta::transaction_status`].
         pub status: Option<serde_json::Value>,
     }
 
@@ -95,6 +168,9 @@ pub mod transaction {
         pub program_id_index: u32,
         pub accounts: Vec<u32>,
         pub data: String,
+        /// Invoke depth of an inner instruction, 2 for one invoked by a top-level instruction.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        pub stack_height: Option<u32>,
     }
 
     #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -111,12 +187,41 @@ pub mod transaction {
         pub readonly: Vec<String>,
     }
 
-    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
-    #[serde(rename_all = "camelCase")]
-    #[serde(untagged)]
-    pub enum OneofTransactionStatus {
-        Ok(bool),
-        Err(serde_json::Value),
+    /// Outcome of a transaction, serialized like the RPC's `{"Ok": null}` / `{"Err": ...}`.
+    #[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    pub enum TransactionStatus {
+        Ok(()),
+        Err(TransactionError),
+    }
+
+    impl TransactionStatus {
+        pub fn is_ok(&self) -> bool {
+            matches!(self, TransactionStatus::Ok(()))
+        }
+
+        pub fn error(&self) -> Option<&TransactionError> {
+            match self {
+                TransactionStatus::Ok(()) => None,
+                TransactionStatus::Err(err) => Some(err),
+            }
+        }
+
+        /// Index of the failing instruction and its error, if an instruction failed.
+        pub fn instruction_error(&self) -> Option<(u8, &InstructionError)> {
+            match self.error()? {
+                TransactionError::InstructionError(index, err) => Some((*index, err)),
+                _ => None,
+            }
+        }
+
+        /// Index of the failing instruction and the custom error code the program returned,
+        /// e.g. an Anchor `#[error_code]`.
+        pub fn custom_error(&self) -> Option<(u8, u32)> {
+            match self.instruction_error()? {
+                (index, InstructionError::Custom(code)) => Some((index, *code)),
+                _ => None,
+            }
+        }
     }
 
     #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
@@ -194,7 +299,8 @@ pub mod block {
         pub slot: u64,
         pub blockhash: String,
         pub rewards: Vec<Reward>,
-        pub block_time: Option<Timestamp>,
+        /// Unix timestamp in seconds.
+        pub block_time: Option<u64>,
         pub block_height: Option<u64>,
         pub parent_slot: Option<u64>,
         pub parent_blockhash: Option<String>,
@@ -203,7 +309,7 @@ pub mod block {
 }
 
 pub mod slot {
-    use super::Timestamp;
+    use super::{SlotStatus, Timestamp};
 
     #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
@@ -222,32 +328,34 @@ pub mod slot {
     pub struct Value {
         pub slot: u64,
         pub parent: Option<u64>,
-        pub status: String,
+        pub status: SlotStatus,
     }
 }
 
+/// A block with its transactions. No subscription method yields it yet, see
+/// [`methods`](super::methods).
 pub mod full_block {
     use super::transaction::TransactionWrite;
     use super::*;
 
-    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Rewards {
         pub rewards: Vec<Reward>,
     }
 
-    #[derive(serde::Serialize, serde::Deserialize, Clone)]
+    #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
     pub struct FullBlock {
         pub context: Option<Context>,
         pub value: Option<Value>,
     }
 
-    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Context {
         pub node_time: Option<Timestamp>,
     }
-    #[derive(Clone, serde::Serialize, serde::Deserialize)]
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
     #[serde(rename_all = "camelCase")]
     pub struct Value {
         pub slot: u64,
@@ -269,3 +377,375 @@ pub mod full_block {
         pub entries: Vec<()>,
     }
 }
+
+/// Notifications of [`AccountMethod`](super::methods::AccountMethod). **Experimental**, the
+/// shape is not taken from the API documentation.
+pub mod account {
+    use super::Timestamp;
+
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct AccountUpdate {
+        pub context: Option<Context>,
+        pub value: Value,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Context {
+        pub node_time: Option<Timestamp>,
+    }
+
+    #[derive(Clone, Debug, serde::Serialize, serde::Deserialize, PartialEq)]
+    #[serde(rename_all = "camelCase")]
+    pub struct Value {
+        pub slot: u64,
+        pub pubkey: String,
+        pub lamports: u64,
+        pub owner: String,
+        /// Account data, decoded from base64.
+        #[serde(with = "base64_data")]
+        pub data: Vec<u8>,
+        pub executable: bool,
+        pub rent_epoch: u64,
+        /// Orders writes to the same account within a slot.
+        pub write_version: u64,
+        /// Signature of the transaction that caused the write, if any.
+        pub txn_signature: Option<String>,
+    }
+
+    /// Account data is sent either as a base64 string or as `[data, "base64"]`.
+    mod base64_data {
+        use base64::{engine::general_purpose::STANDARD, Engine};
+        use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Encoded {
+            Plain(String),
+            Tagged(String, String),
+        }
+
+        pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&STANDARD.encode(data))
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(
+            deserializer: D,
+        ) -> Result<Vec<u8>, D::Error> {
+            let data = match Encoded::deserialize(deserializer)? {
+                Encoded::Plain(data) => data,
+                Encoded::Tagged(data, encoding) if encoding == "base64" => data,
+                Encoded::Tagged(_, encoding) => {
+                    return Err(D::Error::custom(format!(
+                        "unsupported encoding: {encoding}"
+                    )))
+                }
+            };
+            STANDARD.decode(data).map_err(D::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hand-written notifications following the field names and casing declared in this module.
+    // They are not captures of live ChainStream output.
+
+    const SLOT_UPDATE: &str = r#"{
+        "context": { "nodeTime": "2025-01-21T14:02:11.482907113Z" },
+        "value": { "slot": 315254001, "parent": 315254000, "status": "confirmed" }
+    }"#;
+
+    const BLOCK_UPDATE: &str = r#"{
+        "context": { "nodeTime": "2025-01-21T14:02:11.902114520Z" },
+        "value": {
+            "slot": 315254001,
+            "blockhash": "6r2QbKhpyz4cvqmWEYgT2vG3BmmZDyGb8tGMxwU1PX8M",
+            "rewards": [
+                {
+                    "pubkey": "DRpbCBMxVnDK7maPM5tGv6MvB3v1sRMC86PZ8okm21hy",
+                    "lamports": 24870431,
+                    "postBalance": 441820915221,
+                    "rewardType": 1,
+                    "commission": null
+                }
+            ],
+            "blockTime": 1737468131,
+            "blockHeight": 293595577,
+            "parentSlot": 315254000,
+            "parentBlockhash": "9Qk1YtdbEYU1Xp5PxKxZj5mSd2Jg2KpDVrJrGXpWHZ3t",
+            "executedTransactionCount": 1422
+        }
+    }"#;
+
+    const TRANSACTION_WRITE: &str = r#"{
+        "context": {
+            "slotStatus": "confirmed",
+            "nodeTime": "2025-01-21T14:02:11.529331982Z",
+            "isVote": false,
+            "signature": "5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW",
+            "index": 187
+        },
+        "value": {
+            "blockTime": null,
+            "slot": 315254001,
+            "meta": {
+                "err": null,
+                "fee": 5000,
+                "innerInstructions": [],
+                "loadedAddresses": { "writable": [], "readonly": [] },
+                "logMessages": [
+                    "Program 11111111111111111111111111111111 invoke [1]",
+                    "Program 11111111111111111111111111111111 success"
+                ],
+                "postBalances": [999995000, 1],
+                "postTokenBalances": [],
+                "preBalances": [1000000000, 1],
+                "preTokenBalances": [],
+                "rewards": [],
+                "status": null
+            },
+            "message": {
+                "accountKeys": [
+                    "DRpbCBMxVnDK7maPM5tGv6MvB3v1sRMC86PZ8okm21hy",
+                    "11111111111111111111111111111111"
+                ],
+                "addressTableLookups": [],
+                "header": {
+                    "numReadonlySignedAccounts": 0,
+                    "numReadonlyUnsignedAccounts": 1,
+                    "numRequiredSignatures": 1
+                },
+                "instructions": [
+                    { "programIdIndex": 1, "accounts": [0], "data": "AgAAAOgDAAAAAAAA" }
+                ],
+                "recentBlockhash": "9Qk1YtdbEYU1Xp5PxKxZj5mSd2Jg2KpDVrJrGXpWHZ3t"
+            },
+            "messageHash": "HbDHn3yA8rSXrJzwH8XGNdwtR5YVmgvNpVTkmxV2dhb8",
+            "signatures": [
+                "5VERv8NMvzbJMEkV8xnrLkEaWRtSz9CosKDYjCJjBRnbJLgp8uirBgmQpjKhoR4tjF3ZpRzrFmBV6UjKdiSZkQUW"
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn test_deserialize_slot_update() {
+        let update: slot::SlotUpdate = serde_json::from_str(SLOT_UPDATE).unwrap();
+        assert!(update.context.unwrap().node_time.is_some());
+        assert_eq!(
+            update.value.unwrap(),
+            slot::Value {
+                slot: 315254001,
+                parent: Some(315254000),
+                status: SlotStatus::Confirmed,
+            }
+        );
+    }
+
+    #[test]
+    fn test_deserialize_block_update() {
+        let update: block::BlockUpdate = serde_json::from_str(BLOCK_UPDATE).unwrap();
+        let value = update.value.unwrap();
+        assert_eq!(value.slot, 315254001);
+        assert_eq!(value.parent_slot, Some(315254000));
+        assert_eq!(value.block_height, Some(293595577));
+        assert_eq!(value.executed_transaction_count, Some(1422));
+        assert_eq!(value.rewards.len(), 1);
+        assert_eq!(value.rewards[0].lamports, 24870431);
+        assert_eq!(value.rewards[0].commission, None);
+    }
+
+    #[test]
+    fn test_deserialize_full_block() {
+        let full_block = format!(
+            r#"{{
+                "context": {{ "nodeTime": "2025-01-21T14:02:11.902114520Z" }},
+                "value": {{
+                    "slot": 315254001,
+                    "blockhash": "6r2QbKhpyz4cvqmWEYgT2vG3BmmZDyGb8tGMxwU1PX8M",
+                    "rewards": [],
+                    "blockTime": 1737468131,
+                    "blockHeight": 293595577,
+                    "parentSlot": 315254000,
+                    "parentBlockhash": "9Qk1YtdbEYU1Xp5PxKxZj5mSd2Jg2KpDVrJrGXpWHZ3t",
+                    "executedTransactionCount": 1,
+                    "transactions": [{TRANSACTION_WRITE}],
+                    "transactionSignatures": null
+                }}
+            }}"#
+        );
+
+        let block: full_block::FullBlock = serde_json::from_str(&full_block).unwrap();
+        let value = block.value.unwrap();
+        assert_eq!(value.block_time, 1737468131);
+        assert_eq!(value.parent_slot, 315254000);
+
+        let transactions = value.transactions.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].value.slot, 315254001);
+        assert_eq!(transactions[0].context.index, Some(187));
+        assert_eq!(transactions[0].logs().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_deserialize_account_update() {
+        let json = r#"{
+            "context": { "nodeTime": "2025-01-21T14:02:11.482907113Z" },
+            "value": {
+                "slot": 315254001,
+                "pubkey": "3ucNos4NbumPLZNWztqGHNFFgkHeRMBQAVemeeomsUxv",
+                "lamports": 865597441,
+                "owner": "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+                "data": ["AQIDBA==", "base64"],
+                "executable": false,
+                "rentEpoch": 18446744073709551615,
+                "writeVersion": 1417339781022,
+                "txnSignature": null
+            }
+        }"#;
+        let update: account::AccountUpdate = serde_json::from_str(json).unwrap();
+        assert_eq!(update.value.data, [1, 2, 3, 4]);
+        assert_eq!(update.value.write_version, 1417339781022);
+
+        // Serializes back to a plain base64 string, which deserializes as well.
+        let value = serde_json::to_value(&update).unwrap();
+        assert_eq!(value["value"]["data"], "AQIDBA==");
+        let again: account::AccountUpdate = serde_json::from_value(value).unwrap();
+        assert_eq!(again.value, update.value);
+
+        let bad = json.replace(r#""base64""#, r#""base58""#);
+        assert!(serde_json::from_str::<account::AccountUpdate>(&bad).is_err());
+    }
+
+    /// Applies `edit` to [`TRANSACTION_WRITE`] and deserializes the result.
+    fn malformed(edit: impl FnOnce(&mut serde_json::Value)) -> transaction::TransactionWrite {
+        let mut value: serde_json::Value = serde_json::from_str(TRANSACTION_WRITE).unwrap();
+        edit(&mut value);
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_malformed_transactions() {
+        let valid = malformed(|_| {});
+        assert!(valid.signature().is_ok());
+        assert!(valid.meta().is_ok());
+        assert_eq!(valid.context.slot_status, SlotStatus::Confirmed);
+
+        let no_meta = malformed(|v| v["value"]["meta"] = serde_json::Value::Null);
+        assert_eq!(no_meta.meta().unwrap_err(), TypesError::MissingMeta);
+        assert_eq!(no_meta.logs(), None);
+
+        let bad_signature = malformed(|v| v["context"]["signature"] = "not-base58-0OIl".into());
+        assert!(matches!(
+            bad_signature.signature(),
+            Err(TypesError::InvalidSignature(_))
+        ));
+    }
+
+    /// Notifications that must be rejected when deserializing rather than panic later.
+    #[test]
+    fn test_malformed_corpus_is_rejected() {
+        let corpus = [
+            "",
+            "null",
+            "[]",
+            r#"{ "context": {} }"#,
+            r#"{ "value": { "slot": 1 } }"#,
+            &TRANSACTION_WRITE.replace(r#""slot": 315254001"#, r#""slot": "315254001""#),
+            &TRANSACTION_WRITE.replace(r#""isVote": false"#, r#""isVote": "no""#),
+            &TRANSACTION_WRITE.replace(r#""fee": 5000"#, r#""fee": -5000"#),
+        ];
+        for notification in corpus {
+            assert!(
+                serde_json::from_str::<transaction::TransactionWrite>(notification).is_err(),
+                "accepted: {notification}"
+            );
+        }
+    }
+
+    /// Fields the server may extend are accepted, and fail in their accessors instead.
+    #[test]
+    fn test_unfamiliar_values_are_kept() {
+        let status = malformed(|v| v["context"]["slotStatus"] = "optimistic".into());
+        assert_eq!(status.context.slot_status, SlotStatus::Unknown);
+        assert_eq!(
+            "optimistic".parse::<SlotStatus>(),
+            Err(TypesError::UnknownSlotStatus("optimistic".to_string()))
+        );
+
+        let time = malformed(|v| v["context"]["nodeTime"] = "yesterday".into());
+        assert_eq!(
+            time.context.node_time.unwrap().time(),
+            Err(TypesError::InvalidTimestamp("yesterday".to_string()))
+        );
+
+        let err = malformed(|v| v["value"]["meta"]["err"] = "NotAnError".into());
+        assert!(err.meta().unwrap().transaction_error().is_err());
+        assert!(err.meta().unwrap().transaction_status().is_err());
+
+        let slot = SLOT_UPDATE.replace(r#""status": "confirmed""#, r#""status": "sideways""#);
+        let update: slot::SlotUpdate = serde_json::from_str(&slot).unwrap();
+        assert_eq!(update.value.unwrap().status, SlotStatus::Unknown);
+    }
+
+    #[test]
+    fn test_timestamps_and_slot_status() {
+        let transaction: transaction::TransactionWrite =
+            serde_json::from_str(TRANSACTION_WRITE).unwrap();
+        let node_time = transaction.context.node_time.unwrap().time().unwrap();
+        assert_eq!(node_time.timestamp(), 1737468131);
+        assert_eq!(node_time.timestamp_subsec_nanos(), 529331982);
+
+        let block: block::BlockUpdate = serde_json::from_str(BLOCK_UPDATE).unwrap();
+        let block_time = block.value.unwrap().block_time.unwrap();
+        let block_time = DateTime::from_timestamp(block_time as i64, 0).unwrap();
+        assert_eq!((node_time - block_time).num_milliseconds(), 529);
+
+        let rooted = SLOT_UPDATE.replace(r#""status": "confirmed""#, r#""status": "rooted""#);
+        let update: slot::SlotUpdate = serde_json::from_str(&rooted).unwrap();
+        assert_eq!(update.value.unwrap().status, SlotStatus::Finalized);
+    }
+
+    #[test]
+    fn test_transaction_status() {
+        use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+        let failed = TRANSACTION_WRITE
+            .replace(
+                r#""err": null"#,
+                r#""err": { "InstructionError": [2, { "Custom": 6022 }] }"#,
+            )
+            .replace(
+                r#""status": null"#,
+                r#""status": { "Err": { "InstructionError": [2, { "Custom": 6022 }] } }"#,
+            );
+        let failed: transaction::TransactionWrite = serde_json::from_str(&failed).unwrap();
+        let meta = failed.meta().unwrap();
+        let status = meta.transaction_status().unwrap();
+        assert_eq!(
+            serde_json::from_value::<transaction::TransactionStatus>(meta.status.unwrap()).unwrap(),
+            status
+        );
+        assert!(!status.is_ok());
+        assert_eq!(status.custom_error(), Some((2, 6022)));
+
+        let ok: transaction::TransactionWrite = serde_json::from_str(
+            &TRANSACTION_WRITE.replace(r#""status": null"#, r#""status": { "Ok": null }"#),
+        )
+        .unwrap();
+        assert!(ok.meta().unwrap().transaction_status().unwrap().is_ok());
+
+        let not_custom = transaction::TransactionStatus::Err(TransactionError::InstructionError(
+            0,
+            InstructionError::InvalidAccountData,
+        ));
+        assert_eq!(
+            not_custom.instruction_error(),
+            Some((0, &InstructionError::InvalidAccountData))
+        );
+        assert_eq!(not_custom.custom_error(), None);
+    }
+}
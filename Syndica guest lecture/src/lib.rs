@@ -1,2 +1,5 @@
+pub mod anchor;
 pub mod chainstream;
 pub mod raydium;
+#[cfg(test)]
+pub(crate) mod test_util;
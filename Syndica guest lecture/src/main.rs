@@ -2,8 +2,11 @@
 #![allow(unused_variables)]
 #![allow(dead_code)]
 
+mod anchor;
 mod chainstream;
 mod raydium;
+#[cfg(test)]
+mod test_util;
 
 use raydium::anchor_events;
 
@@ -15,7 +18,7 @@ use crate::{
     raydium::{anchor_events::RaydiumCLMMEvent, parse::parse_raydium_anchor_events},
 };
 
-const RAYDIUM_CLMM_PROGRAM: &'static str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+const RAYDIUM_CLMM_PROGRAM: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -10,10 +10,19 @@
 //! which can be found [here](https://github.com/raydium-io/raydium-clmm/blob/master/programs/amm/src/states)
 use anchor_lang::prelude::Pubkey;
 use anchor_lang::prelude::*;
+use base64::{engine::general_purpose, Engine};
+
+use crate::anchor::registry::{EventRegistry, ProgramEvents};
 
 // Number of rewards Token
 const REWARD_NUM: usize = 3;
 
+/// `CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK`
+pub const RAYDIUM_CLMM_PROGRAM: Pubkey = Pubkey::new_from_array([
+    165, 213, 202, 158, 4, 207, 93, 181, 144, 183, 20, 186, 47, 227, 44, 177, 89, 19, 63, 193, 193,
+    146, 183, 34, 87, 253, 7, 211, 156, 176, 64, 30,
+]);
+
 #[allow(unused)]
 #[derive(Debug)]
 pub enum RaydiumCLMMEvent {
@@ -28,9 +37,33 @@ pub enum RaydiumCLMMEvent {
     LiquidityCalculate(LiquidityCalculateEvent),
     CollectPersonalFee(CollectPersonalFeeEvent),
     UpdateRewardInfos(UpdateRewardInfosEvent),
+    /// An event with an unrecognized discriminator, base64 encoded as it was logged.
     Unknown(String),
 }
 
+impl ProgramEvents for RaydiumCLMMEvent {
+    const PROGRAM_ID: Pubkey = RAYDIUM_CLMM_PROGRAM;
+
+    fn registry() -> EventRegistry<Self> {
+        let program = Self::PROGRAM_ID;
+        EventRegistry::new()
+            .event(program, RaydiumCLMMEvent::ConfigChange)
+            .event(program, RaydiumCLMMEvent::Swap)
+            .event(program, RaydiumCLMMEvent::PoolCreated)
+            .event(program, RaydiumCLMMEvent::CollectProtocolFee)
+            .event(program, RaydiumCLMMEvent::LiquidityChange)
+            .event(program, RaydiumCLMMEvent::CreatePersonalPosition)
+            .event(program, RaydiumCLMMEvent::IncreaseLiquidity)
+            .event(program, RaydiumCLMMEvent::DecreaseLiquidity)
+            .event(program, RaydiumCLMMEvent::LiquidityCalculate)
+            .event(program, RaydiumCLMMEvent::CollectPersonalFee)
+            .event(program, RaydiumCLMMEvent::UpdateRewardInfos)
+            .unknown(program, |data| {
+                RaydiumCLMMEvent::Unknown(general_purpose::STANDARD.encode(data))
+            })
+    }
+}
+
 #[event]
 #[derive(Debug)]
 pub struct ConfigChangeEvent {
@@ -0,0 +1,790 @@
+//! Live state of Raydium CLMM pools, maintained from their events.
+//!
+//! A [`PoolBook`] tracks every pool it saw created, or that was added from its account data, and
+//! keeps its price, tick and liquidity current from `Swap` and `LiquidityChange` events. Fee rates
+//! come from `ConfigChange` events, keyed by the config account they apply to. The book can be
+//! saved to disk and restored, so a service can start warm instead of replaying history.
+//!
+//! Events are expected in execution order. Events from a slot older than the last one applied to
+//! a pool are ignored, as they would roll the pool back. So are redelivered events: every pool
+//! remembers the last event applied to it, and the transactions applied to it in that slot.
+//! Config updates older than the rates already known are ignored the same way.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs::File,
+    io::{BufReader, BufWriter, Read},
+    path::{Path, PathBuf},
+};
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    anchor_events::{RaydiumCLMMEvent, RAYDIUM_CLMM_PROGRAM},
+    math::sqrt_price_x64_to_price,
+    parse::{parse_raydium_anchor_events, EventLocation, ParsedEvent},
+    state::{AmmConfig, PoolState},
+};
+use crate::chainstream::{recorder::Compression, types::transaction::TransactionWrite};
+
+/// zstd level used when compressing snapshots.
+const ZSTD_LEVEL: i32 = 3;
+/// Seed of the `AmmConfig` PDA, followed by the big-endian config index.
+const AMM_CONFIG_SEED: &[u8] = b"amm_config";
+
+/// A swap applied to a pool.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Trade {
+    /// Position of the trade among every trade applied to the book.
+    pub sequence: u64,
+    pub signature: String,
+    pub slot: u64,
+    pub zero_for_one: bool,
+    pub amount_0: u64,
+    pub amount_1: u64,
+    /// Pool price after the trade.
+    pub sqrt_price_x64: u128,
+    pub tick: i32,
+}
+
+/// Fee rates of an `AmmConfig`, in hundredths of a bip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeRates {
+    pub trade_fee_rate: u32,
+    /// Share of the trade fee going to the protocol.
+    pub protocol_fee_rate: u32,
+    /// Share of the trade fee going to the fund.
+    pub fund_fee_rate: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PoolEntry {
+    pub pool_state: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub tick_spacing: u16,
+    /// Not reported by `PoolCreated`, only known for pools added from their account data.
+    pub amm_config: Option<Pubkey>,
+    /// Mint decimals of token_0 and token_1, only known for pools added from their account data.
+    pub decimals: Option<(u8, u8)>,
+    pub sqrt_price_x64: u128,
+    pub tick: i32,
+    pub liquidity: u128,
+    /// Slot of the last event applied to the pool.
+    pub slot: u64,
+    /// Most recent trades, oldest first.
+    trades: VecDeque<Trade>,
+    /// Last event applied to the pool, `None` if its state came from account data.
+    #[serde(default)]
+    cursor: Option<Cursor>,
+}
+
+/// Where the last event applied to a pool was, within [`PoolEntry::slot`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Cursor {
+    signature: String,
+    position: EventPosition,
+    /// Transactions applied to the pool in the slot, `signature` included.
+    signatures: HashSet<String>,
+}
+
+/// Execution order of the events of a transaction: by instruction, then by log line. Events of
+/// truncated logs come after every logged one of their instruction, in CPI order.
+type EventPosition = (usize, usize, Option<usize>);
+
+fn position(location: &EventLocation) -> EventPosition {
+    (
+        location.instruction_index,
+        location.log_index.unwrap_or(usize::MAX),
+        location.inner_instruction_index,
+    )
+}
+
+impl PoolEntry {
+    /// Price of one raw unit of token_0 in raw units of token_1.
+    pub fn raw_price(&self) -> f64 {
+        sqrt_price_x64_to_price(self.sqrt_price_x64, 0, 0)
+    }
+
+    /// Price of one whole token_0 in token_1, if the mint decimals are known.
+    pub fn price(&self) -> Option<f64> {
+        let (decimals_0, decimals_1) = self.decimals?;
+        Some(sqrt_price_x64_to_price(
+            self.sqrt_price_x64,
+            decimals_0,
+            decimals_1,
+        ))
+    }
+
+    /// The last `n` trades of the pool, newest first.
+    pub fn trades(&self, n: usize) -> impl Iterator<Item = &Trade> {
+        self.trades.iter().rev().take(n)
+    }
+
+    /// Moves the pool to the event at `location`, returns false if it was already applied or is
+    /// older than the pool.
+    fn advance(&mut self, location: &EventLocation) -> bool {
+        if location.slot < self.slot {
+            return false;
+        }
+        if location.slot == self.slot {
+            if let Some(cursor) = &mut self.cursor {
+                if cursor.signature == location.signature {
+                    if position(location) <= cursor.position {
+                        return false;
+                    }
+                    cursor.position = position(location);
+                    return true;
+                }
+                if !cursor.signatures.insert(location.signature.clone()) {
+                    return false;
+                }
+                cursor.signature = location.signature.clone();
+                cursor.position = position(location);
+                return true;
+            }
+        }
+        self.slot = location.slot;
+        self.cursor = Some(Cursor::new(location));
+        true
+    }
+}
+
+impl Cursor {
+    fn new(location: &EventLocation) -> Self {
+        Self {
+            signature: location.signature.clone(),
+            position: position(location),
+            signatures: HashSet::from([location.signature.clone()]),
+        }
+    }
+}
+
+/// On-disk format of a [`PoolBook`].
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    max_trades: usize,
+    sequence: u64,
+    #[serde(default)]
+    last_slot: u64,
+    pools: Vec<PoolEntry>,
+    /// Fee rates per config, with the slot they were read at.
+    configs: Vec<(Pubkey, u64, FeeRates)>,
+}
+
+/// Pool states maintained from events, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct PoolBook {
+    /// Trades kept per pool.
+    max_trades: usize,
+    sequence: u64,
+    /// Highest slot of the events and account data given to the book.
+    last_slot: u64,
+    pools: HashMap<Pubkey, PoolEntry>,
+    /// Fee rates per config, with the slot they were read at.
+    configs: HashMap<Pubkey, (u64, FeeRates)>,
+    /// Pools per `(mint, mint)`, mints in address order.
+    pairs: HashMap<(Pubkey, Pubkey), Vec<Pubkey>>,
+}
+
+/// The `AmmConfig` account with `index`.
+pub fn amm_config_address(index: u16) -> Pubkey {
+    Pubkey::find_program_address(
+        &[AMM_CONFIG_SEED, &index.to_be_bytes()],
+        &RAYDIUM_CLMM_PROGRAM,
+    )
+    .0
+}
+
+fn pair(mint_a: Pubkey, mint_b: Pubkey) -> (Pubkey, Pubkey) {
+    if mint_a <= mint_b {
+        (mint_a, mint_b)
+    } else {
+        (mint_b, mint_a)
+    }
+}
+
+impl PoolBook {
+    /// An empty book keeping the last `max_trades` trades of every pool.
+    pub fn new(max_trades: usize) -> Self {
+        Self {
+            max_trades,
+            sequence: 0,
+            last_slot: 0,
+            pools: HashMap::new(),
+            configs: HashMap::new(),
+            pairs: HashMap::new(),
+        }
+    }
+
+    fn insert(&mut self, entry: PoolEntry) {
+        let pools = self
+            .pairs
+            .entry(pair(entry.token_mint_0, entry.token_mint_1))
+            .or_default();
+        if !pools.contains(&entry.pool_state) {
+            pools.push(entry.pool_state);
+        }
+        self.pools.insert(entry.pool_state, entry);
+    }
+
+    /// Adds or refreshes a pool from its account data, read at `slot`, returns whether it changed
+    /// the book. Data older than the last event applied to the pool only fills in its config and
+    /// decimals.
+    pub fn track_pool(&mut self, pool_state: Pubkey, pool: &PoolState, slot: u64) -> bool {
+        self.last_slot = self.last_slot.max(slot);
+        if let Some(entry) = self.pools.get_mut(&pool_state) {
+            if slot < entry.slot {
+                let unknown = entry.amm_config.is_none() || entry.decimals.is_none();
+                entry.amm_config = Some(pool.amm_config);
+                entry.decimals = Some((pool.mint_decimals_0, pool.mint_decimals_1));
+                return unknown;
+            }
+        }
+        // Events of the slot already applied stay applied.
+        let (trades, cursor) = self
+            .pools
+            .remove(&pool_state)
+            .map(|entry| (entry.trades, entry.cursor.filter(|_| entry.slot == slot)))
+            .unwrap_or_default();
+        self.insert(PoolEntry {
+            pool_state,
+            token_mint_0: pool.token_mint_0,
+            token_mint_1: pool.token_mint_1,
+            tick_spacing: pool.tick_spacing,
+            amm_config: Some(pool.amm_config),
+            decimals: Some((pool.mint_decimals_0, pool.mint_decimals_1)),
+            sqrt_price_x64: pool.sqrt_price_x64,
+            tick: pool.tick_current,
+            liquidity: pool.liquidity,
+            slot,
+            trades,
+            cursor,
+        });
+        true
+    }
+
+    /// Sets the fee rates of a config from its account data, read at `slot`, returns whether it
+    /// changed the book. Data older than the rates already known is ignored.
+    pub fn track_config(&mut self, amm_config: Pubkey, config: &AmmConfig, slot: u64) -> bool {
+        self.set_fee_rates(
+            amm_config,
+            slot,
+            FeeRates {
+                trade_fee_rate: config.trade_fee_rate,
+                protocol_fee_rate: config.protocol_fee_rate,
+                fund_fee_rate: config.fund_fee_rate,
+            },
+        )
+    }
+
+    fn set_fee_rates(&mut self, amm_config: Pubkey, slot: u64, rates: FeeRates) -> bool {
+        self.last_slot = self.last_slot.max(slot);
+        if self
+            .configs
+            .get(&amm_config)
+            .is_some_and(|(known, _)| slot < *known)
+        {
+            return false;
+        }
+        self.configs.insert(amm_config, (slot, rates));
+        true
+    }
+
+    /// Applies an event, returns whether it changed the book. Events of untracked pools, stale
+    /// events and events already applied are ignored.
+    pub fn apply(&mut self, parsed: &ParsedEvent<RaydiumCLMMEvent>) -> bool {
+        let slot = parsed.location.slot;
+        self.last_slot = self.last_slot.max(slot);
+        match &parsed.event {
+            RaydiumCLMMEvent::PoolCreated(event) => {
+                if self.pools.contains_key(&event.pool_state) {
+                    return false;
+                }
+                self.insert(PoolEntry {
+                    pool_state: event.pool_state,
+                    token_mint_0: event.token_mint_0,
+                    token_mint_1: event.token_mint_1,
+                    tick_spacing: event.tick_spacing,
+                    amm_config: None,
+                    decimals: None,
+                    sqrt_price_x64: event.sqrt_price_x64,
+                    tick: event.tick,
+                    liquidity: 0,
+                    slot,
+                    trades: VecDeque::new(),
+                    cursor: Some(Cursor::new(&parsed.location)),
+                });
+                true
+            }
+            RaydiumCLMMEvent::Swap(event) => {
+                let Some(entry) = self.pools.get_mut(&event.pool_state) else {
+                    return false;
+                };
+                if !entry.advance(&parsed.location) {
+                    return false;
+                }
+                entry.sqrt_price_x64 = event.sqrt_price_x64;
+                entry.tick = event.tick;
+                entry.liquidity = event.liquidity;
+
+                self.sequence += 1;
+                entry.trades.push_back(Trade {
+                    sequence: self.sequence,
+                    signature: parsed.location.signature.clone(),
+                    slot,
+                    zero_for_one: event.zero_for_one,
+                    amount_0: event.amount_0,
+                    amount_1: event.amount_1,
+                    sqrt_price_x64: event.sqrt_price_x64,
+                    tick: event.tick,
+                });
+                while entry.trades.len() > self.max_trades {
+                    entry.trades.pop_front();
+                }
+                true
+            }
+            RaydiumCLMMEvent::LiquidityChange(event) => {
+                let Some(entry) = self.pools.get_mut(&event.pool_state) else {
+                    return false;
+                };
+                if !entry.advance(&parsed.location) {
+                    return false;
+                }
+                entry.tick = event.tick;
+                entry.liquidity = event.liquidity_after;
+                true
+            }
+            RaydiumCLMMEvent::ConfigChange(event) => self.set_fee_rates(
+                amm_config_address(event.index),
+                slot,
+                FeeRates {
+                    trade_fee_rate: event.trade_fee_rate,
+                    protocol_fee_rate: event.protocol_fee_rate,
+                    fund_fee_rate: event.fund_fee_rate,
+                },
+            ),
+            _ => false,
+        }
+    }
+
+    /// Parses the Raydium events of a transaction and applies them, returns how many changed the
+    /// book. Failed transactions are skipped, their events were reverted. So is a transaction whose
+    /// error can't be read, as it has one.
+    pub fn apply_transaction(&mut self, transaction: &TransactionWrite) -> Result<usize> {
+        let failed = transaction
+            .value
+            .meta
+            .as_ref()
+            .is_some_and(|meta| !matches!(meta.transaction_error(), Ok(None)));
+        if failed {
+            return Ok(0);
+        }
+        let events = parse_raydium_anchor_events(transaction)?;
+        Ok(events
+            .iter()
+            .filter(|parsed| !parsed.failed && self.apply(parsed))
+            .count())
+    }
+
+    pub fn pool(&self, pool_state: &Pubkey) -> Option<&PoolEntry> {
+        self.pools.get(pool_state)
+    }
+
+    pub fn pools(&self) -> impl Iterator<Item = &PoolEntry> {
+        self.pools.values()
+    }
+
+    /// Highest slot of the events and account data given to the book, where a restored book
+    /// resumes from. 0 for an empty book.
+    pub fn last_slot(&self) -> u64 {
+        self.last_slot
+    }
+
+    /// Fee rates of a pool, if its config and the config's rates are known.
+    pub fn fee_rates(&self, pool_state: &Pubkey) -> Option<FeeRates> {
+        let amm_config = self.pools.get(pool_state)?.amm_config?;
+        self.config_fee_rates(&amm_config)
+    }
+
+    /// Fee rates of a config, e.g. [`amm_config_address`].
+    pub fn config_fee_rates(&self, amm_config: &Pubkey) -> Option<FeeRates> {
+        self.configs.get(amm_config).map(|(_, rates)| *rates)
+    }
+
+    /// Pools trading `mint_a` against `mint_b`, in either order.
+    pub fn pools_for_pair(&self, mint_a: Pubkey, mint_b: Pubkey) -> Vec<&PoolEntry> {
+        self.pairs
+            .get(&pair(mint_a, mint_b))
+            .into_iter()
+            .flatten()
+            .filter_map(|pool| self.pools.get(pool))
+            .collect()
+    }
+
+    /// Price of one whole `base` in `quote` in the pool of the pair with the most liquidity, if the
+    /// mint decimals of that pool are known.
+    pub fn pair_price(&self, base: Pubkey, quote: Pubkey) -> Option<f64> {
+        let pool = self
+            .pools_for_pair(base, quote)
+            .into_iter()
+            .max_by_key(|pool| pool.liquidity)?;
+        let price = pool.price()?;
+        Some(if pool.token_mint_0 == base {
+            price
+        } else {
+            1.0 / price
+        })
+    }
+
+    /// The last `n` trades across every pool of the pair, newest first.
+    pub fn pair_trades(&self, mint_a: Pubkey, mint_b: Pubkey, n: usize) -> Vec<(Pubkey, &Trade)> {
+        let mut trades: Vec<(Pubkey, &Trade)> = self
+            .pools_for_pair(mint_a, mint_b)
+            .into_iter()
+            .flat_map(|pool| pool.trades(n).map(|trade| (pool.pool_state, trade)))
+            .collect();
+        trades.sort_by_key(|(_, trade)| std::cmp::Reverse(trade.sequence));
+        trades.truncate(n);
+        trades
+    }
+
+    /// Writes the book to `path` as JSON, compressed if the path ends in `.zst`. The snapshot is
+    /// written next to `path` first and moved over it once complete, so a crash never leaves a
+    /// partial snapshot behind.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let snapshot = Snapshot {
+            max_trades: self.max_trades,
+            sequence: self.sequence,
+            last_slot: self.last_slot,
+            pools: self.pools.values().cloned().collect(),
+            configs: self
+                .configs
+                .iter()
+                .map(|(config, (slot, rates))| (*config, *slot, *rates))
+                .collect(),
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = BufWriter::new(File::create(&tmp)?);
+        match Compression::from_path(path) {
+            Compression::None => serde_json::to_writer(&mut file, &snapshot)?,
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(&mut file, ZSTD_LEVEL)?;
+                serde_json::to_writer(&mut encoder, &snapshot)?;
+                encoder.finish()?;
+            }
+        }
+        let file = file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// Reads a book written by [`PoolBook::save`].
+    pub fn restore(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = BufReader::new(File::open(path)?);
+        let reader: Box<dyn Read> = match Compression::from_path(path) {
+            Compression::None => Box::new(file),
+            Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+        };
+        let snapshot: Snapshot = serde_json::from_reader(reader)?;
+
+        let mut book = Self::new(snapshot.max_trades);
+        book.sequence = snapshot.sequence;
+        book.last_slot = snapshot.last_slot;
+        book.configs = snapshot
+            .configs
+            .into_iter()
+            .map(|(config, slot, rates)| (config, (slot, rates)))
+            .collect();
+        for entry in snapshot.pools {
+            book.insert(entry);
+        }
+        Ok(book)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Event;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use bytemuck::Zeroable;
+
+    use super::*;
+    use crate::chainstream::mock::fixtures;
+    use crate::raydium::{
+        anchor_events::{ConfigChangeEvent, LiquidityChangeEvent, PoolCreatedEvent, SwapEvent},
+        math::Q64,
+        parse::EventSource,
+    };
+    use crate::test_util::TempDir;
+
+    fn parsed(slot: u64, event: RaydiumCLMMEvent) -> ParsedEvent<RaydiumCLMMEvent> {
+        parsed_at(slot, &format!("sig-{slot}"), 0, event)
+    }
+
+    fn parsed_at(
+        slot: u64,
+        signature: &str,
+        log_index: usize,
+        event: RaydiumCLMMEvent,
+    ) -> ParsedEvent<RaydiumCLMMEvent> {
+        ParsedEvent {
+            event,
+            program: RAYDIUM_CLMM_PROGRAM,
+            parent: None,
+            source: EventSource::Log,
+            location: EventLocation {
+                signature: signature.to_string(),
+                slot,
+                instruction_index: 0,
+                inner_instruction_index: None,
+                stack_height: Some(1),
+                log_index: Some(log_index),
+            },
+            failed: false,
+        }
+    }
+
+    fn created(pool_state: Pubkey, mints: (Pubkey, Pubkey)) -> RaydiumCLMMEvent {
+        RaydiumCLMMEvent::PoolCreated(PoolCreatedEvent {
+            token_mint_0: mints.0,
+            token_mint_1: mints.1,
+            tick_spacing: 60,
+            pool_state,
+            sqrt_price_x64: Q64,
+            tick: 0,
+            token_vault_0: Pubkey::new_unique(),
+            token_vault_1: Pubkey::new_unique(),
+        })
+    }
+
+    fn swap(pool_state: Pubkey, amount_0: u64, tick: i32, liquidity: u128) -> RaydiumCLMMEvent {
+        RaydiumCLMMEvent::Swap(SwapEvent {
+            pool_state,
+            sender: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            amount_0,
+            transfer_fee_0: 0,
+            amount_1: amount_0 / 2,
+            transfer_fee_1: 0,
+            zero_for_one: true,
+            sqrt_price_x64: Q64 / 2,
+            liquidity,
+            tick,
+        })
+    }
+
+    fn config_change(index: u16, trade_fee_rate: u32) -> RaydiumCLMMEvent {
+        RaydiumCLMMEvent::ConfigChange(ConfigChangeEvent {
+            index,
+            owner: Pubkey::new_unique(),
+            protocol_fee_rate: 120_000,
+            trade_fee_rate,
+            tick_spacing: 60,
+            fund_fee_rate: 40_000,
+            fund_owner: Pubkey::new_unique(),
+        })
+    }
+
+    fn mints() -> (Pubkey, Pubkey) {
+        pair(Pubkey::new_unique(), Pubkey::new_unique())
+    }
+
+    #[test]
+    fn test_applies_events() {
+        let (pool, other) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mints = mints();
+        let mut book = PoolBook::new(2);
+
+        assert!(!book.apply(&parsed(1, swap(pool, 10, -5, 100))));
+        assert!(book.apply(&parsed(1, created(pool, mints))));
+        assert!(book.apply(&parsed(2, swap(pool, 10, -5, 100))));
+        assert!(book.apply(&parsed(
+            3,
+            RaydiumCLMMEvent::LiquidityChange(LiquidityChangeEvent {
+                pool_state: pool,
+                tick: -5,
+                tick_lower: -60,
+                tick_upper: 60,
+                liquidity_before: 100,
+                liquidity_after: 250,
+            })
+        )));
+        // Stale events do not roll the pool back.
+        assert!(!book.apply(&parsed(2, swap(pool, 99, -7, 1))));
+
+        let entry = book.pool(&pool).unwrap();
+        assert_eq!((entry.tick, entry.liquidity, entry.slot), (-5, 250, 3));
+        assert_eq!(entry.raw_price(), 0.25);
+        assert_eq!(entry.price(), None);
+
+        for slot in 4..7 {
+            book.apply(&parsed(slot, swap(pool, slot, -5, 250)));
+        }
+        let amounts: Vec<u64> = entry_trades(&book, &pool, 5);
+        assert_eq!(amounts, [6, 5]);
+
+        // A pool read from its account, with its config, on the same pair with more liquidity.
+        let mut state = PoolState::zeroed();
+        state.token_mint_0 = mints.0;
+        state.token_mint_1 = mints.1;
+        state.mint_decimals_0 = 9;
+        state.mint_decimals_1 = 6;
+        state.sqrt_price_x64 = Q64;
+        state.liquidity = 1_000;
+        state.amm_config = amm_config_address(4);
+        assert!(book.track_pool(other, &state, 5));
+        book.apply(&parsed(7, swap(other, 7, -5, 1_000)));
+        // Older account data doesn't roll the pool back.
+        state.liquidity = 1;
+        assert!(!book.track_pool(other, &state, 6));
+        assert_eq!(book.pool(&other).unwrap().liquidity, 1_000);
+
+        assert!(book.apply(&parsed(8, config_change(4, 2_500))));
+        assert_eq!(book.fee_rates(&other).unwrap().trade_fee_rate, 2_500);
+        assert_eq!(book.fee_rates(&pool), None);
+
+        assert_eq!(book.pools_for_pair(mints.1, mints.0).len(), 2);
+        let price = book.pair_price(mints.0, mints.1).unwrap();
+        assert_eq!(price, 250.0);
+        assert!((book.pair_price(mints.1, mints.0).unwrap() - 0.004).abs() < 1e-12);
+
+        let trades: Vec<(Pubkey, u64)> = book
+            .pair_trades(mints.1, mints.0, 3)
+            .into_iter()
+            .map(|(pool, trade)| (pool, trade.amount_0))
+            .collect();
+        assert_eq!(trades, [(other, 7), (pool, 6), (pool, 5)]);
+
+        // Older account data still fills in what events don't report.
+        assert!(book.track_pool(pool, &state, 1));
+        let entry = book.pool(&pool).unwrap();
+        assert_eq!((entry.liquidity, entry.decimals), (250, Some((9, 6))));
+        assert_eq!(book.fee_rates(&pool).unwrap().trade_fee_rate, 2_500);
+    }
+
+    fn entry_trades(book: &PoolBook, pool: &Pubkey, n: usize) -> Vec<u64> {
+        book.pool(pool)
+            .unwrap()
+            .trades(n)
+            .map(|trade| trade.amount_0)
+            .collect()
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let pool = Pubkey::new_unique();
+        let mints = mints();
+        let mut book = PoolBook::new(10);
+        book.apply(&parsed(1, created(pool, mints)));
+        book.apply(&parsed(2, swap(pool, 10, -5, 100)));
+        book.track_config(amm_config_address(0), &AmmConfig::zeroed(), 2);
+
+        let dir = TempDir::new("raydium-book-snapshot");
+        for name in ["book.json", "book.json.zst"] {
+            let path = dir.path(name);
+            book.save(&path).unwrap();
+            assert!(!dir.path(&format!("{name}.tmp")).exists());
+            let mut restored = PoolBook::restore(&path).unwrap();
+
+            assert_eq!(restored.pool(&pool), book.pool(&pool));
+            assert_eq!(restored.pools_for_pair(mints.0, mints.1).len(), 1);
+            assert!(restored.config_fee_rates(&amm_config_address(0)).is_some());
+            assert_eq!(restored.last_slot(), 2);
+
+            // Events applied before the snapshot stay applied.
+            assert!(!restored.apply(&parsed(2, swap(pool, 10, -5, 100))));
+
+            // Sequence numbers continue where the saved book stopped.
+            restored.apply(&parsed(3, swap(pool, 11, -5, 100)));
+            let trades = restored.pair_trades(mints.0, mints.1, 2);
+            assert_eq!(trades[0].1.sequence, 2);
+            assert_eq!(entry_trades(&restored, &pool, 5), [11, 10]);
+        }
+    }
+
+    #[test]
+    fn test_skips_redelivered_events() {
+        let pool = Pubkey::new_unique();
+        let mut book = PoolBook::new(10);
+        book.apply(&parsed(1, created(pool, mints())));
+
+        assert!(book.apply(&parsed_at(2, "a", 1, swap(pool, 1, -5, 100))));
+        assert!(book.apply(&parsed_at(2, "a", 3, swap(pool, 2, -5, 100))));
+        assert!(book.apply(&parsed_at(2, "b", 1, swap(pool, 3, -5, 100))));
+        // Both transactions of the slot again, in order.
+        for (signature, log_index, amount) in [("a", 1, 1), ("a", 3, 2), ("b", 1, 3)] {
+            assert!(!book.apply(&parsed_at(
+                2,
+                signature,
+                log_index,
+                swap(pool, amount, -5, 100)
+            )));
+        }
+        assert!(book.apply(&parsed_at(2, "c", 1, swap(pool, 4, -5, 100))));
+        assert!(book.apply(&parsed_at(3, "a", 1, swap(pool, 5, -5, 100))));
+        assert!(!book.apply(&parsed_at(3, "a", 0, swap(pool, 6, -5, 100))));
+
+        assert_eq!(entry_trades(&book, &pool, 10), [5, 4, 3, 2, 1]);
+        assert_eq!(book.last_slot(), 3);
+    }
+
+    #[test]
+    fn test_ignores_older_config_updates() {
+        let config = amm_config_address(1);
+        let mut book = PoolBook::new(10);
+        assert!(book.apply(&parsed(5, config_change(1, 2_500))));
+
+        let mut account = AmmConfig::zeroed();
+        account.trade_fee_rate = 100;
+        assert!(!book.track_config(config, &account, 4));
+        assert!(!book.apply(&parsed(3, config_change(1, 500))));
+        assert_eq!(
+            book.config_fee_rates(&config).unwrap().trade_fee_rate,
+            2_500
+        );
+
+        assert!(book.track_config(config, &account, 6));
+        assert_eq!(book.config_fee_rates(&config).unwrap().trade_fee_rate, 100);
+        assert_eq!(book.last_slot(), 6);
+    }
+
+    #[test]
+    fn test_skips_failed_transactions() {
+        let pool = Pubkey::new_unique();
+        let mut book = PoolBook::new(10);
+        book.apply(&parsed(1, created(pool, mints())));
+
+        let RaydiumCLMMEvent::Swap(event) = swap(pool, 10, -5, 100) else {
+            unreachable!();
+        };
+        let logs = vec![
+            format!("Program {RAYDIUM_CLMM_PROGRAM} invoke [1]"),
+            format!("Program data: {}", STANDARD.encode(event.data())),
+            format!("Program {RAYDIUM_CLMM_PROGRAM} failed: custom program error: 0x1786"),
+        ];
+        let mut failed = fixtures::transaction(2, fixtures::signature(2), &["A"], logs.clone());
+        failed.value.meta.as_mut().unwrap().err =
+            Some(serde_json::json!({ "InstructionError": [0, { "Custom": 6022 }] }));
+        assert_eq!(book.apply_transaction(&failed).unwrap(), 0);
+
+        // Even if the error is missing, the event of the failed invocation is skipped.
+        failed.value.meta.as_mut().unwrap().err = None;
+        assert_eq!(book.apply_transaction(&failed).unwrap(), 0);
+        assert!(book.pool(&pool).unwrap().trades(1).next().is_none());
+
+        let mut logs = logs;
+        logs[2] = format!("Program {RAYDIUM_CLMM_PROGRAM} success");
+        let succeeded = fixtures::transaction(3, fixtures::signature(3), &["A"], logs);
+        assert_eq!(book.apply_transaction(&succeeded).unwrap(), 1);
+        assert_eq!(book.pool(&pool).unwrap().liquidity, 100);
+    }
+}
@@ -0,0 +1,703 @@
+//! Raydium CLMM instructions, decoded from instruction data and accounts.
+//!
+//! Anchor prefixes instruction data with the first 8 bytes of `sha256("global:<name>")`, followed
+//! by the borsh-serialized arguments. Accounts are positional, in the order of the instruction's
+//! `#[derive(Accounts)]` struct in the Raydium program, see
+//! [here](https://github.com/raydium-io/raydium-clmm/tree/master/programs/amm/src/instructions).
+//! Program accounts (token, system, memo...) are not mapped.
+use std::io;
+
+use anchor_lang::prelude::*;
+
+use crate::chainstream::{convert::ConversionError, types::transaction::TransactionWrite};
+
+use super::anchor_events::RAYDIUM_CLMM_PROGRAM;
+
+pub const CREATE_POOL: [u8; 8] = [233, 146, 209, 142, 207, 104, 64, 188];
+pub const OPEN_POSITION: [u8; 8] = [135, 128, 47, 77, 15, 152, 240, 49];
+pub const OPEN_POSITION_V2: [u8; 8] = [77, 184, 74, 214, 112, 86, 241, 199];
+pub const INCREASE_LIQUIDITY: [u8; 8] = [46, 156, 243, 118, 13, 205, 251, 178];
+pub const INCREASE_LIQUIDITY_V2: [u8; 8] = [133, 29, 89, 223, 69, 238, 176, 10];
+pub const DECREASE_LIQUIDITY: [u8; 8] = [160, 38, 208, 111, 104, 91, 44, 1];
+pub const DECREASE_LIQUIDITY_V2: [u8; 8] = [58, 127, 188, 62, 79, 82, 196, 96];
+pub const SWAP: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+pub const SWAP_V2: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+pub const COLLECT_PROTOCOL_FEE: [u8; 8] = [136, 136, 252, 221, 194, 66, 126, 89];
+pub const COLLECT_FUND_FEE: [u8; 8] = [167, 138, 78, 149, 223, 194, 6, 126];
+
+#[derive(Debug, ::thiserror::Error)]
+pub enum InstructionDecodeError {
+    #[error("Invalid {instruction} arguments: {source}")]
+    InvalidArgs {
+        instruction: &'static str,
+        source: io::Error,
+    },
+    #[error("{instruction} expects at least {expected} accounts, got {actual}")]
+    MissingAccounts {
+        instruction: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error(transparent)]
+    Conversion(#[from] ConversionError),
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CreatePoolArgs {
+    pub sqrt_price_x64: u128,
+    pub open_time: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenPositionArgs {
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    pub tick_array_lower_start_index: i32,
+    pub tick_array_upper_start_index: i32,
+    pub liquidity: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct OpenPositionV2Args {
+    pub tick_lower_index: i32,
+    pub tick_upper_index: i32,
+    pub tick_array_lower_start_index: i32,
+    pub tick_array_upper_start_index: i32,
+    pub liquidity: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+    pub with_metadata: bool,
+    /// When set, `liquidity` is computed from the token 0 (`true`) or token 1 amount max.
+    pub base_flag: Option<bool>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IncreaseLiquidityArgs {
+    pub liquidity: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IncreaseLiquidityV2Args {
+    pub liquidity: u128,
+    pub amount_0_max: u64,
+    pub amount_1_max: u64,
+    pub base_flag: Option<bool>,
+}
+
+/// Arguments of `decrease_liquidity` and `decrease_liquidity_v2`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct DecreaseLiquidityArgs {
+    pub liquidity: u128,
+    pub amount_0_min: u64,
+    pub amount_1_min: u64,
+}
+
+/// Arguments of `swap` and `swap_v2`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SwapArgs {
+    /// Input amount if `is_base_input`, output amount otherwise.
+    pub amount: u64,
+    /// Minimum output amount if `is_base_input`, maximum input amount otherwise.
+    pub other_amount_threshold: u64,
+    /// The swap stops at this price, 0 for no limit.
+    pub sqrt_price_limit_x64: u128,
+    pub is_base_input: bool,
+}
+
+/// Arguments of `collect_protocol_fee` and `collect_fund_fee`.
+#[derive(AnchorSerialize, AnchorDeserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CollectFeeArgs {
+    pub amount_0_requested: u64,
+    pub amount_1_requested: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CreatePoolAccounts {
+    pub pool_creator: Pubkey,
+    pub amm_config: Pubkey,
+    pub pool_state: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub observation_state: Pubkey,
+    pub tick_array_bitmap: Pubkey,
+}
+
+/// Accounts of `open_position` and `open_position_v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenPositionAccounts {
+    pub payer: Pubkey,
+    pub position_nft_owner: Pubkey,
+    pub position_nft_mint: Pubkey,
+    pub position_nft_account: Pubkey,
+    pub pool_state: Pubkey,
+    pub protocol_position: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+    pub personal_position: Pubkey,
+    pub token_account_0: Pubkey,
+    pub token_account_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    /// Only passed to the v2 instruction.
+    pub vault_0_mint: Option<Pubkey>,
+    pub vault_1_mint: Option<Pubkey>,
+}
+
+/// Accounts of `increase_liquidity` and `increase_liquidity_v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncreaseLiquidityAccounts {
+    pub nft_owner: Pubkey,
+    pub nft_account: Pubkey,
+    pub pool_state: Pubkey,
+    pub protocol_position: Pubkey,
+    pub personal_position: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+    pub token_account_0: Pubkey,
+    pub token_account_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    /// Only passed to the v2 instruction.
+    pub vault_0_mint: Option<Pubkey>,
+    pub vault_1_mint: Option<Pubkey>,
+}
+
+/// Accounts of `decrease_liquidity` and `decrease_liquidity_v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecreaseLiquidityAccounts {
+    pub nft_owner: Pubkey,
+    pub nft_account: Pubkey,
+    pub personal_position: Pubkey,
+    pub pool_state: Pubkey,
+    pub protocol_position: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub tick_array_lower: Pubkey,
+    pub tick_array_upper: Pubkey,
+    pub recipient_token_account_0: Pubkey,
+    pub recipient_token_account_1: Pubkey,
+    /// Only passed to the v2 instruction.
+    pub vault_0_mint: Option<Pubkey>,
+    pub vault_1_mint: Option<Pubkey>,
+}
+
+/// Accounts of `swap` and `swap_v2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapAccounts {
+    pub payer: Pubkey,
+    pub amm_config: Pubkey,
+    pub pool_state: Pubkey,
+    pub input_token_account: Pubkey,
+    pub output_token_account: Pubkey,
+    pub input_vault: Pubkey,
+    pub output_vault: Pubkey,
+    pub observation_state: Pubkey,
+    /// The tick arrays the swap may cross, in crossing order. May also hold the tick array bitmap
+    /// extension of the pool, which can only be told apart by its account data.
+    pub tick_arrays: Vec<Pubkey>,
+    /// Only passed to the v2 instruction.
+    pub input_vault_mint: Option<Pubkey>,
+    pub output_vault_mint: Option<Pubkey>,
+}
+
+/// Accounts of `collect_protocol_fee` and `collect_fund_fee`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollectFeeAccounts {
+    pub owner: Pubkey,
+    pub pool_state: Pubkey,
+    pub amm_config: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub vault_0_mint: Pubkey,
+    pub vault_1_mint: Pubkey,
+    pub recipient_token_account_0: Pubkey,
+    pub recipient_token_account_1: Pubkey,
+}
+
+#[allow(unused)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RaydiumCLMMInstruction {
+    CreatePool {
+        args: CreatePoolArgs,
+        accounts: CreatePoolAccounts,
+    },
+    OpenPosition {
+        args: OpenPositionArgs,
+        accounts: OpenPositionAccounts,
+    },
+    OpenPositionV2 {
+        args: OpenPositionV2Args,
+        accounts: OpenPositionAccounts,
+    },
+    IncreaseLiquidity {
+        args: IncreaseLiquidityArgs,
+        accounts: IncreaseLiquidityAccounts,
+    },
+    IncreaseLiquidityV2 {
+        args: IncreaseLiquidityV2Args,
+        accounts: IncreaseLiquidityAccounts,
+    },
+    DecreaseLiquidity {
+        args: DecreaseLiquidityArgs,
+        accounts: DecreaseLiquidityAccounts,
+    },
+    DecreaseLiquidityV2 {
+        args: DecreaseLiquidityArgs,
+        accounts: DecreaseLiquidityAccounts,
+    },
+    Swap {
+        args: SwapArgs,
+        accounts: SwapAccounts,
+    },
+    SwapV2 {
+        args: SwapArgs,
+        accounts: SwapAccounts,
+    },
+    CollectProtocolFee {
+        args: CollectFeeArgs,
+        accounts: CollectFeeAccounts,
+    },
+    CollectFundFee {
+        args: CollectFeeArgs,
+        accounts: CollectFeeAccounts,
+    },
+}
+
+/// A Raydium CLMM instruction of a transaction, top-level or invoked through CPI.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedInstruction {
+    pub instruction: RaydiumCLMMInstruction,
+    /// Index of the top-level instruction this instruction is, or was invoked by.
+    pub instruction_index: usize,
+    /// Position in the `Meta.inner_instructions` group of `instruction_index`, `None` for a
+    /// top-level instruction.
+    pub inner_instruction_index: Option<usize>,
+}
+
+fn args<T: AnchorDeserialize>(
+    instruction: &'static str,
+    mut data: &[u8],
+) -> std::result::Result<T, InstructionDecodeError> {
+    T::deserialize(&mut data).map_err(|source| InstructionDecodeError::InvalidArgs {
+        instruction,
+        source,
+    })
+}
+
+/// The first `expected` accounts, or an error if the instruction has fewer.
+fn accounts<'a>(
+    instruction: &'static str,
+    accounts: &'a [Pubkey],
+    expected: usize,
+) -> std::result::Result<&'a [Pubkey], InstructionDecodeError> {
+    if accounts.len() < expected {
+        return Err(InstructionDecodeError::MissingAccounts {
+            instruction,
+            expected,
+            actual: accounts.len(),
+        });
+    }
+    Ok(accounts)
+}
+
+impl RaydiumCLMMInstruction {
+    /// Decodes the data and accounts of an instruction sent to the Raydium CLMM program.
+    ///
+    /// Returns `None` for instructions this module does not cover, including `emit_cpi!` event
+    /// self-invocations, see [`super::parse`].
+    pub fn decode(
+        accounts: &[Pubkey],
+        data: &[u8],
+    ) -> std::result::Result<Option<Self>, InstructionDecodeError> {
+        let Some((discriminator, data)) = data.split_first_chunk::<8>() else {
+            return Ok(None);
+        };
+        let instruction = match *discriminator {
+            CREATE_POOL => {
+                let a = self::accounts("create_pool", accounts, 9)?;
+                Self::CreatePool {
+                    args: args("create_pool", data)?,
+                    accounts: CreatePoolAccounts {
+                        pool_creator: a[0],
+                        amm_config: a[1],
+                        pool_state: a[2],
+                        token_mint_0: a[3],
+                        token_mint_1: a[4],
+                        token_vault_0: a[5],
+                        token_vault_1: a[6],
+                        observation_state: a[7],
+                        tick_array_bitmap: a[8],
+                    },
+                }
+            }
+            OPEN_POSITION => Self::OpenPosition {
+                args: args("open_position", data)?,
+                accounts: open_position_accounts("open_position", accounts, false)?,
+            },
+            OPEN_POSITION_V2 => Self::OpenPositionV2 {
+                args: args("open_position_v2", data)?,
+                accounts: open_position_accounts("open_position_v2", accounts, true)?,
+            },
+            INCREASE_LIQUIDITY => Self::IncreaseLiquidity {
+                args: args("increase_liquidity", data)?,
+                accounts: increase_liquidity_accounts("increase_liquidity", accounts, false)?,
+            },
+            INCREASE_LIQUIDITY_V2 => Self::IncreaseLiquidityV2 {
+                args: args("increase_liquidity_v2", data)?,
+                accounts: increase_liquidity_accounts("increase_liquidity_v2", accounts, true)?,
+            },
+            DECREASE_LIQUIDITY => Self::DecreaseLiquidity {
+                args: args("decrease_liquidity", data)?,
+                accounts: decrease_liquidity_accounts("decrease_liquidity", accounts, false)?,
+            },
+            DECREASE_LIQUIDITY_V2 => Self::DecreaseLiquidityV2 {
+                args: args("decrease_liquidity_v2", data)?,
+                accounts: decrease_liquidity_accounts("decrease_liquidity_v2", accounts, true)?,
+            },
+            SWAP => Self::Swap {
+                args: args("swap", data)?,
+                accounts: swap_accounts("swap", accounts, false)?,
+            },
+            SWAP_V2 => Self::SwapV2 {
+                args: args("swap_v2", data)?,
+                accounts: swap_accounts("swap_v2", accounts, true)?,
+            },
+            COLLECT_PROTOCOL_FEE => Self::CollectProtocolFee {
+                args: args("collect_protocol_fee", data)?,
+                accounts: collect_fee_accounts("collect_protocol_fee", accounts)?,
+            },
+            COLLECT_FUND_FEE => Self::CollectFundFee {
+                args: args("collect_fund_fee", data)?,
+                accounts: collect_fee_accounts("collect_fund_fee", accounts)?,
+            },
+            _ => return Ok(None),
+        };
+        Ok(Some(instruction))
+    }
+
+    /// The pool the instruction operates on.
+    pub fn pool_state(&self) -> Pubkey {
+        match self {
+            Self::CreatePool { accounts, .. } => accounts.pool_state,
+            Self::OpenPosition { accounts, .. } | Self::OpenPositionV2 { accounts, .. } => {
+                accounts.pool_state
+            }
+            Self::IncreaseLiquidity { accounts, .. }
+            | Self::IncreaseLiquidityV2 { accounts, .. } => accounts.pool_state,
+            Self::DecreaseLiquidity { accounts, .. }
+            | Self::DecreaseLiquidityV2 { accounts, .. } => accounts.pool_state,
+            Self::Swap { accounts, .. } | Self::SwapV2 { accounts, .. } => accounts.pool_state,
+            Self::CollectProtocolFee { accounts, .. } | Self::CollectFundFee { accounts, .. } => {
+                accounts.pool_state
+            }
+        }
+    }
+}
+
+fn open_position_accounts(
+    instruction: &'static str,
+    accounts: &[Pubkey],
+    v2: bool,
+) -> std::result::Result<OpenPositionAccounts, InstructionDecodeError> {
+    // v2 appends token_program_2022, vault_0_mint and vault_1_mint to the 19 accounts of v1.
+    let a = self::accounts(instruction, accounts, if v2 { 22 } else { 14 })?;
+    Ok(OpenPositionAccounts {
+        payer: a[0],
+        position_nft_owner: a[1],
+        position_nft_mint: a[2],
+        position_nft_account: a[3],
+        // 4 is the metadata account.
+        pool_state: a[5],
+        protocol_position: a[6],
+        tick_array_lower: a[7],
+        tick_array_upper: a[8],
+        personal_position: a[9],
+        token_account_0: a[10],
+        token_account_1: a[11],
+        token_vault_0: a[12],
+        token_vault_1: a[13],
+        vault_0_mint: v2.then(|| a[20]),
+        vault_1_mint: v2.then(|| a[21]),
+    })
+}
+
+fn increase_liquidity_accounts(
+    instruction: &'static str,
+    accounts: &[Pubkey],
+    v2: bool,
+) -> std::result::Result<IncreaseLiquidityAccounts, InstructionDecodeError> {
+    // v2 appends token_program_2022, vault_0_mint and vault_1_mint to the 12 accounts of v1.
+    let a = self::accounts(instruction, accounts, if v2 { 15 } else { 11 })?;
+    Ok(IncreaseLiquidityAccounts {
+        nft_owner: a[0],
+        nft_account: a[1],
+        pool_state: a[2],
+        protocol_position: a[3],
+        personal_position: a[4],
+        tick_array_lower: a[5],
+        tick_array_upper: a[6],
+        token_account_0: a[7],
+        token_account_1: a[8],
+        token_vault_0: a[9],
+        token_vault_1: a[10],
+        vault_0_mint: v2.then(|| a[13]),
+        vault_1_mint: v2.then(|| a[14]),
+    })
+}
+
+fn decrease_liquidity_accounts(
+    instruction: &'static str,
+    accounts: &[Pubkey],
+    v2: bool,
+) -> std::result::Result<DecreaseLiquidityAccounts, InstructionDecodeError> {
+    // v2 appends token_program_2022, memo_program, vault_0_mint and vault_1_mint to the 12
+    // accounts of v1. Reward accounts follow as remaining accounts.
+    let a = self::accounts(instruction, accounts, if v2 { 16 } else { 11 })?;
+    Ok(DecreaseLiquidityAccounts {
+        nft_owner: a[0],
+        nft_account: a[1],
+        personal_position: a[2],
+        pool_state: a[3],
+        protocol_position: a[4],
+        token_vault_0: a[5],
+        token_vault_1: a[6],
+        tick_array_lower: a[7],
+        tick_array_upper: a[8],
+        recipient_token_account_0: a[9],
+        recipient_token_account_1: a[10],
+        vault_0_mint: v2.then(|| a[14]),
+        vault_1_mint: v2.then(|| a[15]),
+    })
+}
+
+fn swap_accounts(
+    instruction: &'static str,
+    accounts: &[Pubkey],
+    v2: bool,
+) -> std::result::Result<SwapAccounts, InstructionDecodeError> {
+    // v1 passes token_program then the first tick array, v2 passes token_program,
+    // token_program_2022, memo_program and both vault mints. Tick arrays follow as remaining
+    // accounts.
+    let (a, tick_arrays) = if v2 {
+        let a = self::accounts(instruction, accounts, 13)?;
+        (a, &a[13..])
+    } else {
+        let a = self::accounts(instruction, accounts, 10)?;
+        (a, &a[9..])
+    };
+    Ok(SwapAccounts {
+        payer: a[0],
+        amm_config: a[1],
+        pool_state: a[2],
+        input_token_account: a[3],
+        output_token_account: a[4],
+        input_vault: a[5],
+        output_vault: a[6],
+        observation_state: a[7],
+        tick_arrays: tick_arrays.to_vec(),
+        input_vault_mint: v2.then(|| a[11]),
+        output_vault_mint: v2.then(|| a[12]),
+    })
+}
+
+fn collect_fee_accounts(
+    instruction: &'static str,
+    accounts: &[Pubkey],
+) -> std::result::Result<CollectFeeAccounts, InstructionDecodeError> {
+    let a = self::accounts(instruction, accounts, 9)?;
+    Ok(CollectFeeAccounts {
+        owner: a[0],
+        pool_state: a[1],
+        amm_config: a[2],
+        token_vault_0: a[3],
+        token_vault_1: a[4],
+        vault_0_mint: a[5],
+        vault_1_mint: a[6],
+        recipient_token_account_0: a[7],
+        recipient_token_account_1: a[8],
+    })
+}
+
+/// Decodes every Raydium CLMM instruction of a transaction, top-level and inner, in execution
+/// order. Instructions this module does not cover are skipped.
+pub fn decode_raydium_instructions(
+    transaction: &TransactionWrite,
+) -> std::result::Result<Vec<DecodedInstruction>, InstructionDecodeError> {
+    let mut decoded = Vec::new();
+    for instruction in transaction.instructions()? {
+        let instruction = instruction?;
+        if instruction.program_id.to_bytes() != RAYDIUM_CLMM_PROGRAM.to_bytes() {
+            continue;
+        }
+        let accounts: Vec<Pubkey> = instruction
+            .accounts
+            .iter()
+            .map(|account| Pubkey::new_from_array(account.to_bytes()))
+            .collect();
+        if let Some(raydium) = RaydiumCLMMInstruction::decode(&accounts, &instruction.data)? {
+            decoded.push(DecodedInstruction {
+                instruction: raydium,
+                instruction_index: instruction.top_level_index,
+                inner_instruction_index: instruction.inner_index,
+            });
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use anchor_lang::solana_program::hash::hash;
+    use base64::{engine::general_purpose, Engine};
+
+    use super::*;
+    use crate::chainstream::{
+        mock::fixtures,
+        types::transaction::{CompiledInstruction, Header, InnerInstructions},
+    };
+
+    fn data(discriminator: [u8; 8], args: impl AnchorSerialize) -> Vec<u8> {
+        [&discriminator[..], &args.try_to_vec().unwrap()].concat()
+    }
+
+    fn swap_args() -> SwapArgs {
+        SwapArgs {
+            amount: 1_000_000,
+            other_amount_threshold: 990_000,
+            sqrt_price_limit_x64: 0,
+            is_base_input: true,
+        }
+    }
+
+    #[test]
+    fn test_discriminators() {
+        for (name, discriminator) in [
+            ("create_pool", CREATE_POOL),
+            ("open_position", OPEN_POSITION),
+            ("open_position_v2", OPEN_POSITION_V2),
+            ("increase_liquidity", INCREASE_LIQUIDITY),
+            ("increase_liquidity_v2", INCREASE_LIQUIDITY_V2),
+            ("decrease_liquidity", DECREASE_LIQUIDITY),
+            ("decrease_liquidity_v2", DECREASE_LIQUIDITY_V2),
+            ("swap", SWAP),
+            ("swap_v2", SWAP_V2),
+            ("collect_protocol_fee", COLLECT_PROTOCOL_FEE),
+            ("collect_fund_fee", COLLECT_FUND_FEE),
+        ] {
+            let hash = hash(format!("global:{name}").as_bytes());
+            assert_eq!(hash.to_bytes()[..8], discriminator, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_decode_swap() {
+        let accounts: Vec<Pubkey> = (0..15).map(|_| Pubkey::new_unique()).collect();
+
+        let decoded = RaydiumCLMMInstruction::decode(&accounts, &data(SWAP_V2, swap_args()))
+            .unwrap()
+            .unwrap();
+        let RaydiumCLMMInstruction::SwapV2 { args, accounts: a } = &decoded else {
+            panic!("unexpected instruction: {decoded:?}");
+        };
+        assert_eq!(*args, swap_args());
+        assert_eq!(a.pool_state, accounts[2]);
+        assert_eq!((a.input_vault, a.output_vault), (accounts[5], accounts[6]));
+        assert_eq!(a.output_vault_mint, Some(accounts[12]));
+        assert_eq!(a.tick_arrays, accounts[13..]);
+        assert_eq!(decoded.pool_state(), accounts[2]);
+
+        let decoded = RaydiumCLMMInstruction::decode(&accounts[..11], &data(SWAP, swap_args()))
+            .unwrap()
+            .unwrap();
+        let RaydiumCLMMInstruction::Swap { accounts: a, .. } = decoded else {
+            panic!("unexpected instruction: {decoded:?}");
+        };
+        assert_eq!(a.tick_arrays, accounts[9..11]);
+        assert_eq!(a.input_vault_mint, None);
+
+        assert!(matches!(
+            RaydiumCLMMInstruction::decode(&accounts[..12], &data(SWAP_V2, swap_args())),
+            Err(InstructionDecodeError::MissingAccounts {
+                instruction: "swap_v2",
+                expected: 13,
+                actual: 12
+            })
+        ));
+        assert!(matches!(
+            RaydiumCLMMInstruction::decode(&accounts, &data(SWAP_V2, swap_args())[..20]),
+            Err(InstructionDecodeError::InvalidArgs { .. })
+        ));
+        // Other instructions and event self-invocations are skipped.
+        assert!(RaydiumCLMMInstruction::decode(&accounts, &[1, 2, 3])
+            .unwrap()
+            .is_none());
+        assert!(
+            RaydiumCLMMInstruction::decode(&accounts, &data([0; 8], swap_args()))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_decode_raydium_instructions() {
+        let raydium = RAYDIUM_CLMM_PROGRAM.to_string();
+        let keys: Vec<String> = (0..12).map(|_| Pubkey::new_unique().to_string()).collect();
+        let mut all: Vec<&str> = keys.iter().map(String::as_str).collect();
+        all.push(&raydium);
+        let mut transaction = fixtures::transaction(7, fixtures::signature(3), &all, vec![]);
+
+        let instruction = |data: Vec<u8>| CompiledInstruction {
+            program_id_index: 12,
+            accounts: (0..12).collect(),
+            data: general_purpose::STANDARD.encode(data),
+            stack_height: None,
+        };
+        let args = DecreaseLiquidityArgs {
+            liquidity: 5,
+            amount_0_min: 1,
+            amount_1_min: 2,
+        };
+        let message = transaction
+            .value
+            .transaction
+            .as_mut()
+            .unwrap()
+            .message
+            .as_mut()
+            .unwrap();
+        message.header = Some(Header {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 1,
+        });
+        message.instructions = vec![
+            instruction(data(SWAP, swap_args())),
+            instruction(data(DECREASE_LIQUIDITY, args.clone())),
+        ];
+        transaction.value.meta.as_mut().unwrap().inner_instructions = vec![InnerInstructions {
+            index: 0,
+            instructions: vec![
+                instruction(data([7; 8], ())),
+                instruction(data(SWAP, swap_args())),
+            ],
+        }];
+
+        let decoded = decode_raydium_instructions(&transaction).unwrap();
+        let summary: Vec<_> = decoded
+            .iter()
+            .map(|d| (d.instruction_index, d.inner_instruction_index))
+            .collect();
+        assert_eq!(summary, [(0, None), (0, Some(1)), (1, None)]);
+        assert!(matches!(
+            &decoded[2].instruction,
+            RaydiumCLMMInstruction::DecreaseLiquidity { args: decoded, accounts }
+                if *decoded == args && accounts.pool_state.to_string() == keys[3]
+        ));
+    }
+}
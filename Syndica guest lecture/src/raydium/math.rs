@@ -0,0 +1,720 @@
+//! Q64.64 price and tick math of the Raydium CLMM program.
+//!
+//! Prices are stored as `sqrt_price_x64`, the square root of the price of token_0 in token_1 as a
+//! Q64.64 fixed-point number, in raw token units. A tick `i` has the price `1.0001^i`.
+//!
+//! The integer functions were lifted from the Raydium program source code, which can be found
+//! [here](https://github.com/raydium-io/raydium-clmm/tree/master/programs/amm/src/libraries), and
+//! round the same way so amounts match the ones the program computes. The `f64` helpers are
+//! meant for display and analytics only.
+use super::anchor_events::SwapEvent;
+
+pub use big_num::{U128, U256, U512};
+
+mod big_num {
+    // The lints fire on the code generated by the macro.
+    #![allow(clippy::all)]
+    use uint::construct_uint;
+
+    construct_uint! {
+        pub struct U128(2);
+    }
+    construct_uint! {
+        pub struct U256(4);
+    }
+    construct_uint! {
+        pub struct U512(8);
+    }
+}
+
+/// The minimum tick, the log base 1.0001 of 2^-128.
+pub const MIN_TICK: i32 = -443636;
+/// The maximum tick, the log base 1.0001 of 2^128.
+pub const MAX_TICK: i32 = -MIN_TICK;
+/// `get_sqrt_price_at_tick(MIN_TICK)`
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+/// `get_sqrt_price_at_tick(MAX_TICK)`
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+/// 1.0 as a Q64.64.
+pub const Q64: u128 = 1 << 64;
+/// Fee rates are denominated in hundredths of a bip.
+pub const FEE_RATE_DENOMINATOR_VALUE: u32 = 1_000_000;
+
+const RESOLUTION: u32 = 64;
+/// Number of fractional bits of the log2 approximation in `get_tick_at_sqrt_price`.
+const BIT_PRECISION: u32 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, thiserror::Error)]
+pub enum MathError {
+    #[error("Tick {0} is out of range")]
+    TickOutOfRange(i32),
+    #[error("Sqrt price {0} is out of range")]
+    SqrtPriceOutOfRange(u128),
+    #[error("Token amount does not fit in a u64")]
+    MaxTokenOverflow,
+    #[error("Arithmetic overflow")]
+    Overflow,
+    #[error("Liquidity and sqrt price must be positive")]
+    Zero,
+    #[error("Fee rate {0} is not below 1_000_000")]
+    InvalidFeeRate(u32),
+}
+
+fn widen(value: U256) -> U512 {
+    let mut words = [0; 8];
+    words[..4].copy_from_slice(&value.0);
+    U512(words)
+}
+
+fn narrow(value: U512) -> Result<U256, MathError> {
+    if value.0[4..].iter().any(|word| *word != 0) {
+        return Err(MathError::Overflow);
+    }
+    Ok(U256(value.0[..4].try_into().unwrap()))
+}
+
+fn mul_div_floor(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::Overflow);
+    }
+    narrow(widen(a) * widen(b) / widen(denominator))
+}
+
+fn mul_div_ceil(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::Overflow);
+    }
+    let (result, remainder) = (widen(a) * widen(b)).div_mod(widen(denominator));
+    if remainder.is_zero() {
+        narrow(result)
+    } else {
+        narrow(result + 1)
+    }
+}
+
+fn div_rounding_up(a: U256, b: U256) -> U256 {
+    let (result, remainder) = a.div_mod(b);
+    if remainder.is_zero() {
+        result
+    } else {
+        result + 1
+    }
+}
+
+fn to_u64(amount: U256) -> Result<u64, MathError> {
+    if amount > U256::from(u64::MAX) {
+        return Err(MathError::MaxTokenOverflow);
+    }
+    Ok(amount.as_u64())
+}
+
+fn to_u128(sqrt_price_x64: U256) -> Result<u128, MathError> {
+    if sqrt_price_x64 > U256::from(u128::MAX) {
+        return Err(MathError::Overflow);
+    }
+    Ok(sqrt_price_x64.as_u128())
+}
+
+/// `sqrt(1.0001^tick)` as a Q64.64.
+pub fn get_sqrt_price_at_tick(tick: i32) -> Result<u128, MathError> {
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        return Err(MathError::TickOutOfRange(tick));
+    }
+
+    // `1 / sqrt(1.0001)^(2^i)` as a Q64.64, for every bit `i` of the tick.
+    const RATIOS: [u64; 19] = [
+        0xfffcb933bd6fb800,
+        0xfff97272373d4000,
+        0xfff2e50f5f657000,
+        0xffe5caca7e10f000,
+        0xffcb9843d60f7000,
+        0xff973b41fa98e800,
+        0xff2ea16466c9b000,
+        0xfe5dee046a9a3800,
+        0xfcbe86c7900bb000,
+        0xf987a7253ac65800,
+        0xf3392b0822bb6000,
+        0xe7159475a2caf000,
+        0xd097f3bdfd2f2000,
+        0xa9f746462d9f8000,
+        0x70d869a156f31c00,
+        0x31be135f97ed3200,
+        0x9aa508b5b85a500,
+        0x5d6af8dedc582c,
+        0x2216e584f5fa,
+    ];
+    let mut ratio = if abs_tick & 0x1 != 0 {
+        U128([RATIOS[0], 0])
+    } else {
+        U128([0, 1])
+    };
+    for (i, factor) in RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * U128([*factor, 0])) >> RESOLUTION;
+        }
+    }
+
+    // Divide to obtain sqrt(1.0001^tick) from 1/sqrt(1.0001^tick)
+    if tick > 0 {
+        ratio = U128::MAX / ratio;
+    }
+    Ok(ratio.as_u128())
+}
+
+/// The greatest tick whose sqrt price is at most `sqrt_price_x64`.
+pub fn get_tick_at_sqrt_price(sqrt_price_x64: u128) -> Result<i32, MathError> {
+    // The price can never reach the price at the max tick.
+    if !(MIN_SQRT_PRICE_X64..MAX_SQRT_PRICE_X64).contains(&sqrt_price_x64) {
+        return Err(MathError::SqrtPriceOutOfRange(sqrt_price_x64));
+    }
+
+    // Integer part of log2(sqrt_price), from the most significant bit.
+    let msb: u32 = 128 - sqrt_price_x64.leading_zeros() - 1;
+    let log2p_integer_x32 = (msb as i128 - 64) << 32;
+
+    // Fractional part, one bit per iteration: squaring r, as a Q1.63 in [1, 2), doubles its log2,
+    // so the next bit is set whenever r^2 reaches 2.
+    let mut bit: i128 = 0x8000_0000_0000_0000i128;
+    let mut precision = 0;
+    let mut log2p_fraction_x64 = 0;
+    let mut r = if msb >= 64 {
+        sqrt_price_x64 >> (msb - 63)
+    } else {
+        sqrt_price_x64 << (63 - msb)
+    };
+    while bit > 0 && precision < BIT_PRECISION {
+        r *= r;
+        let is_r_more_than_two = (r >> 127) as u32;
+        r >>= 63 + is_r_more_than_two;
+        log2p_fraction_x64 += bit * is_r_more_than_two as i128;
+        bit >>= 1;
+        precision += 1;
+    }
+    let log2p_fraction_x32 = log2p_fraction_x64 >> 32;
+    let log2p_x32 = log2p_integer_x32 + log2p_fraction_x32;
+
+    // Change of base: multiply by 2^32 / log2(sqrt(1.0001)).
+    let log_sqrt_10001_x64 = log2p_x32 * 59543866431248i128;
+
+    // The approximation error is below one tick, so the tick is one of these two.
+    let tick_low = ((log_sqrt_10001_x64 - 184467440737095516i128) >> 64) as i32;
+    let tick_high = ((log_sqrt_10001_x64 + 15793534762490258745i128) >> 64) as i32;
+
+    Ok(if tick_low == tick_high {
+        tick_low
+    } else if get_sqrt_price_at_tick(tick_high)? <= sqrt_price_x64 {
+        tick_high
+    } else {
+        tick_low
+    })
+}
+
+/// Amount of token_0 between two prices for `liquidity`,
+/// `liquidity * (sqrt_b - sqrt_a) / (sqrt_a * sqrt_b)`.
+pub fn get_delta_amount_0_unsigned(
+    mut sqrt_ratio_a_x64: u128,
+    mut sqrt_ratio_b_x64: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u64, MathError> {
+    if sqrt_ratio_a_x64 > sqrt_ratio_b_x64 {
+        std::mem::swap(&mut sqrt_ratio_a_x64, &mut sqrt_ratio_b_x64);
+    }
+    if sqrt_ratio_a_x64 == 0 {
+        return Err(MathError::Zero);
+    }
+    let numerator_1 = U256::from(liquidity) << RESOLUTION;
+    let numerator_2 = U256::from(sqrt_ratio_b_x64 - sqrt_ratio_a_x64);
+
+    let result = if round_up {
+        div_rounding_up(
+            mul_div_ceil(numerator_1, numerator_2, U256::from(sqrt_ratio_b_x64))?,
+            U256::from(sqrt_ratio_a_x64),
+        )
+    } else {
+        mul_div_floor(numerator_1, numerator_2, U256::from(sqrt_ratio_b_x64))?
+            / U256::from(sqrt_ratio_a_x64)
+    };
+    to_u64(result)
+}
+
+/// Amount of token_1 between two prices for `liquidity`, `liquidity * (sqrt_b - sqrt_a)`.
+pub fn get_delta_amount_1_unsigned(
+    mut sqrt_ratio_a_x64: u128,
+    mut sqrt_ratio_b_x64: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Result<u64, MathError> {
+    if sqrt_ratio_a_x64 > sqrt_ratio_b_x64 {
+        std::mem::swap(&mut sqrt_ratio_a_x64, &mut sqrt_ratio_b_x64);
+    }
+    let liquidity = U256::from(liquidity);
+    let delta = U256::from(sqrt_ratio_b_x64 - sqrt_ratio_a_x64);
+    let result = if round_up {
+        mul_div_ceil(liquidity, delta, U256::from(Q64))?
+    } else {
+        mul_div_floor(liquidity, delta, U256::from(Q64))?
+    };
+    to_u64(result)
+}
+
+/// The price after adding or removing `amount` of token_0, rounded up so the pool never gives
+/// out more than it should.
+fn get_next_sqrt_price_from_amount_0_rounding_up(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    amount: u64,
+    add: bool,
+) -> Result<u128, MathError> {
+    if amount == 0 {
+        return Ok(sqrt_price_x64);
+    }
+    let sqrt_price = U256::from(sqrt_price_x64);
+    let numerator_1 = U256::from(liquidity) << RESOLUTION;
+    let product = U256::from(amount) * sqrt_price;
+
+    if add {
+        let denominator = numerator_1 + product;
+        to_u128(mul_div_ceil(numerator_1, sqrt_price, denominator)?)
+    } else {
+        let denominator = numerator_1
+            .checked_sub(product)
+            .filter(|denominator| !denominator.is_zero())
+            .ok_or(MathError::Overflow)?;
+        to_u128(mul_div_ceil(numerator_1, sqrt_price, denominator)?)
+    }
+}
+
+/// The price after adding or removing `amount` of token_1, rounded down.
+fn get_next_sqrt_price_from_amount_1_rounding_down(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    amount: u64,
+    add: bool,
+) -> Result<u128, MathError> {
+    let amount = U256::from(amount) << RESOLUTION;
+    if add {
+        let quotient = to_u128(amount / U256::from(liquidity))?;
+        sqrt_price_x64
+            .checked_add(quotient)
+            .ok_or(MathError::Overflow)
+    } else {
+        let quotient = to_u128(div_rounding_up(amount, U256::from(liquidity)))?;
+        sqrt_price_x64
+            .checked_sub(quotient)
+            .ok_or(MathError::Overflow)
+    }
+}
+
+/// The price after swapping `amount_in` into the pool.
+pub fn get_next_sqrt_price_from_input(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    amount_in: u64,
+    zero_for_one: bool,
+) -> Result<u128, MathError> {
+    if sqrt_price_x64 == 0 || liquidity == 0 {
+        return Err(MathError::Zero);
+    }
+    if zero_for_one {
+        get_next_sqrt_price_from_amount_0_rounding_up(sqrt_price_x64, liquidity, amount_in, true)
+    } else {
+        get_next_sqrt_price_from_amount_1_rounding_down(sqrt_price_x64, liquidity, amount_in, true)
+    }
+}
+
+/// The price after taking `amount_out` out of the pool.
+pub fn get_next_sqrt_price_from_output(
+    sqrt_price_x64: u128,
+    liquidity: u128,
+    amount_out: u64,
+    zero_for_one: bool,
+) -> Result<u128, MathError> {
+    if sqrt_price_x64 == 0 || liquidity == 0 {
+        return Err(MathError::Zero);
+    }
+    if zero_for_one {
+        get_next_sqrt_price_from_amount_1_rounding_down(
+            sqrt_price_x64,
+            liquidity,
+            amount_out,
+            false,
+        )
+    } else {
+        get_next_sqrt_price_from_amount_0_rounding_up(sqrt_price_x64, liquidity, amount_out, false)
+    }
+}
+
+/// Result of swapping within a single tick range.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SwapStep {
+    /// The price after the step, `sqrt_price_target_x64` if the step consumed the whole range.
+    pub sqrt_price_next_x64: u128,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Charged on top of `amount_in`.
+    pub fee_amount: u64,
+}
+
+/// Amount needed to move the price to the target, input if `is_base_input` and output otherwise.
+/// `None` if it does not fit in a u64, i.e. the step ends before the target.
+fn calculate_amount_in_range(
+    sqrt_price_current_x64: u128,
+    sqrt_price_target_x64: u128,
+    liquidity: u128,
+    zero_for_one: bool,
+    is_base_input: bool,
+) -> Result<Option<u64>, MathError> {
+    let result = match (is_base_input, zero_for_one) {
+        (true, true) => get_delta_amount_0_unsigned(
+            sqrt_price_target_x64,
+            sqrt_price_current_x64,
+            liquidity,
+            true,
+        ),
+        (true, false) => get_delta_amount_1_unsigned(
+            sqrt_price_current_x64,
+            sqrt_price_target_x64,
+            liquidity,
+            true,
+        ),
+        (false, true) => get_delta_amount_1_unsigned(
+            sqrt_price_target_x64,
+            sqrt_price_current_x64,
+            liquidity,
+            false,
+        ),
+        (false, false) => get_delta_amount_0_unsigned(
+            sqrt_price_current_x64,
+            sqrt_price_target_x64,
+            liquidity,
+            false,
+        ),
+    };
+    match result {
+        Ok(amount) => Ok(Some(amount)),
+        Err(MathError::MaxTokenOverflow) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+/// Swaps `amount_remaining` within one tick range, from the current price towards the target
+/// price, with `fee_rate` in hundredths of a bip. `amount_remaining` is the input amount fees
+/// included if `is_base_input`, the output amount otherwise.
+pub fn compute_swap_step(
+    sqrt_price_current_x64: u128,
+    sqrt_price_target_x64: u128,
+    liquidity: u128,
+    amount_remaining: u64,
+    fee_rate: u32,
+    is_base_input: bool,
+    zero_for_one: bool,
+) -> Result<SwapStep, MathError> {
+    if fee_rate >= FEE_RATE_DENOMINATOR_VALUE {
+        return Err(MathError::InvalidFeeRate(fee_rate));
+    }
+    let mut step = SwapStep::default();
+    let in_range = calculate_amount_in_range(
+        sqrt_price_current_x64,
+        sqrt_price_target_x64,
+        liquidity,
+        zero_for_one,
+        is_base_input,
+    )?;
+    if is_base_input {
+        let amount_remaining_less_fee =
+            (amount_remaining as u128 * u128::from(FEE_RATE_DENOMINATOR_VALUE - fee_rate)
+                / u128::from(FEE_RATE_DENOMINATOR_VALUE)) as u64;
+        step.amount_in = in_range.unwrap_or_default();
+        step.sqrt_price_next_x64 = match in_range {
+            Some(amount_in) if amount_remaining_less_fee >= amount_in => sqrt_price_target_x64,
+            _ => get_next_sqrt_price_from_input(
+                sqrt_price_current_x64,
+                liquidity,
+                amount_remaining_less_fee,
+                zero_for_one,
+            )?,
+        };
+    } else {
+        step.amount_out = in_range.unwrap_or_default();
+        step.sqrt_price_next_x64 = match in_range {
+            Some(amount_out) if amount_remaining >= amount_out => sqrt_price_target_x64,
+            _ => get_next_sqrt_price_from_output(
+                sqrt_price_current_x64,
+                liquidity,
+                amount_remaining,
+                zero_for_one,
+            )?,
+        };
+    }
+
+    // Recompute the amounts that were not fixed by reaching the target.
+    let max = sqrt_price_target_x64 == step.sqrt_price_next_x64;
+    let next = step.sqrt_price_next_x64;
+    let current = sqrt_price_current_x64;
+    if zero_for_one {
+        if !max || !is_base_input {
+            step.amount_in = get_delta_amount_0_unsigned(next, current, liquidity, true)?;
+        }
+        if !max || is_base_input {
+            step.amount_out = get_delta_amount_1_unsigned(next, current, liquidity, false)?;
+        }
+    } else {
+        if !max || !is_base_input {
+            step.amount_in = get_delta_amount_1_unsigned(current, next, liquidity, true)?;
+        }
+        if !max || is_base_input {
+            step.amount_out = get_delta_amount_0_unsigned(current, next, liquidity, false)?;
+        }
+    }
+
+    if !is_base_input && step.amount_out > amount_remaining {
+        step.amount_out = amount_remaining;
+    }
+
+    step.fee_amount = if is_base_input && step.sqrt_price_next_x64 != sqrt_price_target_x64 {
+        // The whole remaining amount is used, what is not swapped is the fee.
+        amount_remaining - step.amount_in
+    } else {
+        let fee = mul_div_ceil(
+            U256::from(step.amount_in),
+            U256::from(fee_rate),
+            U256::from(FEE_RATE_DENOMINATOR_VALUE - fee_rate),
+        )?;
+        to_u64(fee)?
+    };
+    Ok(step)
+}
+
+/// `10^(decimals_0 - decimals_1)`, turns a raw price into a price in whole tokens.
+fn decimals_factor(decimals_0: u8, decimals_1: u8) -> f64 {
+    10f64.powi(i32::from(decimals_0) - i32::from(decimals_1))
+}
+
+/// Price of one whole token_0 in token_1.
+pub fn sqrt_price_x64_to_price(sqrt_price_x64: u128, decimals_0: u8, decimals_1: u8) -> f64 {
+    let sqrt_price = sqrt_price_x64 as f64 / Q64 as f64;
+    sqrt_price * sqrt_price * decimals_factor(decimals_0, decimals_1)
+}
+
+/// Inverse of [`sqrt_price_x64_to_price`], exact up to `f64` precision.
+pub fn price_to_sqrt_price_x64(price: f64, decimals_0: u8, decimals_1: u8) -> u128 {
+    ((price / decimals_factor(decimals_0, decimals_1)).sqrt() * Q64 as f64) as u128
+}
+
+/// Price of one whole token_0 in token_1 at `tick`.
+pub fn tick_to_price(tick: i32, decimals_0: u8, decimals_1: u8) -> f64 {
+    1.0001f64.powi(tick) * decimals_factor(decimals_0, decimals_1)
+}
+
+/// The greatest tick whose price is at most `price`, a price of one whole token_0 in token_1.
+pub fn price_to_tick(price: f64, decimals_0: u8, decimals_1: u8) -> Result<i32, MathError> {
+    let sqrt_price_x64 = price_to_sqrt_price_x64(price, decimals_0, decimals_1);
+    get_tick_at_sqrt_price(sqrt_price_x64)
+}
+
+/// Average price of one whole token_0 in token_1 paid or received in a swap of raw amounts.
+pub fn execution_price(amount_0: u64, amount_1: u64, decimals_0: u8, decimals_1: u8) -> f64 {
+    amount_1 as f64 / amount_0 as f64 * decimals_factor(decimals_0, decimals_1)
+}
+
+/// Relative distance of the execution price from the spot price before the swap, e.g. 0.01 for
+/// an execution 1% worse than spot. Negative if the execution was better than spot.
+pub fn price_impact(spot_price: f64, execution_price: f64, zero_for_one: bool) -> f64 {
+    if zero_for_one {
+        // Selling token_0: a lower price is worse.
+        1.0 - execution_price / spot_price
+    } else {
+        execution_price / spot_price - 1.0
+    }
+}
+
+impl SwapEvent {
+    /// Average price of one whole token_0 in token_1, transfer fees excluded.
+    pub fn execution_price(&self, decimals_0: u8, decimals_1: u8) -> f64 {
+        execution_price(self.amount_0, self.amount_1, decimals_0, decimals_1)
+    }
+
+    /// Price impact of the swap, given the pool price before it, see [`price_impact`].
+    pub fn price_impact(&self, sqrt_price_before_x64: u128) -> f64 {
+        let spot = sqrt_price_x64_to_price(sqrt_price_before_x64, 0, 0);
+        price_impact(spot, self.execution_price(0, 0), self.zero_for_one)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn test_tick_bounds() {
+        assert_eq!(get_sqrt_price_at_tick(0).unwrap(), Q64);
+        assert_eq!(
+            get_sqrt_price_at_tick(MIN_TICK).unwrap(),
+            MIN_SQRT_PRICE_X64
+        );
+        assert_eq!(
+            get_sqrt_price_at_tick(MAX_TICK).unwrap(),
+            MAX_SQRT_PRICE_X64
+        );
+        assert_eq!(
+            get_sqrt_price_at_tick(MAX_TICK + 1),
+            Err(MathError::TickOutOfRange(MAX_TICK + 1))
+        );
+        assert_eq!(
+            get_tick_at_sqrt_price(MIN_SQRT_PRICE_X64).unwrap(),
+            MIN_TICK
+        );
+        assert_eq!(
+            get_tick_at_sqrt_price(MAX_SQRT_PRICE_X64 - 1).unwrap(),
+            MAX_TICK - 1
+        );
+        assert!(get_tick_at_sqrt_price(MAX_SQRT_PRICE_X64).is_err());
+    }
+
+    #[test]
+    fn test_prices() {
+        // SOL (9 decimals) / USDC (6 decimals) at 150 USDC per SOL.
+        let sqrt_price_x64 = price_to_sqrt_price_x64(150.0, 9, 6);
+        let price = sqrt_price_x64_to_price(sqrt_price_x64, 9, 6);
+        assert!((price - 150.0).abs() < 1e-9);
+
+        let tick = price_to_tick(150.0, 9, 6).unwrap();
+        assert_eq!(tick, -18973);
+        assert!(tick_to_price(tick, 9, 6) <= 150.0);
+        assert!(tick_to_price(tick + 1, 9, 6) > 150.0);
+
+        // Sold 2 SOL for 298.5 USDC.
+        let execution = execution_price(2_000_000_000, 298_500_000, 9, 6);
+        assert!((execution - 149.25).abs() < 1e-9);
+        assert!((price_impact(150.0, execution, true) - 0.005).abs() < 1e-9);
+        assert!((price_impact(150.0, 151.5, false) - 0.01).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_swap_step() {
+        // 1% fee, reaching the target before the input runs out.
+        let current = Q64;
+        let target = get_sqrt_price_at_tick(-10).unwrap();
+        let step = compute_swap_step(
+            current,
+            target,
+            1_000_000_000,
+            u64::MAX / 4,
+            10_000,
+            true,
+            true,
+        )
+        .unwrap();
+        assert_eq!(step.sqrt_price_next_x64, target);
+        assert_eq!(
+            step.amount_in,
+            get_delta_amount_0_unsigned(target, current, 1_000_000_000, true).unwrap()
+        );
+        assert_eq!(
+            step.fee_amount,
+            (step.amount_in as u128 * 10_000).div_ceil(990_000) as u64
+        );
+
+        // Exact output that does not reach the target.
+        let step = compute_swap_step(current, target, 1 << 40, 1_000, 2_500, false, true).unwrap();
+        assert_eq!(step.amount_out, 1_000);
+        assert!(step.sqrt_price_next_x64 < current && step.sqrt_price_next_x64 > target);
+    }
+
+    fn sqrt_price() -> impl Strategy<Value = u128> {
+        MIN_SQRT_PRICE_X64..MAX_SQRT_PRICE_X64
+    }
+
+    proptest! {
+        #[test]
+        fn test_tick_round_trip(tick in MIN_TICK..=MAX_TICK) {
+            let sqrt_price_x64 = get_sqrt_price_at_tick(tick).unwrap();
+            if tick < MAX_TICK {
+                prop_assert_eq!(get_tick_at_sqrt_price(sqrt_price_x64).unwrap(), tick);
+                prop_assert!(get_sqrt_price_at_tick(tick + 1).unwrap() > sqrt_price_x64);
+            }
+        }
+
+        #[test]
+        fn test_tick_at_sqrt_price_is_floor(sqrt_price_x64 in sqrt_price()) {
+            let tick = get_tick_at_sqrt_price(sqrt_price_x64).unwrap();
+            prop_assert!(get_sqrt_price_at_tick(tick).unwrap() <= sqrt_price_x64);
+            prop_assert!(get_sqrt_price_at_tick(tick + 1).unwrap() > sqrt_price_x64);
+        }
+
+        #[test]
+        fn test_delta_rounding(
+            a in sqrt_price(),
+            b in sqrt_price(),
+            liquidity in 1u128..u64::MAX as u128,
+        ) {
+            for delta in [get_delta_amount_0_unsigned, get_delta_amount_1_unsigned] {
+                if let (Ok(down), Ok(up)) = (delta(a, b, liquidity, false), delta(a, b, liquidity, true)) {
+                    prop_assert!(up == down || up == down + 1);
+                }
+            }
+        }
+
+        #[test]
+        fn test_swap_step_bounds(
+            current_tick in -200_000i32..200_000,
+            ticks in 1i32..2_000,
+            liquidity in 1u128..1 << 80,
+            amount_remaining in 1u64..u64::MAX / 2,
+            fee_rate in 0u32..100_000,
+            is_base_input: bool,
+            zero_for_one: bool,
+        ) {
+            let current = get_sqrt_price_at_tick(current_tick).unwrap();
+            let target_tick = if zero_for_one { current_tick - ticks } else { current_tick + ticks };
+            let target = get_sqrt_price_at_tick(target_tick).unwrap();
+            let step = compute_swap_step(
+                current, target, liquidity, amount_remaining, fee_rate, is_base_input, zero_for_one,
+            );
+            // Skip steps whose amounts do not fit in a u64, the program fails those too.
+            let Ok(step) = step else { return Ok(()) };
+
+            let next = step.sqrt_price_next_x64;
+            if zero_for_one {
+                prop_assert!(target <= next && next <= current);
+            } else {
+                prop_assert!(current <= next && next <= target);
+            }
+            if is_base_input {
+                prop_assert!(step.amount_in as u128 + step.fee_amount as u128 <= amount_remaining as u128);
+            } else {
+                prop_assert!(step.amount_out <= amount_remaining);
+            }
+            // Rounding favors the pool: the input covers the price move, the output does not
+            // exceed it.
+            let (amount_in, amount_out) = if zero_for_one {
+                (
+                    get_delta_amount_0_unsigned(next, current, liquidity, false).unwrap(),
+                    get_delta_amount_1_unsigned(next, current, liquidity, true).unwrap(),
+                )
+            } else {
+                (
+                    get_delta_amount_1_unsigned(current, next, liquidity, false).unwrap(),
+                    get_delta_amount_0_unsigned(current, next, liquidity, true).unwrap(),
+                )
+            };
+            prop_assert!(step.amount_in >= amount_in);
+            prop_assert!(step.amount_out <= amount_out);
+        }
+
+        #[test]
+        fn test_price_round_trip(tick in -300_000i32..300_000, decimals_0 in 0u8..12, decimals_1 in 0u8..12) {
+            let sqrt_price_x64 = get_sqrt_price_at_tick(tick).unwrap();
+            let from_sqrt = sqrt_price_x64_to_price(sqrt_price_x64, decimals_0, decimals_1);
+            let from_tick = tick_to_price(tick, decimals_0, decimals_1);
+            prop_assert!((from_sqrt / from_tick - 1.0).abs() < 1e-9);
+
+            let back = price_to_sqrt_price_x64(from_sqrt, decimals_0, decimals_1);
+            prop_assert!((back as f64 / sqrt_price_x64 as f64 - 1.0).abs() < 1e-12);
+        }
+    }
+}
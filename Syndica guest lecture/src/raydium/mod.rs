@@ -1,2 +1,7 @@
 pub mod anchor_events;
+pub mod book;
+pub mod instructions;
+pub mod math;
 pub mod parse;
+pub mod simulate;
+pub mod state;
@@ -2,231 +2,932 @@
 //!
 //! This code was mostly adapted from raydium-io's raydium-clmm repository:
 //! https://github.com/raydium-io/raydium-clmm/blob/master/client/src/instructions/events_instructions_parse.rs
+//!
+//! Anchor programs emit events as `Program data: <base64>` log lines. The line does not say which
+//! program wrote it, so the parser replays the `invoke [n]`, `success` and `failed` lines of the
+//! runtime to know which program is executing at any point, e.g. Raydium called by Jupiter.
 #![allow(unused)]
 
-use anchor_lang::{prelude::*, Discriminator};
-use anyhow::anyhow;
+use std::{collections::HashMap, str::FromStr, sync::OnceLock};
+
+use anchor_lang::{event::EVENT_IX_TAG_LE, prelude::*};
 use base64::Engine;
-use regex::Regex;
 
-use crate::chainstream::types::transaction::Meta;
+use crate::{
+    anchor::registry::{EventRegistry, ProgramEvents},
+    chainstream::{
+        resolve::ResolvedInstruction,
+        types::transaction::{Meta, TransactionWrite},
+    },
+};
 use base64::engine::general_purpose;
 
 use super::anchor_events::*;
 
-const PROGRAM_LOG: &str = "Program log: ";
 const PROGRAM_DATA: &str = "Program data: ";
+const LOG_TRUNCATED: &str = "Log truncated";
+
+/// An event decoded from a transaction, with the invocation that emitted it.
+#[derive(Debug, Clone)]
+pub struct ParsedEvent<E> {
+    pub event: E,
+    /// The program that emitted the event.
+    pub program: Pubkey,
+    /// The program that invoked `program` through CPI, `None` for a top-level instruction or if
+    /// the depth of a self-CPI event is unknown.
+    pub parent: Option<Pubkey>,
+    pub source: EventSource,
+    pub location: EventLocation,
+    /// Whether the transaction failed, so the event was reverted along with the rest of it. Read
+    /// from the transaction error in the meta; if the meta reports none, whether the invocation
+    /// that emitted the event, or one of its callers, logged a failure.
+    pub failed: bool,
+}
 
-const RAYDIUM_CLMM_PROGRAM: &'static str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+/// How an event was emitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EventSource {
+    /// A `Program data:` log line, written by `emit!`.
+    Log,
+    /// The data of an inner instruction the program sent to itself, written by `emit_cpi!`.
+    Cpi,
+}
+
+/// Where in a transaction an event was emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventLocation {
+    pub signature: String,
+    pub slot: u64,
+    /// Index of the top-level instruction that emitted the event, directly or through CPI.
+    pub instruction_index: usize,
+    /// Position in the `Meta.inner_instructions` group of `instruction_index` of the CPI that
+    /// emitted the event, or of the self-CPI carrying it. `None` if the top-level instruction
+    /// logged it.
+    pub inner_instruction_index: Option<usize>,
+    /// Invoke depth of the program that logged the event, 1 for a top-level instruction, or of the
+    /// self-CPI carrying it. `None` if neither ChainStream nor the logs report the depth of a
+    /// self-CPI, and it can't be derived from the instruction that made it.
+    pub stack_height: Option<usize>,
+    /// Index in `Meta.log_messages` of the `Program data:` line, or of the `invoke` line of the
+    /// self-CPI. `None` if the logs were truncated before it.
+    pub log_index: Option<usize>,
+}
 
 /// Top-level event parser. Returns a list of parsed events (if any).
-pub fn parse_raydium_anchor_events(meta: Meta) -> Result<Vec<RaydiumCLMMEvent>> {
-    let mut parsed_events = Vec::new();
-    let mut logs = &meta.log_messages[..];
-
-    if logs.is_empty() {
-        println!("log is empty");
-        return Ok(parsed_events); // Return empty if no logs
-    }
-
-    if let Ok(mut execution) = Execution::new(&mut logs) {
-        for l in logs {
-            let (new_program, did_pop, maybe_event) =
-                if !execution.is_empty() && RAYDIUM_CLMM_PROGRAM == execution.program() {
-                    // Current program log
-                    handle_program_log(RAYDIUM_CLMM_PROGRAM, l, true)?
-                } else {
-                    // Possibly a system/cpi log
-                    let (program, did_pop) = handle_system_log(RAYDIUM_CLMM_PROGRAM, l);
-                    (program, did_pop, None)
-                };
+pub fn parse_raydium_anchor_events(
+    transaction: &TransactionWrite,
+) -> Result<Vec<ParsedEvent<RaydiumCLMMEvent>>> {
+    static REGISTRY: OnceLock<EventRegistry<RaydiumCLMMEvent>> = OnceLock::new();
+    parse_anchor_events(
+        transaction,
+        REGISTRY.get_or_init(RaydiumCLMMEvent::registry),
+    )
+}
 
-            // Collect any event found
-            if let Some(evt) = maybe_event {
-                parsed_events.push(evt);
-            }
+/// Parses the events of every program in `registry` from a transaction, in execution order. A
+/// transaction without meta has no events.
+///
+/// Events are read from the `Program data:` logs and from `emit_cpi!` self-invocations in
+/// `Meta.inner_instructions`. Log parsing stops at a `Log truncated` line, so events emitted after
+/// it are only found if they were emitted through CPI. An event found in both places is reported
+/// once, as a log event. Events of a failed transaction are returned with
+/// [`ParsedEvent::failed`] set.
+pub fn parse_anchor_events<E: 'static>(
+    transaction: &TransactionWrite,
+    registry: &EventRegistry<E>,
+) -> Result<Vec<ParsedEvent<E>>> {
+    let Some(meta) = &transaction.value.meta else {
+        return Ok(Vec::new());
+    };
+    let instructions = transaction
+        .instructions()
+        .ok()
+        .and_then(|instructions| {
+            instructions
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok()
+        })
+        .unwrap_or_default();
+    let programs = InstructionPrograms::new(&instructions);
+    let location =
+        |instruction_index, inner_instruction_index, stack_height, log_index| EventLocation {
+            signature: transaction.context.signature.clone(),
+            slot: transaction.value.slot,
+            instruction_index,
+            inner_instruction_index,
+            stack_height,
+            log_index,
+        };
 
-            // Switch program context on CPI
-            if let Some(new_program) = new_program {
-                execution.push(new_program);
-            }
+    // Events with their raw data, and whether a self-CPI duplicate was found for them.
+    let mut parsed_events: Vec<(ParsedEvent<E>, Vec<u8>, bool)> = Vec::new();
+    // Inner invocations seen in the logs, by position.
+    let mut invokes: HashMap<(usize, usize), Invoke> = HashMap::new();
+    let mut cursor = InstructionCursor::default();
+    let mut stack = CallStack::default();
+    // Log index ranges of the invocations that failed.
+    let mut failed = Vec::new();
 
-            // Program returned
-            if did_pop {
-                execution.pop();
+    for (log_index, l) in meta.log_messages.iter().enumerate() {
+        match LogLine::parse(l) {
+            LogLine::Invoke { program, depth } => {
+                let (instruction_index, inner_index) = cursor.invoke(&programs, &program, depth);
+                if let Some(inner_index) = inner_index {
+                    let invoke = Invoke {
+                        log_index,
+                        depth,
+                        caller_parent: stack.parent().map(|parent| parent.program),
+                    };
+                    invokes.insert((instruction_index, inner_index), invoke);
+                }
+                stack.invoke(Frame {
+                    program,
+                    depth,
+                    instruction_index,
+                    inner_index,
+                    log_index,
+                });
+            }
+            LogLine::Success { program } => {
+                stack.exit(program);
+            }
+            LogLine::Failed { program } => {
+                if let Some(frame) = stack.exit(program) {
+                    failed.push(frame.log_index..=log_index);
+                }
             }
+            LogLine::Data(data) => {
+                let Some(frame) = stack.current() else {
+                    continue;
+                };
+                if !registry.contains_program(&frame.program) {
+                    continue;
+                }
+                // Not every program writes base64 event data, skip what isn't.
+                let Ok(data) = general_purpose::STANDARD.decode(data) else {
+                    continue;
+                };
+                if let Some(event) = registry.decode(&frame.program, &data) {
+                    let event = ParsedEvent {
+                        event: event?,
+                        program: frame.program,
+                        parent: stack.parent().map(|parent| parent.program),
+                        source: EventSource::Log,
+                        location: location(
+                            frame.instruction_index,
+                            frame.inner_index,
+                            Some(frame.depth),
+                            Some(log_index),
+                        ),
+                        failed: false,
+                    };
+                    parsed_events.push((event, data, false));
+                }
+            }
+            LogLine::Truncated => break,
+            LogLine::Other => {}
+        }
+    }
+
+    for (position, instruction) in instructions.iter().enumerate() {
+        let Some(inner_index) = instruction.inner_index else {
+            continue;
+        };
+        let Some(data) = instruction.data.strip_prefix(&EVENT_IX_TAG_LE) else {
+            continue;
+        };
+        let program = Pubkey::new_from_array(instruction.program_id.to_bytes());
+        if !registry.contains_program(&program) {
+            continue;
         }
+        let Some(event) = registry.decode(&program, data) else {
+            continue;
+        };
+
+        let top_level_index = instruction.top_level_index;
+        let duplicate = parsed_events.iter_mut().find(|(logged, raw, paired)| {
+            !*paired
+                && logged.program == program
+                && logged.location.instruction_index == top_level_index
+                && raw == data
+        });
+        if let Some((_, _, paired)) = duplicate {
+            *paired = true;
+            continue;
+        }
+
+        // The self-CPI is invoked by `program`, whose own caller is the event's parent.
+        let invoke = invokes.get(&(top_level_index, inner_index));
+        let (stack_height, parent) = match (instruction.stack_height, invoke) {
+            (Some(height), _) => {
+                let height = height as usize;
+                let parent = caller_at(&instructions[..position], height.saturating_sub(2));
+                (Some(height), parent)
+            }
+            (None, Some(invoke)) => (Some(invoke.depth), invoke.caller_parent),
+            (None, None) => self_cpi_depth(&instructions[..position], &program)
+                .map_or((None, None), |(height, parent)| (Some(height), parent)),
+        };
+        let event = ParsedEvent {
+            event: event?,
+            program,
+            parent,
+            source: EventSource::Cpi,
+            location: location(
+                top_level_index,
+                Some(inner_index),
+                stack_height,
+                invoke.map(|invoke| invoke.log_index),
+            ),
+            failed: false,
+        };
+        parsed_events.push((event, data.to_vec(), false));
     }
 
+    // Every event of a failed transaction was reverted, even one whose failure is past a
+    // truncation. An error that can't be read still means the transaction failed.
+    let transaction_failed = !matches!(meta.transaction_error(), Ok(None));
+    let mut parsed_events: Vec<_> = parsed_events
+        .into_iter()
+        .map(|(mut event, _, _)| {
+            event.failed = transaction_failed
+                || event
+                    .location
+                    .log_index
+                    .is_some_and(|i| failed.iter().any(|range| range.contains(&i)));
+            event
+        })
+        .collect();
+    parsed_events.sort_by_key(|e| {
+        let location = &e.location;
+        (
+            location.log_index.unwrap_or(usize::MAX),
+            location.instruction_index,
+            location.inner_instruction_index,
+        )
+    });
     Ok(parsed_events)
 }
 
-// Minimal call stack simulation
-struct Execution {
-    stack: Vec<String>,
+/// An inner invocation as seen in the logs.
+#[derive(Debug, Clone, Copy)]
+struct Invoke {
+    log_index: usize,
+    depth: usize,
+    /// Parent of the invoking program.
+    caller_parent: Option<Pubkey>,
 }
 
-impl Execution {
-    pub fn new(logs: &mut &[String]) -> anyhow::Result<Self> {
-        // We require at least one log line to initialize
-        let l = &logs[0];
-        *logs = &logs[1..];
+/// Depth and parent of a self-CPI of `program` whose depth wasn't reported, derived from the last
+/// instruction of `program` in `preceding` that isn't a self-CPI itself: the one that made it.
+fn self_cpi_depth(
+    preceding: &[ResolvedInstruction],
+    program: &Pubkey,
+) -> Option<(usize, Option<Pubkey>)> {
+    let current = preceding.last()?.top_level_index;
+    let (position, caller) = preceding
+        .iter()
+        .enumerate()
+        .rev()
+        .take_while(|(_, instruction)| instruction.top_level_index == current)
+        .find(|(_, instruction)| {
+            instruction.program_id.to_bytes() == program.to_bytes()
+                && !instruction.data.starts_with(&EVENT_IX_TAG_LE)
+        })?;
+    let caller_height = match caller.inner_index {
+        None => 1,
+        Some(_) => caller.stack_height? as usize,
+    };
+    let parent = caller_at(&preceding[..position], caller_height - 1);
+    Some((caller_height + 1, parent))
+}
 
-        let re = Regex::new(r"^Program (.*) invoke.*$").unwrap();
-        let c = re
-            .captures(l)
-            .ok_or_else(|| anyhow!("log parse error: {l}"))?;
+/// The program executing at `stack_height` at the end of `preceding`, the instructions that ran
+/// before an inner instruction, using the stack heights ChainStream reports.
+fn caller_at(preceding: &[ResolvedInstruction], stack_height: usize) -> Option<Pubkey> {
+    if stack_height == 0 {
+        return None;
+    }
+    let current = preceding.last()?.top_level_index;
+    preceding
+        .iter()
+        .rev()
+        .take_while(|instruction| instruction.top_level_index == current)
+        .find(|instruction| match instruction.inner_index {
+            None => stack_height == 1,
+            Some(_) => instruction.stack_height == Some(stack_height as u32),
+        })
+        .map(|instruction| Pubkey::new_from_array(instruction.program_id.to_bytes()))
+}
 
-        let program = c
-            .get(1)
-            .ok_or_else(|| anyhow!("log parse error: {l}"))?
-            .as_str()
-            .to_string();
+/// Whether the runtime cut the logs short, dropping every line after the limit.
+pub fn logs_truncated(meta: &Meta) -> bool {
+    meta.log_messages.iter().any(|l| l == LOG_TRUNCATED)
+}
 
-        Ok(Self {
-            stack: vec![program],
-        })
+/// The log lines the parser cares about.
+#[derive(Debug, PartialEq)]
+enum LogLine<'a> {
+    /// `Program <id> invoke [<depth>]`
+    Invoke {
+        program: Pubkey,
+        depth: usize,
+    },
+    /// `Program <id> success`
+    Success {
+        program: Pubkey,
+    },
+    /// `Program <id> failed: <reason>`
+    Failed {
+        program: Pubkey,
+    },
+    /// `Program data: <base64>`
+    Data(&'a str),
+    Truncated,
+    /// Logs, return data, compute unit consumption and anything unrecognized.
+    Other,
+}
+
+impl<'a> LogLine<'a> {
+    fn parse(line: &'a str) -> Self {
+        if line == LOG_TRUNCATED {
+            return LogLine::Truncated;
+        }
+        if let Some(data) = line.strip_prefix(PROGRAM_DATA) {
+            return LogLine::Data(data);
+        }
+        let Some((program, rest)) = line
+            .strip_prefix("Program ")
+            .and_then(|line| line.split_once(' '))
+        else {
+            return LogLine::Other;
+        };
+        // `Program log: ...` and `Program return: ...` don't start with a program id.
+        let Ok(program) = Pubkey::from_str(program) else {
+            return LogLine::Other;
+        };
+
+        if let Some(depth) = rest
+            .strip_prefix("invoke [")
+            .and_then(|rest| rest.strip_suffix(']'))
+        {
+            return match depth.parse() {
+                Ok(depth) => LogLine::Invoke { program, depth },
+                Err(_) => LogLine::Other,
+            };
+        }
+        if rest == "success" {
+            return LogLine::Success { program };
+        }
+        if rest.starts_with("failed") {
+            return LogLine::Failed { program };
+        }
+        LogLine::Other
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    program: Pubkey,
+    depth: usize,
+    instruction_index: usize,
+    inner_index: Option<usize>,
+    /// Index of the `invoke` line.
+    log_index: usize,
+}
+
+/// The programs executing at the current log line, outermost first.
+#[derive(Debug, Default)]
+struct CallStack {
+    frames: Vec<Frame>,
+}
+
+impl CallStack {
+    fn invoke(&mut self, frame: Frame) {
+        // The depth is authoritative. If exit lines are missing, e.g. because a program aborted
+        // without logging, frames at or below the new depth are stale.
+        self.frames.retain(|f| f.depth < frame.depth.max(1));
+        self.frames.push(frame);
     }
 
-    pub fn program(&self) -> String {
-        assert!(!self.stack.is_empty());
-        self.stack[self.stack.len() - 1].clone()
+    /// Returns the frame that exited.
+    fn exit(&mut self, program: Pubkey) -> Option<Frame> {
+        // Unwind to the innermost frame of `program`, ignoring exits that match nothing.
+        let position = self.frames.iter().rposition(|f| f.program == program)?;
+        let frame = self.frames[position];
+        self.frames.truncate(position);
+        Some(frame)
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.stack.is_empty()
+    fn current(&self) -> Option<Frame> {
+        self.frames.last().copied()
+    }
+
+    fn parent(&self) -> Option<Frame> {
+        self.frames.iter().rev().nth(1).copied()
+    }
+}
+
+/// Program ids of the instructions of a transaction, used to align `invoke` lines with
+/// instructions in case some invocation did not log one. Empty if the accounts can't be resolved.
+#[derive(Debug, Default)]
+struct InstructionPrograms {
+    top_level: Vec<Pubkey>,
+    /// Keyed by top-level instruction index.
+    inner: HashMap<usize, Vec<Pubkey>>,
+}
+
+impl InstructionPrograms {
+    fn new(instructions: &[ResolvedInstruction]) -> Self {
+        let mut programs = Self::default();
+        for instruction in instructions {
+            let program = Pubkey::new_from_array(instruction.program_id.to_bytes());
+            match instruction.inner_index {
+                None => programs.top_level.push(program),
+                Some(_) => programs
+                    .inner
+                    .entry(instruction.top_level_index)
+                    .or_default()
+                    .push(program),
+            }
+        }
+        programs
     }
 
-    pub fn push(&mut self, new_program: String) {
-        self.stack.push(new_program);
+    /// Index of the first of `programs` at or after `from` that is `program`, or `from` if there
+    /// is none.
+    fn find(programs: Option<&Vec<Pubkey>>, from: usize, program: &Pubkey) -> usize {
+        programs
+            .and_then(|programs| programs.get(from..))
+            .and_then(|rest| rest.iter().position(|p| p == program))
+            .map_or(from, |i| from + i)
     }
+}
+
+/// Tracks which instruction the `invoke` lines belong to.
+#[derive(Debug, Default)]
+struct InstructionCursor {
+    top_level: Option<usize>,
+    inner: Option<usize>,
+}
+
+impl InstructionCursor {
+    /// Returns the top-level instruction index and inner instruction index of an invocation.
+    fn invoke(
+        &mut self,
+        programs: &InstructionPrograms,
+        program: &Pubkey,
+        depth: usize,
+    ) -> (usize, Option<usize>) {
+        if depth <= 1 {
+            let next = self.top_level.map_or(0, |i| i + 1);
+            let index = InstructionPrograms::find(Some(&programs.top_level), next, program);
+            self.top_level = Some(index);
+            self.inner = None;
+            return (index, None);
+        }
 
-    pub fn pop(&mut self) {
-        assert!(!self.stack.is_empty());
-        self.stack.pop();
+        let top_level = self.top_level.unwrap_or(0);
+        let next = self.inner.map_or(0, |i| i + 1);
+        let index = InstructionPrograms::find(programs.inner.get(&top_level), next, program);
+        self.inner = Some(index);
+        (top_level, Some(index))
     }
 }
 
-/// Attempt to parse a log line from the "current" program context.
-/// Returns (optional_new_program, did_pop, parsed_event).
-pub fn handle_program_log(
-    self_program_str: &str,
-    l: &str,
-    with_prefix: bool,
-) -> Result<(Option<String>, bool, Option<RaydiumCLMMEvent>)> {
-    // If the line has a recognized prefix, strip it for decoding
-    let log = if with_prefix {
-        l.strip_prefix(PROGRAM_LOG)
-            .or_else(|| l.strip_prefix(PROGRAM_DATA))
-    } else {
-        // If we are not expecting a prefix, take it as-is
-        Some(l)
+#[cfg(test)]
+mod tests {
+    use anchor_lang::Event;
+
+    use super::*;
+    use crate::chainstream::{
+        mock::fixtures,
+        recorder::Replay,
+        types::transaction::{CompiledInstruction, Header, InnerInstructions},
     };
 
-    if let Some(log) = log {
-        // If it starts with "Program log:", it’s not an encoded event
-        if l.starts_with("Program log:") {
-            return Ok((None, false, None));
+    const JUPITER: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+    const TOKEN: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+    const COMPUTE_BUDGET: &str = "ComputeBudget111111111111111111111111111111";
+
+    fn swap_data(amount_0: u64) -> Vec<u8> {
+        SwapEvent {
+            pool_state: Pubkey::new_unique(),
+            sender: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            amount_0,
+            transfer_fee_0: 0,
+            amount_1: 1,
+            transfer_fee_1: 0,
+            zero_for_one: true,
+            sqrt_price_x64: 1 << 64,
+            liquidity: 1,
+            tick: 0,
         }
+        .data()
+    }
+
+    fn swap(amount_0: u64) -> String {
+        format!(
+            "Program data: {}",
+            general_purpose::STANDARD.encode(swap_data(amount_0))
+        )
+    }
+
+    /// Event data logged by another program, not in Raydium's format.
+    fn foreign_data() -> String {
+        format!(
+            "Program data: {}",
+            general_purpose::STANDARD.encode([0xa5; 48])
+        )
+    }
+
+    fn transaction(logs: Vec<String>) -> TransactionWrite {
+        fixtures::transaction(1, fixtures::signature(1), &["A"], logs)
+    }
 
-        // Try base64 decode
-        let borsh_bytes = match general_purpose::STANDARD.decode(log) {
-            Ok(b) => b,
-            _ => {
-                println!("Could not base64 decode log: {}", log);
-                return Ok((None, false, None));
+    fn meta(logs: Vec<String>) -> Meta {
+        transaction(logs).meta().unwrap()
+    }
+
+    fn parse(logs: Vec<String>) -> Vec<(u64, Option<usize>, Option<Pubkey>)> {
+        parse_raydium_anchor_events(&transaction(logs))
+            .unwrap()
+            .into_iter()
+            .map(|parsed| match parsed.event {
+                RaydiumCLMMEvent::Swap(swap) => {
+                    (swap.amount_0, parsed.location.stack_height, parsed.parent)
+                }
+                other => panic!("unexpected event: {other:?}"),
+            })
+            .collect()
+    }
+
+    /// A two-hop Jupiter route through Raydium CLMM. Hand-written following the runtime's log
+    /// format, not a capture of a mainnet transaction.
+    fn jupiter_route() -> Vec<String> {
+        let raydium = RAYDIUM_CLMM_PROGRAM.to_string();
+        vec![
+            format!("Program {COMPUTE_BUDGET} invoke [1]"),
+            format!("Program {COMPUTE_BUDGET} success"),
+            format!("Program {JUPITER} invoke [1]"),
+            "Program log: Instruction: Route".to_string(),
+            format!("Program {raydium} invoke [2]"),
+            "Program log: Instruction: Swap".to_string(),
+            format!("Program {TOKEN} invoke [3]"),
+            "Program log: Instruction: Transfer".to_string(),
+            format!("Program {TOKEN} consumed 4645 of 180000 compute units"),
+            format!("Program {TOKEN} success"),
+            format!("Program {TOKEN} invoke [3]"),
+            "Program log: Instruction: Transfer".to_string(),
+            format!("Program {TOKEN} consumed 4736 of 172000 compute units"),
+            format!("Program {TOKEN} success"),
+            swap(1),
+            format!("Program {raydium} consumed 60000 of 220000 compute units"),
+            format!("Program {raydium} success"),
+            // Data logged by Jupiter between the hops.
+            foreign_data(),
+            format!("Program {raydium} invoke [2]"),
+            swap(2),
+            format!("Program {raydium} success"),
+            format!("Program return: {JUPITER} AAAAAAAAAAA="),
+            format!("Program {JUPITER} consumed 120000 of 240000 compute units"),
+            format!("Program {JUPITER} success"),
+            // A direct call to Raydium after the route.
+            format!("Program {raydium} invoke [1]"),
+            swap(3),
+            format!("Program {raydium} success"),
+        ]
+    }
+
+    #[test]
+    fn test_nested_invocations() {
+        let jupiter = Pubkey::from_str(JUPITER).unwrap();
+        assert_eq!(
+            parse(jupiter_route()),
+            [
+                (1, Some(2), Some(jupiter)),
+                (2, Some(2), Some(jupiter)),
+                (3, Some(1), None)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_and_truncated_logs() {
+        let raydium = RAYDIUM_CLMM_PROGRAM.to_string();
+        let failed = vec![
+            format!("Program {JUPITER} invoke [1]"),
+            format!("Program {raydium} invoke [2]"),
+            format!("Program {TOKEN} invoke [3]"),
+            "Program log: Error: insufficient funds".to_string(),
+            format!("Program {TOKEN} failed: custom program error: 0x1"),
+            swap(1),
+            format!("Program {raydium} failed: custom program error: 0x1"),
+            foreign_data(),
+            format!("Program {JUPITER} failed: custom program error: 0x1"),
+        ];
+        let jupiter = Pubkey::from_str(JUPITER).unwrap();
+        assert_eq!(parse(failed.clone()), [(1, Some(2), Some(jupiter))]);
+        let events = parse_raydium_anchor_events(&transaction(failed)).unwrap();
+        assert!(events[0].failed);
+        let events = parse_raydium_anchor_events(&transaction(jupiter_route())).unwrap();
+        assert!(events.iter().all(|event| !event.failed));
+
+        // Raydium succeeds, then Jupiter fails: the event is reverted as well.
+        let caller_failed = vec![
+            format!("Program {JUPITER} invoke [1]"),
+            format!("Program {raydium} invoke [2]"),
+            swap(1),
+            format!("Program {raydium} success"),
+            format!("Program {JUPITER} failed: slippage tolerance exceeded"),
+        ];
+        let events = parse_raydium_anchor_events(&transaction(caller_failed)).unwrap();
+        assert!(events[0].failed);
+
+        // The token program aborts without an exit line; the next invoke at depth 2 resets the
+        // stack to Jupiter.
+        let missing_exit = vec![
+            format!("Program {JUPITER} invoke [1]"),
+            format!("Program {raydium} invoke [2]"),
+            format!("Program {TOKEN} invoke [3]"),
+            format!("Program {raydium} invoke [2]"),
+            swap(2),
+        ];
+        assert_eq!(parse(missing_exit), [(2, Some(2), Some(jupiter))]);
+
+        let mut truncated = jupiter_route();
+        truncated.truncate(15);
+        truncated.push(LOG_TRUNCATED.to_string());
+        assert!(logs_truncated(&meta(truncated.clone())));
+        assert_eq!(parse(truncated.clone()), [(1, Some(2), Some(jupiter))]);
+        assert!(!logs_truncated(&meta(jupiter_route())));
+
+        // The failure is past the truncation, only the meta's error tells.
+        let mut failed_truncated = transaction(truncated);
+        failed_truncated.value.meta.as_mut().unwrap().err =
+            Some(serde_json::json!({ "InstructionError": [1, { "Custom": 6022 }] }));
+        let events = parse_raydium_anchor_events(&failed_truncated).unwrap();
+        assert!(events[0].failed);
+    }
+
+    /// Checks the parser against a session recorded from mainnet with
+    /// [`Recorder`](crate::chainstream::recorder::Recorder), e.g. of transactions touching
+    /// Raydium CLMM. No capture is checked in; run with
+    /// `CHAINSTREAM_CAPTURE=<path> cargo test -- --ignored`.
+    #[tokio::test]
+    #[ignore = "needs a recorded session in CHAINSTREAM_CAPTURE"]
+    async fn test_captured_session() {
+        let path = std::env::var("CHAINSTREAM_CAPTURE").unwrap();
+        let mut replay = Replay::<TransactionWrite>::open(path)
+            .unwrap()
+            .method("transactionsSubscribe");
+
+        let mut transactions = 0;
+        while let Some(transaction) = replay.next().await {
+            let transaction = transaction.unwrap();
+            let events = parse_raydium_anchor_events(&transaction).unwrap();
+            let meta = transaction.meta().unwrap();
+            let failed = transaction.transaction_error().unwrap().is_some();
+            for event in &events {
+                assert_eq!(event.failed, failed, "{}", event.location.signature);
+                if !logs_truncated(&meta) && event.source == EventSource::Log {
+                    assert!(event.location.log_index.is_some());
+                }
             }
-        };
+            transactions += 1;
+        }
+        assert!(transactions > 0, "the capture holds no transactions");
+    }
 
-        let mut slice: &[u8] = &borsh_bytes[..];
-        // First 8 bytes are the discriminator
-        if borsh_bytes.len() < 8 {
-            println!("Unknown/invalid event: {log}");
-            return Ok((None, false, None));
+    fn instruction(program_id_index: u32) -> CompiledInstruction {
+        CompiledInstruction {
+            program_id_index,
+            accounts: vec![],
+            data: String::new(),
+            stack_height: None,
         }
-        let mut disc = [0u8; 8];
-        disc.copy_from_slice(&borsh_bytes[..8]);
-        slice = &slice[8..];
-
-        // Match recognized event discriminators
-        let event = match disc {
-            ConfigChangeEvent::DISCRIMINATOR => {
-                let e = decode_event::<ConfigChangeEvent>(&mut slice)?;
-                RaydiumCLMMEvent::ConfigChange(e)
-            }
-            SwapEvent::DISCRIMINATOR => {
-                let e = decode_event::<SwapEvent>(&mut slice)?;
-                RaydiumCLMMEvent::Swap(e)
-            }
-            PoolCreatedEvent::DISCRIMINATOR => {
-                let e = decode_event::<PoolCreatedEvent>(&mut slice)?;
-                RaydiumCLMMEvent::PoolCreated(e)
-            }
-            CollectProtocolFeeEvent::DISCRIMINATOR => {
-                let e = decode_event::<CollectProtocolFeeEvent>(&mut slice)?;
-                RaydiumCLMMEvent::CollectProtocolFee(e)
-            }
-            LiquidityChangeEvent::DISCRIMINATOR => {
-                let e = decode_event::<LiquidityChangeEvent>(&mut slice)?;
-                RaydiumCLMMEvent::LiquidityChange(e)
-            }
-            CreatePersonalPositionEvent::DISCRIMINATOR => {
-                let e = decode_event::<CreatePersonalPositionEvent>(&mut slice)?;
-                RaydiumCLMMEvent::CreatePersonalPosition(e)
-            }
-            IncreaseLiquidityEvent::DISCRIMINATOR => {
-                let e = decode_event::<IncreaseLiquidityEvent>(&mut slice)?;
-                RaydiumCLMMEvent::IncreaseLiquidity(e)
-            }
-            DecreaseLiquidityEvent::DISCRIMINATOR => {
-                let e = decode_event::<DecreaseLiquidityEvent>(&mut slice)?;
-                RaydiumCLMMEvent::DecreaseLiquidity(e)
-            }
-            LiquidityCalculateEvent::DISCRIMINATOR => {
-                let e = decode_event::<LiquidityCalculateEvent>(&mut slice)?;
-                RaydiumCLMMEvent::LiquidityCalculate(e)
-            }
-            CollectPersonalFeeEvent::DISCRIMINATOR => {
-                let e = decode_event::<CollectPersonalFeeEvent>(&mut slice)?;
-                RaydiumCLMMEvent::CollectPersonalFee(e)
-            }
-            UpdateRewardInfosEvent::DISCRIMINATOR => {
-                let e = decode_event::<UpdateRewardInfosEvent>(&mut slice)?;
-                RaydiumCLMMEvent::UpdateRewardInfos(e)
+    }
+
+    /// An `emit_cpi!` self-invocation carrying `event`.
+    fn event_cpi(
+        program_id_index: u32,
+        event: &[u8],
+        stack_height: Option<u32>,
+    ) -> CompiledInstruction {
+        CompiledInstruction {
+            data: general_purpose::STANDARD.encode([&EVENT_IX_TAG_LE[..], event].concat()),
+            stack_height,
+            ..instruction(program_id_index)
+        }
+    }
+
+    /// A transaction with the accounts payer, compute budget, Jupiter, Raydium and token program.
+    fn with_instructions(
+        logs: Vec<String>,
+        top_level: Vec<CompiledInstruction>,
+        inner: Vec<InnerInstructions>,
+    ) -> TransactionWrite {
+        let raydium = RAYDIUM_CLMM_PROGRAM.to_string();
+        let payer = Pubkey::new_unique().to_string();
+        let keys = [payer.as_str(), COMPUTE_BUDGET, JUPITER, &raydium, TOKEN];
+        let mut transaction = fixtures::transaction(7, fixtures::signature(3), &keys, logs);
+        let message = transaction
+            .value
+            .transaction
+            .as_mut()
+            .unwrap()
+            .message
+            .as_mut()
+            .unwrap();
+        message.header = Some(Header {
+            num_required_signatures: 1,
+            num_readonly_signed_accounts: 0,
+            num_readonly_unsigned_accounts: 4,
+        });
+        message.instructions = top_level;
+        transaction.value.meta.as_mut().unwrap().inner_instructions = inner;
+        transaction
+    }
+
+    #[test]
+    fn test_event_locations() {
+        // An instruction that logs nothing, like a precompile, precedes the direct Raydium call.
+        let transaction = with_instructions(
+            jupiter_route(),
+            vec![
+                instruction(1),
+                instruction(2),
+                instruction(0),
+                instruction(3),
+            ],
+            vec![InnerInstructions {
+                index: 1,
+                instructions: vec![
+                    instruction(3),
+                    instruction(4),
+                    instruction(4),
+                    instruction(3),
+                ],
+            }],
+        );
+
+        let locations: Vec<_> = parse_raydium_anchor_events(&transaction)
+            .unwrap()
+            .into_iter()
+            .map(|parsed| parsed.location)
+            .collect();
+        assert_eq!(
+            locations[0],
+            EventLocation {
+                signature: fixtures::signature(3),
+                slot: 7,
+                instruction_index: 1,
+                inner_instruction_index: Some(0),
+                stack_height: Some(2),
+                log_index: Some(14),
             }
-            _ => RaydiumCLMMEvent::Unknown(l.to_string()),
+        );
+        let positions: Vec<_> = locations
+            .iter()
+            .map(|l| (l.instruction_index, l.inner_instruction_index, l.log_index))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                (1, Some(0), Some(14)),
+                (1, Some(3), Some(19)),
+                (3, None, Some(25))
+            ]
+        );
+
+        // Without resolvable accounts, instructions are counted from the logs alone.
+        let positions: Vec<_> = parse_raydium_anchor_events(&self::transaction(jupiter_route()))
+            .unwrap()
+            .into_iter()
+            .map(|parsed| {
+                let l = parsed.location;
+                (l.instruction_index, l.inner_instruction_index)
+            })
+            .collect();
+        assert_eq!(positions, [(1, Some(0)), (1, Some(3)), (2, None)]);
+    }
+
+    #[test]
+    fn test_event_cpi() {
+        let raydium = RAYDIUM_CLMM_PROGRAM.to_string();
+        let jupiter = Pubkey::from_str(JUPITER).unwrap();
+        let summary = |events: Vec<ParsedEvent<RaydiumCLMMEvent>>| -> Vec<_> {
+            events
+                .into_iter()
+                .map(|parsed| {
+                    let RaydiumCLMMEvent::Swap(swap) = parsed.event else {
+                        panic!("unexpected event: {:?}", parsed.event);
+                    };
+                    let l = parsed.location;
+                    (
+                        swap.amount_0,
+                        parsed.source,
+                        parsed.parent,
+                        l.inner_instruction_index,
+                        l.stack_height,
+                        l.log_index,
+                    )
+                })
+                .collect()
         };
 
-        Ok((None, false, Some(event)))
-    } else {
-        // If there's no recognized prefix, treat as a system log
-        let (program, did_pop) = handle_system_log(self_program_str, l);
-        Ok((program, did_pop, None))
-    }
-}
-
-/// Handle system logs, i.e., lines that indicate a program "invoke" or "success".
-/// Returns (optional_new_program, did_pop).
-fn handle_system_log(this_program_str: &str, log: &str) -> (Option<String>, bool) {
-    if log.starts_with(&format!("Program {this_program_str} invoke")) {
-        // The current program is invoked
-        (Some(this_program_str.to_string()), false)
-    } else if log.contains("invoke") {
-        // Another CPI call
-        (Some("cpi".to_string()), false)
-    } else {
-        // Possibly a "success" or something else
-        let re = Regex::new(r"^Program (.*) success*$").unwrap();
-        if re.is_match(log) {
-            // When a program returns, we pop from the stack
-            (None, true)
-        } else {
-            (None, false)
-        }
+        // Jupiter calls Raydium, which emits its first event both ways and its second one through
+        // CPI after the logs were truncated.
+        let first = swap_data(1);
+        let logs = vec![
+            format!("Program {JUPITER} invoke [1]"),
+            format!("Program {raydium} invoke [2]"),
+            format!("Program data: {}", general_purpose::STANDARD.encode(&first)),
+            format!("Program {raydium} invoke [3]"),
+            format!("Program {raydium} success"),
+            LOG_TRUNCATED.to_string(),
+        ];
+        let inner = vec![InnerInstructions {
+            index: 0,
+            instructions: vec![
+                instruction(3),
+                event_cpi(3, &first, None),
+                event_cpi(3, &swap_data(2), Some(3)),
+            ],
+        }];
+        let transaction = with_instructions(logs, vec![instruction(2)], inner.clone());
+        assert_eq!(
+            summary(parse_raydium_anchor_events(&transaction).unwrap()),
+            [
+                (
+                    1,
+                    EventSource::Log,
+                    Some(jupiter),
+                    Some(0),
+                    Some(2),
+                    Some(2)
+                ),
+                (2, EventSource::Cpi, Some(jupiter), Some(2), Some(3), None),
+            ]
+        );
+
+        // Without the logs, the CPI copy of the first event is reported instead. Its depth can't
+        // be told without the depth of the Raydium instruction that made it.
+        let transaction = with_instructions(vec![], vec![instruction(2)], inner.clone());
+        let events = summary(parse_raydium_anchor_events(&transaction).unwrap());
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], (1, EventSource::Cpi, None, Some(1), None, None));
+        assert_eq!(
+            events[1],
+            (2, EventSource::Cpi, Some(jupiter), Some(2), Some(3), None)
+        );
+
+        // With it, the self-CPI is one level deeper and its parent is Raydium's caller.
+        let mut reported = inner;
+        reported[0].instructions[0].stack_height = Some(2);
+        let transaction = with_instructions(vec![], vec![instruction(2)], reported);
+        let events = summary(parse_raydium_anchor_events(&transaction).unwrap());
+        assert_eq!(
+            events[0],
+            (1, EventSource::Cpi, Some(jupiter), Some(1), Some(3), None)
+        );
+
+        // A top-level Raydium instruction emits at depth 2, and has no parent.
+        let direct = vec![InnerInstructions {
+            index: 0,
+            instructions: vec![event_cpi(3, &first, None)],
+        }];
+        let transaction = with_instructions(vec![], vec![instruction(3)], direct);
+        let events = summary(parse_raydium_anchor_events(&transaction).unwrap());
+        assert_eq!(
+            events,
+            [(1, EventSource::Cpi, None, Some(0), Some(2), None)]
+        );
     }
-}
 
-/// Generic borsh decoding of an Event
-fn decode_event<T: anchor_lang::Event + AnchorDeserialize>(slice: &mut &[u8]) -> Result<T> {
-    Ok(T::deserialize(slice)?)
+    #[test]
+    fn test_log_lines() {
+        let token = Pubkey::from_str(TOKEN).unwrap();
+        assert_eq!(
+            LogLine::parse(&format!("Program {TOKEN} invoke [3]")),
+            LogLine::Invoke {
+                program: token,
+                depth: 3
+            }
+        );
+        assert_eq!(
+            LogLine::parse(&format!("Program {TOKEN} success")),
+            LogLine::Success { program: token }
+        );
+        for other in [
+            "Program log: Instruction: invoke [1]".to_string(),
+            format!("Program {TOKEN} consumed 4645 of 180000 compute units"),
+            format!("Program {TOKEN} invoke [x]"),
+            "Program is not deployed".to_string(),
+        ] {
+            assert_eq!(LogLine::parse(&other), LogLine::Other, "{other}");
+        }
+    }
 }
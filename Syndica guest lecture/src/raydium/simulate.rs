@@ -0,0 +1,682 @@
+//! Off-chain quotes for Raydium CLMM swaps.
+//!
+//! [`SwapSimulator`] replays the swap loop of the program on a [`PoolState`] and the tick arrays
+//! around its price: it moves the price from one initialized tick to the next with
+//! [`compute_swap_step`], updating the liquidity at every tick it crosses.
+//!
+//! A quote is meant to be exact: given the pool, its config and tick arrays as they were before a
+//! swap, [`SwapSimulator::quote_instruction`] should reproduce the amounts, price, tick and
+//! liquidity of the `SwapEvent` the program emitted, see [`SwapQuote::matches_event`]. The
+//! ignored `test_captured_swaps` checks this against swaps captured from mainnet; no capture is
+//! checked in, so exactness has not been verified yet.
+//!
+//! Fee growth, protocol and fund fees and reward accounting are not simulated, they do not change
+//! the swap amounts.
+use std::collections::BTreeMap;
+
+use anchor_lang::prelude::Pubkey;
+
+use super::{
+    anchor_events::SwapEvent,
+    instructions::{SwapAccounts, SwapArgs},
+    math::{
+        compute_swap_step, get_sqrt_price_at_tick, get_tick_at_sqrt_price, MathError,
+        MAX_SQRT_PRICE_X64, MAX_TICK, MIN_SQRT_PRICE_X64, MIN_TICK,
+    },
+    state::{AmmConfig, PoolState, TickArrayState, TICK_ARRAY_SIZE},
+};
+
+/// Number of tick arrays on each side of tick 0 tracked by `PoolState.tick_array_bitmap`. Arrays
+/// further away are tracked by the bitmap extension account, which only pools with a tick
+/// spacing below 15 can reach.
+const TICK_ARRAY_BITMAP_SIZE: i32 = 512;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SimulationError {
+    #[error(transparent)]
+    Math(#[from] MathError),
+    /// The swap reaches a tick array that may hold initialized ticks, but it was not provided.
+    #[error("Tick array starting at tick {0} is needed but was not provided")]
+    MissingTickArray(i32),
+    #[error("No liquidity left in the swap direction")]
+    LiquidityInsufficient,
+    #[error("Sqrt price limit {0} is on the wrong side of the pool price or out of range")]
+    InvalidPriceLimit(u128),
+    #[error("Swap amount is zero")]
+    ZeroAmount,
+    #[error("Input vault {0} is not a vault of the pool")]
+    UnknownVault(Pubkey),
+    /// An exact-input swap would pay out less than the instruction's minimum.
+    #[error("Swap pays out {amount_out}, less than the minimum of {minimum}")]
+    TooLittleOutputReceived { amount_out: u64, minimum: u64 },
+    /// An exact-output swap would take more than the instruction's maximum input.
+    #[error("Swap takes {amount_in}, more than the maximum of {maximum}")]
+    TooMuchInputPaid { amount_in: u64, maximum: u64 },
+}
+
+/// Outcome of a simulated swap.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SwapQuote {
+    pub zero_for_one: bool,
+    /// Amount paid into the pool, fee included.
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    /// Price, tick and liquidity of the pool after the swap.
+    pub sqrt_price_x64: u128,
+    pub tick: i32,
+    pub liquidity: u128,
+    /// Initialized ticks crossed, in crossing order.
+    pub ticks_crossed: Vec<i32>,
+}
+
+impl SwapQuote {
+    /// Amount of token_0 in or out of the pool, like `SwapEvent.amount_0`.
+    pub fn amount_0(&self) -> u64 {
+        if self.zero_for_one {
+            self.amount_in
+        } else {
+            self.amount_out
+        }
+    }
+
+    /// Amount of token_1 in or out of the pool, like `SwapEvent.amount_1`.
+    pub fn amount_1(&self) -> u64 {
+        if self.zero_for_one {
+            self.amount_out
+        } else {
+            self.amount_in
+        }
+    }
+
+    /// Whether the program reported the same swap in `event`. Token-2022 transfer fees are not
+    /// simulated, so they must be zero.
+    pub fn matches_event(&self, event: &SwapEvent) -> bool {
+        event.zero_for_one == self.zero_for_one
+            && event.amount_0 == self.amount_0()
+            && event.amount_1 == self.amount_1()
+            && event.sqrt_price_x64 == self.sqrt_price_x64
+            && event.liquidity == self.liquidity
+            && event.tick == self.tick
+            && event.transfer_fee_0 == 0
+            && event.transfer_fee_1 == 0
+    }
+}
+
+/// Simulates swaps against a pool, see the [module docs](self).
+#[derive(Debug, Clone)]
+pub struct SwapSimulator {
+    pool: PoolState,
+    trade_fee_rate: u32,
+    tick_arrays: BTreeMap<i32, TickArrayState>,
+}
+
+impl SwapSimulator {
+    /// A simulator for `pool`, charging `trade_fee_rate` from the pool's `AmmConfig` or the latest
+    /// `ConfigChangeEvent` of it, in hundredths of a bip.
+    pub fn new(pool: &PoolState, trade_fee_rate: u32) -> Self {
+        Self {
+            pool: *pool,
+            trade_fee_rate,
+            tick_arrays: BTreeMap::new(),
+        }
+    }
+
+    #[allow(unused)]
+    pub fn with_config(pool: &PoolState, config: &AmmConfig) -> Self {
+        Self::new(pool, config.trade_fee_rate)
+    }
+
+    /// Adds a tick array of the pool, replacing any array with the same start tick.
+    #[allow(unused)]
+    pub fn tick_array(mut self, tick_array: &TickArrayState) -> Self {
+        self.tick_arrays
+            .insert(tick_array.start_tick_index, *tick_array);
+        self
+    }
+
+    pub fn pool(&self) -> &PoolState {
+        &self.pool
+    }
+
+    /// Quotes a swap of `amount`, the input amount fee included if `is_base_input`, the output
+    /// amount otherwise. The swap stops early at `sqrt_price_limit_x64`, `None` for no limit.
+    pub fn quote(
+        &self,
+        amount: u64,
+        is_base_input: bool,
+        zero_for_one: bool,
+        sqrt_price_limit_x64: Option<u128>,
+    ) -> Result<SwapQuote, SimulationError> {
+        if amount == 0 {
+            return Err(SimulationError::ZeroAmount);
+        }
+        let pool_sqrt_price_x64 = self.pool.sqrt_price_x64;
+        let sqrt_price_limit_x64 = match sqrt_price_limit_x64 {
+            Some(limit) => limit,
+            None if zero_for_one => MIN_SQRT_PRICE_X64 + 1,
+            None => MAX_SQRT_PRICE_X64 - 1,
+        };
+        let valid_limit = if zero_for_one {
+            sqrt_price_limit_x64 < pool_sqrt_price_x64 && sqrt_price_limit_x64 > MIN_SQRT_PRICE_X64
+        } else {
+            sqrt_price_limit_x64 > pool_sqrt_price_x64 && sqrt_price_limit_x64 < MAX_SQRT_PRICE_X64
+        };
+        if !valid_limit {
+            return Err(SimulationError::InvalidPriceLimit(sqrt_price_limit_x64));
+        }
+
+        let mut remaining = amount;
+        let mut calculated = 0u64;
+        let mut fee_amount = 0u64;
+        let mut sqrt_price_x64 = pool_sqrt_price_x64;
+        let mut tick = self.pool.tick_current;
+        let mut liquidity = self.pool.liquidity;
+        let mut ticks_crossed = Vec::new();
+
+        while remaining != 0
+            && sqrt_price_x64 != sqrt_price_limit_x64
+            && tick < MAX_TICK
+            && tick > MIN_TICK
+        {
+            let sqrt_price_start_x64 = sqrt_price_x64;
+            let (tick_next, liquidity_net) = self
+                .next_initialized_tick(tick, zero_for_one)?
+                .ok_or(SimulationError::LiquidityInsufficient)?;
+            let tick_next = tick_next.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next_x64 = get_sqrt_price_at_tick(tick_next)?;
+            let target_price = if (zero_for_one && sqrt_price_next_x64 < sqrt_price_limit_x64)
+                || (!zero_for_one && sqrt_price_next_x64 > sqrt_price_limit_x64)
+            {
+                sqrt_price_limit_x64
+            } else {
+                sqrt_price_next_x64
+            };
+
+            let step = compute_swap_step(
+                sqrt_price_x64,
+                target_price,
+                liquidity,
+                remaining,
+                self.trade_fee_rate,
+                is_base_input,
+                zero_for_one,
+            )?;
+            sqrt_price_x64 = step.sqrt_price_next_x64;
+            fee_amount += step.fee_amount;
+            if is_base_input {
+                remaining -= step.amount_in + step.fee_amount;
+                calculated = checked_add(calculated, step.amount_out)?;
+            } else {
+                remaining -= step.amount_out;
+                calculated = checked_add(calculated, step.amount_in + step.fee_amount)?;
+            }
+
+            if sqrt_price_x64 == sqrt_price_next_x64 {
+                // Crossing the tick, the liquidity of the positions bounded by it changes.
+                let delta = if zero_for_one {
+                    -liquidity_net
+                } else {
+                    liquidity_net
+                };
+                liquidity = liquidity
+                    .checked_add_signed(delta)
+                    .ok_or(MathError::Overflow)?;
+                ticks_crossed.push(tick_next);
+                tick = if zero_for_one {
+                    tick_next - 1
+                } else {
+                    tick_next
+                };
+            } else if sqrt_price_x64 != sqrt_price_start_x64 {
+                tick = get_tick_at_sqrt_price(sqrt_price_x64)?;
+            }
+        }
+
+        let (amount_in, amount_out) = if is_base_input {
+            (amount - remaining, calculated)
+        } else {
+            (calculated, amount - remaining)
+        };
+        Ok(SwapQuote {
+            zero_for_one,
+            amount_in,
+            amount_out,
+            fee_amount,
+            sqrt_price_x64,
+            tick,
+            liquidity,
+            ticks_crossed,
+        })
+    }
+
+    /// Quotes a decoded `swap` or `swap_v2` instruction on this pool. Fails like the program does
+    /// when the quote misses the instruction's `other_amount_threshold`.
+    pub fn quote_instruction(
+        &self,
+        args: &SwapArgs,
+        accounts: &SwapAccounts,
+    ) -> Result<SwapQuote, SimulationError> {
+        let (vault_0, vault_1) = (self.pool.token_vault_0, self.pool.token_vault_1);
+        let zero_for_one = if accounts.input_vault == vault_0 {
+            true
+        } else if accounts.input_vault == vault_1 {
+            false
+        } else {
+            return Err(SimulationError::UnknownVault(accounts.input_vault));
+        };
+        let limit = (args.sqrt_price_limit_x64 != 0).then_some(args.sqrt_price_limit_x64);
+        let quote = self.quote(args.amount, args.is_base_input, zero_for_one, limit)?;
+
+        let threshold = args.other_amount_threshold;
+        if args.is_base_input && quote.amount_out < threshold {
+            return Err(SimulationError::TooLittleOutputReceived {
+                amount_out: quote.amount_out,
+                minimum: threshold,
+            });
+        }
+        if !args.is_base_input && quote.amount_in > threshold {
+            return Err(SimulationError::TooMuchInputPaid {
+                amount_in: quote.amount_in,
+                maximum: threshold,
+            });
+        }
+        Ok(quote)
+    }
+
+    /// Moves the pool to the state after `quote`, so the next swap can be quoted.
+    pub fn apply(&mut self, quote: &SwapQuote) {
+        self.pool.sqrt_price_x64 = quote.sqrt_price_x64;
+        self.pool.tick_current = quote.tick;
+        self.pool.liquidity = quote.liquidity;
+    }
+
+    /// The closest initialized tick at or below `tick` when `zero_for_one`, above it otherwise,
+    /// with its `liquidity_net`. `None` if there is none.
+    fn next_initialized_tick(
+        &self,
+        tick: i32,
+        zero_for_one: bool,
+    ) -> Result<Option<(i32, i128)>, SimulationError> {
+        let tick_spacing = self.pool.tick_spacing;
+        let ticks_in_array = TICK_ARRAY_SIZE as i32 * i32::from(tick_spacing);
+        let first = TickArrayState::start_index(MIN_TICK, tick_spacing);
+        let last = TickArrayState::start_index(MAX_TICK, tick_spacing);
+
+        let mut start = TickArrayState::start_index(tick, tick_spacing);
+        // Offset of `tick` in the current array, the search starts next to it.
+        let mut offset = (tick - start) / i32::from(tick_spacing);
+        while (first..=last).contains(&start) {
+            match self.tick_arrays.get(&start) {
+                Some(array) => {
+                    let ticks = &array.ticks[..];
+                    let found = if zero_for_one {
+                        (0..=offset.min(TICK_ARRAY_SIZE as i32 - 1))
+                            .rev()
+                            .find(|i| ticks[*i as usize].is_initialized())
+                    } else {
+                        (offset + 1..TICK_ARRAY_SIZE as i32)
+                            .find(|i| ticks[*i as usize].is_initialized())
+                    };
+                    if let Some(i) = found {
+                        let tick = ticks[i as usize];
+                        return Ok(Some((tick.tick, tick.liquidity_net)));
+                    }
+                }
+                None if self.tick_array_initialized(start) == Some(false) => {}
+                None => return Err(SimulationError::MissingTickArray(start)),
+            }
+            if zero_for_one {
+                start -= ticks_in_array;
+                offset = TICK_ARRAY_SIZE as i32 - 1;
+            } else {
+                start += ticks_in_array;
+                offset = -1;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Whether the tick array starting at `start` holds initialized ticks according to the pool's
+    /// bitmap, `None` if the array is tracked by the bitmap extension instead.
+    fn tick_array_initialized(&self, start: i32) -> Option<bool> {
+        let ticks_in_array = TICK_ARRAY_SIZE as i32 * i32::from(self.pool.tick_spacing);
+        let position = start / ticks_in_array + TICK_ARRAY_BITMAP_SIZE;
+        if !(0..2 * TICK_ARRAY_BITMAP_SIZE).contains(&position) {
+            return None;
+        }
+        let bitmap = self.pool.tick_array_bitmap;
+        let position = position as usize;
+        Some(bitmap[position / 64] >> (position % 64) & 1 == 1)
+    }
+}
+
+fn checked_add(a: u64, b: u64) -> Result<u64, SimulationError> {
+    a.checked_add(b)
+        .ok_or(SimulationError::Math(MathError::MaxTokenOverflow))
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use bytemuck::Zeroable;
+
+    use super::*;
+    use crate::{
+        chainstream::types::transaction::TransactionWrite,
+        raydium::{
+            anchor_events::RaydiumCLMMEvent,
+            instructions::{decode_raydium_instructions, RaydiumCLMMInstruction},
+            math::get_delta_amount_1_unsigned,
+            parse::parse_raydium_anchor_events,
+            state::RaydiumAccount,
+        },
+    };
+
+    const FEE_RATE: u32 = 2_500;
+    const TICK_SPACING: u16 = 60;
+    const INNER: u128 = 1_000_000_000_000;
+    const OUTER: u128 = 300_000_000_000;
+
+    /// A pool at tick 0 with two positions, one over [-600, 600] and one over [-7200, 7200].
+    fn simulator() -> SwapSimulator {
+        let mut pool = PoolState::zeroed();
+        pool.tick_spacing = TICK_SPACING;
+        pool.sqrt_price_x64 = get_sqrt_price_at_tick(0).unwrap();
+        pool.liquidity = INNER + OUTER;
+        pool.token_vault_0 = Pubkey::new_unique();
+        pool.token_vault_1 = Pubkey::new_unique();
+
+        let mut simulator = SwapSimulator::new(&pool, FEE_RATE);
+        for (tick, liquidity_net) in [
+            (-7200, OUTER as i128),
+            (-600, INNER as i128),
+            (600, -(INNER as i128)),
+            (7200, -(OUTER as i128)),
+        ] {
+            let start = TickArrayState::start_index(tick, TICK_SPACING);
+            let array = simulator.tick_arrays.entry(start).or_insert_with(|| {
+                let mut array = TickArrayState::zeroed();
+                array.start_tick_index = start;
+                array
+            });
+            let i = ((tick - start) / i32::from(TICK_SPACING)) as usize;
+            array.ticks[i].tick = tick;
+            array.ticks[i].liquidity_net = liquidity_net;
+            array.ticks[i].liquidity_gross = liquidity_net.unsigned_abs();
+        }
+        let mut bitmap = [0u64; 16];
+        for start in simulator.tick_arrays.keys() {
+            let position = (start / (60 * 60) + TICK_ARRAY_BITMAP_SIZE) as usize;
+            bitmap[position / 64] |= 1 << (position % 64);
+        }
+        simulator.pool.tick_array_bitmap = bitmap;
+        simulator
+    }
+
+    #[test]
+    fn test_swap_within_range() {
+        let simulator = simulator();
+        let quote = simulator.quote(1_000_000, true, true, None).unwrap();
+
+        let step = compute_swap_step(
+            simulator.pool.sqrt_price_x64,
+            get_sqrt_price_at_tick(-600).unwrap(),
+            INNER + OUTER,
+            1_000_000,
+            FEE_RATE,
+            true,
+            true,
+        )
+        .unwrap();
+        assert_eq!(quote.amount_in, 1_000_000);
+        assert_eq!(quote.amount_out, step.amount_out);
+        assert_eq!(quote.fee_amount, step.fee_amount);
+        assert_eq!(quote.sqrt_price_x64, step.sqrt_price_next_x64);
+        assert_eq!(
+            quote.tick,
+            get_tick_at_sqrt_price(step.sqrt_price_next_x64).unwrap()
+        );
+        assert!(quote.ticks_crossed.is_empty());
+    }
+
+    #[test]
+    fn test_swap_crosses_ticks() {
+        let mut simulator = simulator();
+        let limit = get_sqrt_price_at_tick(-1000).unwrap();
+        let quote = simulator
+            .quote(u64::MAX / 2, true, true, Some(limit))
+            .unwrap();
+        assert_eq!(quote.ticks_crossed, [-600]);
+        assert_eq!(quote.liquidity, OUTER);
+        assert_eq!(quote.sqrt_price_x64, limit);
+        assert_eq!(quote.tick, -1000);
+
+        // The output is the token_1 between both prices, at each range's liquidity.
+        let p_600 = get_sqrt_price_at_tick(-600).unwrap();
+        let expected = get_delta_amount_1_unsigned(p_600, 1 << 64, INNER + OUTER, false).unwrap()
+            + get_delta_amount_1_unsigned(limit, p_600, OUTER, false).unwrap();
+        assert_eq!(quote.amount_out, expected);
+
+        // Swapping the output back crosses -600 again, but fees keep the price below the start.
+        simulator.apply(&quote);
+        let back = simulator
+            .quote(quote.amount_out, true, false, None)
+            .unwrap();
+        assert_eq!(back.ticks_crossed, [-600]);
+        assert_eq!(back.liquidity, INNER + OUTER);
+        assert!(back.tick < 0);
+        assert!(back.amount_out < quote.amount_in);
+    }
+
+    #[test]
+    fn test_quote_instruction() {
+        let simulator = simulator();
+        let mut accounts = SwapAccounts {
+            payer: Pubkey::new_unique(),
+            amm_config: Pubkey::new_unique(),
+            pool_state: Pubkey::new_unique(),
+            input_token_account: Pubkey::new_unique(),
+            output_token_account: Pubkey::new_unique(),
+            input_vault: simulator.pool.token_vault_1,
+            output_vault: simulator.pool.token_vault_0,
+            observation_state: Pubkey::new_unique(),
+            tick_arrays: vec![],
+            input_vault_mint: None,
+            output_vault_mint: None,
+        };
+        let args = SwapArgs {
+            amount: 5_000_000_000,
+            other_amount_threshold: 0,
+            sqrt_price_limit_x64: 0,
+            is_base_input: true,
+        };
+        let quote = simulator.quote_instruction(&args, &accounts).unwrap();
+        assert!(!quote.zero_for_one);
+        assert_eq!(quote.amount_1(), 5_000_000_000);
+        assert_eq!(
+            quote,
+            simulator.quote(5_000_000_000, true, false, None).unwrap()
+        );
+
+        // Slippage checks: the minimum output of an exact-input swap...
+        let minimum = SwapArgs {
+            other_amount_threshold: quote.amount_out,
+            ..args.clone()
+        };
+        assert!(simulator.quote_instruction(&minimum, &accounts).is_ok());
+        let minimum = SwapArgs {
+            other_amount_threshold: quote.amount_out + 1,
+            ..args.clone()
+        };
+        assert_eq!(
+            simulator.quote_instruction(&minimum, &accounts),
+            Err(SimulationError::TooLittleOutputReceived {
+                amount_out: quote.amount_out,
+                minimum: quote.amount_out + 1,
+            })
+        );
+
+        // ...and the maximum input of an exact-output one.
+        let exact_out = simulator.quote(1_000_000, false, false, None).unwrap();
+        let maximum = SwapArgs {
+            amount: 1_000_000,
+            other_amount_threshold: exact_out.amount_in,
+            sqrt_price_limit_x64: 0,
+            is_base_input: false,
+        };
+        assert_eq!(
+            simulator.quote_instruction(&maximum, &accounts),
+            Ok(exact_out.clone())
+        );
+        let maximum = SwapArgs {
+            other_amount_threshold: exact_out.amount_in - 1,
+            ..maximum
+        };
+        assert_eq!(
+            simulator.quote_instruction(&maximum, &accounts),
+            Err(SimulationError::TooMuchInputPaid {
+                amount_in: exact_out.amount_in,
+                maximum: exact_out.amount_in - 1,
+            })
+        );
+
+        accounts.input_vault = Pubkey::new_unique();
+        assert!(matches!(
+            simulator.quote_instruction(&args, &accounts),
+            Err(SimulationError::UnknownVault(_))
+        ));
+    }
+
+    /// Only checks the comparison, the event is built from the quote. Whether quotes match real
+    /// events is checked by `test_captured_swaps`.
+    #[test]
+    fn test_matches_event() {
+        let quote = simulator().quote(5_000_000_000, true, false, None).unwrap();
+        let event = SwapEvent {
+            pool_state: Pubkey::new_unique(),
+            sender: Pubkey::new_unique(),
+            token_account_0: Pubkey::new_unique(),
+            token_account_1: Pubkey::new_unique(),
+            amount_0: quote.amount_0(),
+            transfer_fee_0: 0,
+            amount_1: quote.amount_1(),
+            transfer_fee_1: 0,
+            zero_for_one: false,
+            sqrt_price_x64: quote.sqrt_price_x64,
+            liquidity: quote.liquidity,
+            tick: quote.tick,
+        };
+        assert!(quote.matches_event(&event));
+        assert!(!quote.matches_event(&SwapEvent {
+            amount_0: event.amount_0 + 1,
+            ..event
+        }));
+        assert!(!quote.matches_event(&SwapEvent {
+            transfer_fee_1: 1,
+            ..event
+        }));
+    }
+
+    #[test]
+    fn test_simulation_errors() {
+        let mut simulator = simulator();
+        // Past the outer position there is no liquidity left.
+        assert_eq!(
+            simulator.quote(u64::MAX / 2, false, true, None),
+            Err(SimulationError::LiquidityInsufficient)
+        );
+        assert_eq!(
+            simulator.quote(1, true, true, Some(1 << 65)),
+            Err(SimulationError::InvalidPriceLimit(1 << 65))
+        );
+        assert_eq!(
+            simulator.quote(0, true, true, None),
+            Err(SimulationError::ZeroAmount)
+        );
+
+        // The bitmap says the array holding -7200 has initialized ticks.
+        let start = TickArrayState::start_index(-7200, TICK_SPACING);
+        simulator.tick_arrays.remove(&start);
+        assert_eq!(
+            simulator.quote(u64::MAX / 2, false, true, None),
+            Err(SimulationError::MissingTickArray(start))
+        );
+    }
+
+    /// One swap captured from mainnet: the pool, its config and the tick arrays the swap may
+    /// reach, as they were right before the transaction, and the transaction itself. Account data
+    /// is base64, discriminator included.
+    #[derive(serde::Deserialize)]
+    struct SwapCapture {
+        pool_state: String,
+        amm_config: String,
+        tick_arrays: Vec<String>,
+        transaction: TransactionWrite,
+    }
+
+    /// Checks quotes against swaps captured from mainnet, one [`SwapCapture`] JSON file per
+    /// transaction in the directory named by `RAYDIUM_SWAP_CAPTURES`. No captures are checked in;
+    /// run with `RAYDIUM_SWAP_CAPTURES=<dir> cargo test -- --ignored`.
+    #[test]
+    #[ignore = "needs captured swaps in RAYDIUM_SWAP_CAPTURES"]
+    fn test_captured_swaps() {
+        let decode = |data: &str| STANDARD.decode(data).unwrap();
+        let dir = std::env::var("RAYDIUM_SWAP_CAPTURES").unwrap();
+
+        let mut swaps = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let capture: SwapCapture =
+                serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+            let pool = *PoolState::load(&decode(&capture.pool_state)).unwrap();
+            let config = *AmmConfig::load(&decode(&capture.amm_config)).unwrap();
+            let mut simulator = SwapSimulator::with_config(&pool, &config);
+            for data in &capture.tick_arrays {
+                simulator = simulator.tick_array(TickArrayState::load(&decode(data)).unwrap());
+            }
+
+            // The swaps on the captured pool, and the events they emitted, in execution order.
+            let vaults = [pool.token_vault_0, pool.token_vault_1];
+            let instructions: Vec<_> = decode_raydium_instructions(&capture.transaction)
+                .unwrap()
+                .into_iter()
+                .filter_map(|decoded| match decoded.instruction {
+                    RaydiumCLMMInstruction::Swap { args, accounts }
+                    | RaydiumCLMMInstruction::SwapV2 { args, accounts }
+                        if vaults.contains(&accounts.input_vault) =>
+                    {
+                        Some((args, accounts))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let events: Vec<_> = parse_raydium_anchor_events(&capture.transaction)
+                .unwrap()
+                .into_iter()
+                .filter_map(|parsed| match parsed.event {
+                    RaydiumCLMMEvent::Swap(event) if !parsed.failed => Some(event),
+                    _ => None,
+                })
+                .filter(|event| {
+                    instructions
+                        .iter()
+                        .any(|(_, accounts)| accounts.pool_state == event.pool_state)
+                })
+                .collect();
+            assert_eq!(instructions.len(), events.len(), "{}", path.display());
+
+            for ((args, accounts), event) in instructions.iter().zip(&events) {
+                let quote = simulator.quote_instruction(args, accounts).unwrap();
+                assert!(
+                    quote.matches_event(event),
+                    "{}: quoted {quote:?}, the program emitted {event:?}",
+                    path.display()
+                );
+                simulator.apply(&quote);
+                swaps += 1;
+            }
+        }
+        assert!(swaps > 0, "the captures hold no swaps");
+    }
+}
@@ -0,0 +1,471 @@
+//! Zero-copy views of the Raydium CLMM program accounts.
+//!
+//! The layouts were lifted from the Raydium program source code, which can be found
+//! [here](https://github.com/raydium-io/raydium-clmm/blob/master/programs/amm/src/states).
+//! `PoolState`, `TickArrayState` and `ObservationState` are `#[repr(C, packed)]` zero-copy
+//! accounts there. `AmmConfig` and `PersonalPositionState` are borsh accounts, but only hold
+//! fixed-size fields, so their borsh encoding is the packed layout as well.
+//!
+//! Every account starts with the 8-byte Anchor discriminator `sha256("account:<name>")[..8]`,
+//! which [`RaydiumAccount::load`] checks before casting the rest of the data.
+use std::mem::size_of;
+
+use anchor_lang::prelude::Pubkey;
+use bytemuck::{Pod, Zeroable};
+
+use super::anchor_events::{SwapEvent, RAYDIUM_CLMM_PROGRAM};
+use crate::chainstream::types::account;
+
+// Number of rewards Token
+pub const REWARD_NUM: usize = 3;
+/// Number of ticks in a [`TickArrayState`].
+pub const TICK_ARRAY_SIZE: usize = 60;
+/// Number of observations in an [`ObservationState`].
+pub const OBSERVATION_NUM: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum AccountDecodeError {
+    #[error("{account} account is {actual} bytes, expected at least {expected}")]
+    TooShort {
+        account: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    #[error("Account is not a {account}, discriminator {actual:?}")]
+    InvalidDiscriminator {
+        account: &'static str,
+        actual: [u8; 8],
+    },
+    #[error("Account is owned by {0}, not the Raydium CLMM program")]
+    InvalidOwner(String),
+}
+
+/// An account of the Raydium CLMM program.
+pub trait RaydiumAccount: Pod {
+    const NAME: &'static str;
+    const DISCRIMINATOR: [u8; 8];
+    /// Size of the account data, discriminator included.
+    const LEN: usize = 8 + size_of::<Self>();
+
+    /// Casts account data, discriminator included. Trailing bytes are ignored, like Anchor does.
+    fn load(data: &[u8]) -> Result<&Self, AccountDecodeError> {
+        if data.len() < Self::LEN {
+            return Err(AccountDecodeError::TooShort {
+                account: Self::NAME,
+                expected: Self::LEN,
+                actual: data.len(),
+            });
+        }
+        if data[..8] != Self::DISCRIMINATOR {
+            return Err(AccountDecodeError::InvalidDiscriminator {
+                account: Self::NAME,
+                actual: data[..8].try_into().unwrap(),
+            });
+        }
+        // Packed layouts have an alignment of 1, so the cast cannot fail.
+        Ok(bytemuck::from_bytes(&data[8..Self::LEN]))
+    }
+
+    /// Casts the data of an account update, after checking the Raydium program owns it.
+    fn load_update(value: &account::Value) -> Result<&Self, AccountDecodeError> {
+        if value.owner != RAYDIUM_CLMM_PROGRAM.to_string() {
+            return Err(AccountDecodeError::InvalidOwner(value.owner.clone()));
+        }
+        Self::load(&value.data)
+    }
+
+    /// Account data for `self`, discriminator included.
+    fn to_account_data(&self) -> Vec<u8> {
+        [&Self::DISCRIMINATOR[..], bytemuck::bytes_of(self)].concat()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct RewardInfo {
+    /// Reward state
+    pub reward_state: u8,
+    /// Reward open time
+    pub open_time: u64,
+    /// Reward end time
+    pub end_time: u64,
+    /// Reward last update time
+    pub last_update_time: u64,
+    /// Q64.64 number indicates how many tokens per second are earned per unit of liquidity.
+    pub emissions_per_second_x64: u128,
+    /// The total amount of reward emissioned
+    pub reward_total_emissioned: u64,
+    /// The total amount of claimed reward
+    pub reward_claimed: u64,
+    /// Reward token mint.
+    pub token_mint: Pubkey,
+    /// Reward vault token account.
+    pub token_vault: Pubkey,
+    /// The owner that has permission to set reward param
+    pub authority: Pubkey,
+    /// Q64.64 number that tracks the total tokens earned per unit of liquidity since the reward
+    /// emissions were turned on.
+    pub reward_growth_global_x64: u128,
+}
+
+/// The state of a pool, one account per pool.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct PoolState {
+    /// Bump to identify PDA
+    pub bump: [u8; 1],
+    pub amm_config: Pubkey,
+    pub owner: Pubkey,
+    /// Token pair of the pool, where token_mint_0 address < token_mint_1 address
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    /// Token pair vault
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    /// observation account key
+    pub observation_key: Pubkey,
+    /// mint0 and mint1 decimals
+    pub mint_decimals_0: u8,
+    pub mint_decimals_1: u8,
+    /// The minimum number of ticks between initialized ticks
+    pub tick_spacing: u16,
+    /// The currently in range liquidity available to the pool.
+    pub liquidity: u128,
+    /// The current price of the pool as a sqrt(token_1/token_0) Q64.64 value
+    pub sqrt_price_x64: u128,
+    /// The current tick of the pool, i.e. according to the last tick transition that was run.
+    pub tick_current: i32,
+    pub padding3: u16,
+    pub padding4: u16,
+    /// The fee growth as a Q64.64 number, i.e. fees of token_0 and token_1 collected per
+    /// unit of liquidity for the entire life of the pool.
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    /// The amounts of token_0 and token_1 that are owed to the protocol.
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    /// The amounts in and out of swap token_0 and token_1
+    pub swap_in_amount_token_0: u128,
+    pub swap_out_amount_token_1: u128,
+    pub swap_in_amount_token_1: u128,
+    pub swap_out_amount_token_0: u128,
+    /// Bitwise representation of the state of the pool
+    /// bit0, 1: disable open position and increase liquidity, 0: normal
+    /// bit1, 1: disable decrease liquidity, 0: normal
+    /// bit2, 1: disable collect fee, 0: normal
+    /// bit3, 1: disable collect reward, 0: normal
+    /// bit4, 1: disable swap, 0: normal
+    pub status: u8,
+    pub padding: [u8; 7],
+    pub reward_infos: [RewardInfo; REWARD_NUM],
+    /// Packed initialized tick array state
+    pub tick_array_bitmap: [u64; 16],
+    /// except protocol_fee and fund_fee
+    pub total_fees_token_0: u64,
+    /// except protocol_fee and fund_fee
+    pub total_fees_claimed_token_0: u64,
+    pub total_fees_token_1: u64,
+    pub total_fees_claimed_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    /// The timestamp allowed for swap in the pool.
+    pub open_time: u64,
+    /// account recent update epoch
+    pub recent_epoch: u64,
+    pub padding1: [u64; 24],
+    pub padding2: [u64; 32],
+}
+
+impl RaydiumAccount for PoolState {
+    const NAME: &'static str = "PoolState";
+    const DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+}
+
+impl PoolState {
+    /// Whether the price, liquidity and tick reported by `event` are the current state of the
+    /// pool, i.e. no other swap or liquidity change on the pool followed it.
+    pub fn matches_swap(&self, event: &SwapEvent) -> bool {
+        let (sqrt_price_x64, liquidity, tick) =
+            (self.sqrt_price_x64, self.liquidity, self.tick_current);
+        sqrt_price_x64 == event.sqrt_price_x64 && liquidity == event.liquidity && tick == event.tick
+    }
+}
+
+/// Holds the fee rates shared by the pools created with it.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct AmmConfig {
+    /// Bump to identify PDA
+    pub bump: u8,
+    pub index: u16,
+    /// Address of the protocol owner
+    pub owner: Pubkey,
+    /// The protocol fee
+    pub protocol_fee_rate: u32,
+    /// The trade fee, denominated in hundredths of a bip (10^-6)
+    pub trade_fee_rate: u32,
+    /// The tick spacing
+    pub tick_spacing: u16,
+    /// The fund fee, denominated in hundredths of a bip (10^-6)
+    pub fund_fee_rate: u32,
+    pub padding_u32: u32,
+    pub fund_owner: Pubkey,
+    pub padding: [u64; 3],
+}
+
+impl RaydiumAccount for AmmConfig {
+    const NAME: &'static str = "AmmConfig";
+    const DISCRIMINATOR: [u8; 8] = [218, 244, 33, 104, 203, 203, 43, 111];
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct PositionRewardInfo {
+    /// Q64.64
+    pub growth_inside_last_x64: u128,
+    pub reward_amount_owed: u64,
+}
+
+/// A liquidity position, owned by whoever holds its NFT.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct PersonalPositionState {
+    /// Bump to identify PDA
+    pub bump: [u8; 1],
+    /// Mint address of the tokenized position
+    pub nft_mint: Pubkey,
+    /// The ID of the pool with which this token is connected
+    pub pool_id: Pubkey,
+    /// The lower bound tick of the position
+    pub tick_lower_index: i32,
+    /// The upper bound tick of the position
+    pub tick_upper_index: i32,
+    /// The amount of liquidity owned by this position
+    pub liquidity: u128,
+    /// The token_0 fee growth of the aggregate position as of the last action on the individual
+    /// position
+    pub fee_growth_inside_0_last_x64: u128,
+    /// The token_1 fee growth of the aggregate position as of the last action on the individual
+    /// position
+    pub fee_growth_inside_1_last_x64: u128,
+    /// The fees owed to the position owner in token_0, as of the last computation
+    pub token_fees_owed_0: u64,
+    /// The fees owed to the position owner in token_1, as of the last computation
+    pub token_fees_owed_1: u64,
+    pub reward_infos: [PositionRewardInfo; REWARD_NUM],
+    /// account update recent epoch
+    pub recent_epoch: u64,
+    pub padding: [u64; 7],
+}
+
+impl RaydiumAccount for PersonalPositionState {
+    const NAME: &'static str = "PersonalPositionState";
+    const DISCRIMINATOR: [u8; 8] = [70, 111, 150, 126, 230, 15, 25, 117];
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct TickState {
+    pub tick: i32,
+    /// Amount of net liquidity added (subtracted) when tick is crossed from left to right (right
+    /// to left)
+    pub liquidity_net: i128,
+    /// The total position liquidity that references this tick
+    pub liquidity_gross: u128,
+    /// Fee growth per unit of liquidity on the _other_ side of this tick (relative to the current
+    /// tick), only has relative meaning, not absolute — the value depends on when the tick is
+    /// initialized
+    pub fee_growth_outside_0_x64: u128,
+    pub fee_growth_outside_1_x64: u128,
+    /// Reward growth per unit of liquidity like fee, array of Q64.64
+    pub reward_growths_outside_x64: [u128; REWARD_NUM],
+    pub padding: [u32; 13],
+}
+
+impl TickState {
+    pub fn is_initialized(&self) -> bool {
+        let liquidity_gross = self.liquidity_gross;
+        liquidity_gross != 0
+    }
+}
+
+/// [`TICK_ARRAY_SIZE`] consecutive initializable ticks of a pool.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct TickArrayState {
+    pub pool_id: Pubkey,
+    pub start_tick_index: i32,
+    pub ticks: [TickState; TICK_ARRAY_SIZE],
+    pub initialized_tick_count: u8,
+    /// account update recent epoch
+    pub recent_epoch: u64,
+    pub padding: [u8; 107],
+}
+
+impl RaydiumAccount for TickArrayState {
+    const NAME: &'static str = "TickArrayState";
+    const DISCRIMINATOR: [u8; 8] = [192, 155, 85, 205, 49, 249, 129, 42];
+}
+
+impl TickArrayState {
+    /// `start_tick_index` of the array holding `tick`.
+    pub fn start_index(tick: i32, tick_spacing: u16) -> i32 {
+        let ticks_in_array = TICK_ARRAY_SIZE as i32 * i32::from(tick_spacing);
+        tick.div_euclid(ticks_in_array) * ticks_in_array
+    }
+
+    /// The state of `tick`, or `None` if it is outside of the array or not a multiple of
+    /// `tick_spacing`.
+    pub fn tick(&self, tick: i32, tick_spacing: u16) -> Option<TickState> {
+        let offset = tick.checked_sub(self.start_tick_index)?;
+        let tick_spacing = i32::from(tick_spacing);
+        if tick_spacing == 0 || offset < 0 || offset % tick_spacing != 0 {
+            return None;
+        }
+        // `TickState` is packed too, so the ticks can be borrowed in place.
+        self.ticks[..]
+            .get((offset / tick_spacing) as usize)
+            .copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct Observation {
+    /// The block timestamp of the observation
+    pub block_timestamp: u32,
+    /// the cumulative of tick during the duration time
+    pub tick_cumulative: i64,
+    /// padding for feature update
+    pub padding: [u64; 4],
+}
+
+/// A ring buffer of price observations of a pool.
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+#[repr(C, packed)]
+pub struct ObservationState {
+    /// Whether the ObservationState is initialized, a `bool` in the program
+    pub initialized: u8,
+    /// recent update epoch
+    pub recent_epoch: u64,
+    /// the most-recently updated index of the observations array
+    pub observation_index: u16,
+    /// belongs to which pool
+    pub pool_id: Pubkey,
+    /// observation array
+    pub observations: [Observation; OBSERVATION_NUM],
+    /// padding for feature update
+    pub padding: [u64; 4],
+}
+
+impl RaydiumAccount for ObservationState {
+    const NAME: &'static str = "ObservationState";
+    const DISCRIMINATOR: [u8; 8] = [122, 174, 197, 53, 129, 9, 165, 132];
+}
+
+// Account sizes of the Raydium program, discriminator included.
+const _: () = assert!(PoolState::LEN == 1544);
+const _: () = assert!(AmmConfig::LEN == 117);
+const _: () = assert!(PersonalPositionState::LEN == 281);
+const _: () = assert!(TickArrayState::LEN == 10240);
+const _: () = assert!(ObservationState::LEN == 4483);
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use anchor_lang::solana_program::hash::hash;
+
+    use super::*;
+    use crate::chainstream::mock::fixtures;
+
+    #[test]
+    fn test_layouts() {
+        for (name, discriminator) in [
+            (PoolState::NAME, PoolState::DISCRIMINATOR),
+            (AmmConfig::NAME, AmmConfig::DISCRIMINATOR),
+            (
+                PersonalPositionState::NAME,
+                PersonalPositionState::DISCRIMINATOR,
+            ),
+            (TickArrayState::NAME, TickArrayState::DISCRIMINATOR),
+            (ObservationState::NAME, ObservationState::DISCRIMINATOR),
+        ] {
+            let hash = hash(format!("account:{name}").as_bytes());
+            assert_eq!(hash.to_bytes()[..8], discriminator, "{name}");
+        }
+
+        // Offsets in the account data, as used by `getProgramAccounts` memcmp filters.
+        assert_eq!(8 + offset_of!(PoolState, token_mint_0), 73);
+        assert_eq!(8 + offset_of!(PoolState, liquidity), 237);
+        assert_eq!(8 + offset_of!(PoolState, sqrt_price_x64), 253);
+        assert_eq!(8 + offset_of!(PoolState, tick_current), 269);
+        assert_eq!(8 + offset_of!(PoolState, tick_array_bitmap), 904);
+        assert_eq!(8 + offset_of!(AmmConfig, trade_fee_rate), 47);
+        assert_eq!(8 + offset_of!(PersonalPositionState, pool_id), 41);
+        assert_eq!(size_of::<TickState>(), 168);
+        assert_eq!(size_of::<Observation>(), 44);
+    }
+
+    #[test]
+    fn test_load_pool_state() {
+        let mut pool = PoolState::zeroed();
+        pool.sqrt_price_x64 = 1 << 64;
+        pool.liquidity = 5_000;
+        pool.tick_current = -3;
+        pool.tick_spacing = 60;
+        let data = pool.to_account_data();
+        assert_eq!(data.len(), 1544);
+        assert_eq!(data[253..269], (1u128 << 64).to_le_bytes());
+
+        let update = fixtures::account_update(
+            3,
+            &Pubkey::new_unique().to_string(),
+            &RAYDIUM_CLMM_PROGRAM.to_string(),
+            data.clone(),
+        );
+        let loaded = PoolState::load_update(&update.value).unwrap();
+        assert_eq!({ loaded.liquidity }, 5_000);
+        assert_eq!({ loaded.tick_current }, -3);
+
+        assert_eq!(
+            PoolState::load(&data[..1000]).unwrap_err(),
+            AccountDecodeError::TooShort {
+                account: "PoolState",
+                expected: 1544,
+                actual: 1000
+            }
+        );
+        assert!(matches!(
+            AmmConfig::load(&data),
+            Err(AccountDecodeError::InvalidDiscriminator {
+                account: "AmmConfig",
+                ..
+            })
+        ));
+        let mut foreign = update.value;
+        foreign.owner = Pubkey::new_unique().to_string();
+        assert!(matches!(
+            PoolState::load_update(&foreign),
+            Err(AccountDecodeError::InvalidOwner(_))
+        ));
+    }
+
+    #[test]
+    fn test_tick_array() {
+        let mut array = TickArrayState::zeroed();
+        array.start_tick_index = -600;
+        array.ticks[2].tick = -480;
+        array.ticks[2].liquidity_gross = 7;
+        let data = array.to_account_data();
+        let loaded = TickArrayState::load(&data).unwrap();
+
+        let tick = loaded.tick(-480, 60).unwrap();
+        assert!(tick.is_initialized());
+        assert_eq!({ tick.tick }, -480);
+        assert!(!loaded.tick(-600, 60).unwrap().is_initialized());
+        assert!(loaded.tick(-470, 60).is_none());
+        assert!(loaded.tick(-660, 60).is_none());
+        assert!(loaded.tick(-600 + 60 * 60, 60).is_none());
+    }
+}